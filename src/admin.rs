@@ -0,0 +1,267 @@
+use axum::{
+    extract::Path,
+    http::Request,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use futures::StreamExt;
+use glob::Pattern;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::env;
+use std::sync::Arc;
+
+use crate::dist::Dist;
+use crate::downloads;
+use crate::git_builder;
+use crate::hosted::is_valid_name;
+use crate::last_error::LastErrors;
+use crate::lookup_error::NegativeCache;
+use crate::metadata_cache::MetadataCache;
+use crate::mirrors::availability::DistAvailability;
+use crate::mirrors::packagist::Packagist;
+use crate::package::Package;
+use crate::secret::constant_time_eq;
+use crate::{Config, PackagistStrategy};
+
+// 列出扩展时同时查询自有存储的请求数
+const LIST_CONCURRENCY: usize = 8;
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+// 所有管理接口都需要 Authorization: Bearer $ADMIN_TOKEN，未设置 ADMIN_TOKEN 时不开启
+async fn authorize<B>(request: Request<B>, next: Next<B>) -> Response {
+    let admin_token = match env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return error(StatusCode::NOT_FOUND, "admin api is not enabled"),
+    };
+    let token = request
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(&admin_token, token) {
+        return error(StatusCode::UNAUTHORIZED, "invalid admin token");
+    }
+
+    next.run(request).await
+}
+
+fn invalid_name() -> Response {
+    error(StatusCode::BAD_REQUEST, "invalid package name")
+}
+
+// 自有存储中已上传的 dist，按 vendor/package/ 前缀列举
+async fn stored_dists(config: &Config, package: &str) -> Value {
+    let storage = match &config.storage {
+        Some(storage) => storage,
+        None => return json!([]),
+    };
+    match storage.list(&format!("{}/", package)).await {
+        Ok(objects) => json!(objects
+            .iter()
+            .map(|object| json!({ "object_name": object, "url": storage.url(object) }))
+            .collect::<Vec<Value>>()),
+        Err(err) => json!({ "error": err }),
+    }
+}
+
+// 与 /p2 相同，匹配的上游仓库优先于 packagist
+fn metadata_cache(config: &Config, package: &str) -> Arc<MetadataCache> {
+    match config
+        .repositories
+        .iter()
+        .find(|repository| repository.matches(package.trim_end_matches("~dev")))
+    {
+        Some(repository) => repository.metadata_cache(),
+        None => MetadataCache::packagist().clone(),
+    }
+}
+
+async fn package_detail(config: &Config, package: &str) -> Value {
+    let pattern = Pattern::new(&Pattern::escape(package)).unwrap();
+    json!({
+        "package": package,
+        "metadata": metadata_cache(config, package).info(package).await,
+        "dists": DistAvailability::global().entries(&pattern),
+        "stored_dists": stored_dists(config, package).await,
+        "last_error": LastErrors::global().get(package),
+    })
+}
+
+async fn list_packages(config: Extension<Config>) -> Json<Value> {
+    let mut cached = BTreeSet::new();
+    for cache in MetadataCache::sources().await {
+        cached.extend(cache.cached_packages().await);
+    }
+    let packages = futures::stream::iter(cached)
+        .map(|package| {
+            let config = config.clone();
+            async move { package_detail(&config, &package).await }
+        })
+        .buffered(LIST_CONCURRENCY)
+        .collect::<Vec<Value>>()
+        .await;
+    Json(json!({ "packages": packages }))
+}
+
+async fn show_package(
+    Path((vendor, package)): Path<(String, String)>,
+    config: Extension<Config>,
+) -> Response {
+    let package = format!("{}/{}", vendor, package);
+    if !is_valid_name(&package) {
+        return invalid_name();
+    }
+    Json(package_detail(&config, &package).await).into_response()
+}
+
+// 模式同时匹配扩展的 ~dev 元数据
+async fn purge(pattern: &str) -> Response {
+    let (pattern, dev_pattern) = match (
+        Pattern::new(pattern),
+        Pattern::new(&format!("{}~dev", pattern)),
+    ) {
+        (Ok(pattern), Ok(dev_pattern)) => (pattern, dev_pattern),
+        (Err(err), _) | (_, Err(err)) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+
+    let mut metadata = Vec::new();
+    for cache in MetadataCache::sources().await {
+        for package in cache.cached_packages().await {
+            if pattern.matches(package.trim_end_matches("~dev")) && cache.invalidate(&package).await
+            {
+                LastErrors::global().remove(&package);
+                metadata.push(package);
+            }
+        }
+    }
+    metadata.sort();
+    metadata.dedup();
+    let dists = DistAvailability::global().purge(&pattern);
+    let not_found =
+        NegativeCache::global().purge(&pattern) + NegativeCache::global().purge(&dev_pattern);
+
+    Json(json!({ "metadata": metadata, "dists": dists, "not_found": not_found })).into_response()
+}
+
+async fn purge_package(Path((vendor, package)): Path<(String, String)>) -> Response {
+    let package = format!("{}/{}", vendor, package);
+    if !is_valid_name(&package) {
+        return invalid_name();
+    }
+    purge(&Pattern::escape(&package)).await
+}
+
+async fn purge_pattern(Json(body): Json<Value>) -> Response {
+    match body["pattern"].as_str() {
+        Some(pattern) => purge(pattern).await,
+        None => error(StatusCode::BAD_REQUEST, "pattern is required"),
+    }
+}
+
+async fn refresh_package(
+    Path((vendor, package)): Path<(String, String)>,
+    config: Extension<Config>,
+) -> Response {
+    let package = format!("{}/{}", vendor, package);
+    if !is_valid_name(&package) {
+        return invalid_name();
+    }
+    let escaped = Pattern::escape(&package);
+    NegativeCache::global().purge(&Pattern::new(&escaped).unwrap());
+    NegativeCache::global().purge(&Pattern::new(&format!("{}~dev", escaped)).unwrap());
+    // 托管扩展的元数据由本服务生成，不需要刷新
+    if config.hosted.metadata(&package).await.is_some() {
+        return Json(package_detail(&config, &package).await).into_response();
+    }
+
+    // 与 /p2 相同，匹配的上游仓库优先于 packagist；~dev 元数据已缓存时一起刷新
+    let dev_package = format!("{}~dev", package);
+    let dev_cached = metadata_cache(&config, &package)
+        .get(&dev_package)
+        .await
+        .is_some();
+    let repository = config
+        .repositories
+        .iter()
+        .find(|repository| repository.matches(&package));
+    let mut result = Ok(());
+    for name in [Some(&package), dev_cached.then_some(&dev_package)]
+        .into_iter()
+        .flatten()
+    {
+        let refreshed = match repository {
+            Some(repository) => repository.refresh(name).await,
+            None => {
+                let url = Packagist::new(&config).get_package_meta_url(name);
+                MetadataCache::global().refresh(name, &url).await
+            }
+        };
+        result = result.and(refreshed.map(|_| ()));
+    }
+    match result {
+        Ok(_) => Json(package_detail(&config, &package).await).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn reupload_dist(
+    Path((vendor, package, version, reference_and_type)): Path<(String, String, String, String)>,
+    config: Extension<Config>,
+) -> Response {
+    if config.strategy != PackagistStrategy::StorageSelf {
+        return error(StatusCode::BAD_REQUEST, "self storage strategy is not enabled");
+    }
+    let (reference, dist_type) = match reference_and_type.split_once('.') {
+        Some(parts) => parts,
+        None => return error(StatusCode::BAD_REQUEST, "expected <reference>.<type>"),
+    };
+    if !is_valid_name(&format!("{}/{}", vendor, package)) {
+        return invalid_name();
+    }
+    let package = Package::new(&vendor, &package);
+    let dist = Dist::new(&package, &version, reference, dist_type);
+
+    match Packagist::new(&config).reupload_dist(&dist).await {
+        Ok(object_name) => Json(json!({ "object_name": object_name })).into_response(),
+        Err(message) => error(StatusCode::BAD_GATEWAY, message),
+    }
+}
+
+// 在后台构建全部 git 仓库
+async fn build_repositories(config: Extension<Config>) -> Response {
+    let config = config.0;
+    let repositories = config.git_repositories.clone();
+    tokio::spawn(async move { git_builder::build_all(&config).await });
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "repositories": repositories })),
+    )
+        .into_response()
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/packages", get(list_packages))
+        .route(
+            "/packages/:vendor/:package",
+            get(show_package).delete(purge_package),
+        )
+        .route("/packages/:vendor/:package/refresh", post(refresh_package))
+        .route(
+            "/packages/:vendor/:package/dists/:version/:reference_and_type",
+            post(reupload_dist),
+        )
+        .route("/purge", post(purge_pattern))
+        .route("/repositories/build", post(build_repositories))
+        .route("/downloads", get(downloads::summary))
+        .route("/downloads/:vendor/:package", get(downloads::package))
+        .route_layer(middleware::from_fn(authorize))
+}
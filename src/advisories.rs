@@ -0,0 +1,539 @@
+use axum::{
+    body::Bytes,
+    extract::RawQuery,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::constraint::{version_key, Constraint};
+use crate::metadata::retain_versions;
+use crate::request_helper;
+use crate::{check_package_in_white_list, Config};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdvisorySource {
+    pub name: String,
+    pub remote_id: String,
+}
+
+// 与 packagist 的 security-advisories 接口返回的字段相同
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Advisory {
+    pub advisory_id: String,
+    pub package_name: String,
+    pub remote_id: String,
+    pub title: String,
+    pub link: String,
+    pub cve: Option<String>,
+    // 如 >=2.0.0,<2.5.1|>=3.0.0,<3.0.2，| 分隔不同分支
+    pub affected_versions: String,
+    pub source: String,
+    pub reported_at: String,
+    pub composer_repository: Option<String>,
+    pub severity: Option<String>,
+    pub sources: Vec<AdvisorySource>,
+}
+
+const SOURCE_NAME: &str = "FriendsOfPHP/security-advisories";
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+        .unwrap_or(value)
+        .to_string()
+}
+
+// FriendsOfPHP/security-advisories 的 YAML 结构固定，只解析用到的字段：
+// title、link、cve、reference、composer-repository 以及 branches 下的 time 和 versions
+fn parse_yaml(content: &str, remote_id: &str) -> Option<Advisory> {
+    let mut fields = HashMap::new();
+    let mut branches = Vec::<(String, Vec<String>)>::new();
+    let mut in_branches = false;
+    let mut in_versions = false;
+    for line in content.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        let line = line.trim();
+        if indent == 0 {
+            in_branches = false;
+            in_versions = false;
+            let (key, value) = line.split_once(':')?;
+            if key == "branches" {
+                in_branches = true;
+            } else {
+                fields.insert(key.to_string(), unquote(value));
+            }
+            continue;
+        }
+        if !in_branches {
+            continue;
+        }
+        if let Some(item) = line.strip_prefix("- ") {
+            if in_versions {
+                if let Some((_, versions)) = branches.last_mut() {
+                    versions.push(unquote(item));
+                }
+            }
+            continue;
+        }
+        in_versions = false;
+        match line.split_once(':') {
+            Some(("time", value)) => {
+                if let Some((time, _)) = branches.last_mut() {
+                    *time = unquote(value);
+                }
+            }
+            Some(("versions", value)) => {
+                let value = value.trim();
+                match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                    Some(list) => {
+                        if let Some((_, versions)) = branches.last_mut() {
+                            versions.extend(list.split(',').map(unquote).filter(|v| !v.is_empty()));
+                        }
+                    }
+                    None => in_versions = value.is_empty(),
+                }
+            }
+            Some((_, "")) => branches.push((String::new(), Vec::new())),
+            _ => {}
+        }
+    }
+
+    let package_name = fields
+        .get("reference")?
+        .strip_prefix("composer://")?
+        .to_lowercase();
+    let affected_versions = branches
+        .iter()
+        .filter(|(_, versions)| !versions.is_empty())
+        .map(|(_, versions)| versions.join(","))
+        .collect::<Vec<String>>()
+        .join("|");
+    if affected_versions.is_empty() {
+        return None;
+    }
+    let reported_at = branches
+        .iter()
+        .map(|(time, _)| time.clone())
+        .filter(|time| !time.is_empty())
+        .min()
+        .unwrap_or_default();
+    let cve = fields
+        .get("cve")
+        .filter(|cve| !cve.is_empty() && *cve != "~");
+    let composer_repository = match fields.get("composer-repository").map(|s| s.as_str()) {
+        Some("false") => None,
+        Some(repository) if !repository.is_empty() => Some(repository.to_string()),
+        _ => Some("https://packagist.org".to_string()),
+    };
+
+    Some(Advisory {
+        advisory_id: format!(
+            "LOCAL-{}",
+            &hex::encode(Sha1::digest(remote_id.as_bytes()))[..12]
+        ),
+        package_name,
+        remote_id: remote_id.to_string(),
+        title: fields.get("title").cloned().unwrap_or_default(),
+        link: fields.get("link").cloned().unwrap_or_default(),
+        cve: cve.cloned(),
+        affected_versions,
+        source: SOURCE_NAME.to_string(),
+        reported_at,
+        composer_repository,
+        severity: None,
+        sources: vec![AdvisorySource {
+            name: SOURCE_NAME.to_string(),
+            remote_id: remote_id.to_string(),
+        }],
+    })
+}
+
+// 版本落在 affected_versions 的任一分支内即受影响
+pub fn is_affected(affected_versions: &str, version_normalized: &str) -> bool {
+    match (
+        version_key(version_normalized),
+        Constraint::parse(affected_versions),
+    ) {
+        (Some(version), Some(constraint)) => constraint.matches(&version),
+        _ => false,
+    }
+}
+
+// 本地漏洞库保存在 SECURITY_ADVISORIES_PATH，可以从 FriendsOfPHP/security-advisories 的检出目录导入，
+// 设置 SECURITY_ADVISORIES_UPSTREAM 后同时查询上游并合并结果
+pub struct Advisories {
+    path: PathBuf,
+    upstream: Option<String>,
+    by_package: Mutex<HashMap<String, Vec<Advisory>>>,
+}
+
+fn group(advisories: Vec<Advisory>) -> HashMap<String, Vec<Advisory>> {
+    let mut by_package = HashMap::<String, Vec<Advisory>>::new();
+    for advisory in advisories {
+        by_package
+            .entry(advisory.package_name.clone())
+            .or_default()
+            .push(advisory);
+    }
+    by_package
+}
+
+// 检出目录下按 vendor/package/*.yaml 存放，remoteId 为相对路径
+fn read_dir(dir: &Path) -> Result<Vec<Advisory>, String> {
+    let mut advisories = Vec::new();
+    let vendors = fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+    for vendor in vendors.flatten() {
+        let vendor_name = vendor.file_name().to_string_lossy().to_string();
+        if vendor_name.starts_with('.') || !vendor.path().is_dir() {
+            continue;
+        }
+        for package in fs::read_dir(vendor.path()).into_iter().flatten().flatten() {
+            let package_name = package.file_name().to_string_lossy().to_string();
+            for file in fs::read_dir(package.path()).into_iter().flatten().flatten() {
+                let file_name = file.file_name().to_string_lossy().to_string();
+                if !file_name.ends_with(".yaml") && !file_name.ends_with(".yml") {
+                    continue;
+                }
+                let remote_id = format!("{}/{}/{}", vendor_name, package_name, file_name);
+                match fs::read_to_string(file.path())
+                    .ok()
+                    .and_then(|content| parse_yaml(&content, &remote_id))
+                {
+                    Some(advisory) => advisories.push(advisory),
+                    None => eprintln!("skipped invalid advisory {}", remote_id),
+                }
+            }
+        }
+    }
+    advisories.sort_by(|a, b| a.remote_id.cmp(&b.remote_id));
+    Ok(advisories)
+}
+
+impl Advisories {
+    // 本地漏洞库不存在时为空，文件损坏时返回错误，避免静默地不再移除受影响的版本
+    pub fn new(path: impl Into<PathBuf>, upstream: Option<String>) -> Result<Self, String> {
+        let path = path.into();
+        let advisories = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<Vec<Advisory>>(&content)
+                .map_err(|err| format!("failed to parse {}: {}", path.display(), err))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(format!("failed to read {}: {}", path.display(), err)),
+        };
+        Ok(Self {
+            path,
+            upstream,
+            by_package: Mutex::new(group(advisories)),
+        })
+    }
+
+    // 未设置 SECURITY_ADVISORIES_PATH 和 SECURITY_ADVISORIES_UPSTREAM 时不开启
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let path = env::var("SECURITY_ADVISORIES_PATH")
+            .ok()
+            .filter(|path| !path.is_empty());
+        let upstream = env::var("SECURITY_ADVISORIES_UPSTREAM")
+            .ok()
+            .filter(|url| !url.is_empty());
+        if path.is_none() && upstream.is_none() {
+            return Ok(None);
+        }
+        Self::new(
+            path.unwrap_or_else(|| "./advisories.json".to_string()),
+            upstream,
+        )
+        .map(Some)
+    }
+
+    // 本地漏洞库中没有任何漏洞
+    pub fn is_empty(&self) -> bool {
+        self.by_package.lock().unwrap().is_empty()
+    }
+
+    // 导入检出目录中的全部漏洞，替换原有的本地漏洞库
+    pub fn import(&self, dir: &Path) -> Result<usize, String> {
+        let advisories = read_dir(dir)?;
+        let content = serde_json::to_string(&advisories).map_err(|err| err.to_string())?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| format!("failed to write {}: {}", self.path.display(), err))?;
+        let count = advisories.len();
+        *self.by_package.lock().unwrap() = group(advisories);
+        Ok(count)
+    }
+
+    pub fn local(&self, package: &str) -> Vec<Advisory> {
+        self.by_package
+            .lock()
+            .unwrap()
+            .get(package)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn fetch_upstream(
+        &self,
+        packages: &[String],
+        updated_since: Option<&str>,
+    ) -> Result<HashMap<String, Vec<Advisory>>, String> {
+        let url = match &self.upstream {
+            Some(url) => url,
+            None => return Ok(HashMap::new()),
+        };
+        let mut form = packages
+            .iter()
+            .map(|package| ("packages[]", package.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        if let Some(updated_since) = updated_since {
+            form.push(("updatedSince", updated_since));
+        }
+        let response = request_helper::post_form(url, &form)
+            .await
+            .map_err(|err| format!("{}: {}", url, err))?;
+        if !response.status().is_success() {
+            return Err(format!("{} responded {}", url, response.status()));
+        }
+
+        #[derive(Deserialize)]
+        struct UpstreamResponse {
+            advisories: HashMap<String, Vec<Advisory>>,
+        }
+        response
+            .json::<UpstreamResponse>()
+            .await
+            .map(|response| response.advisories)
+            .map_err(|err| format!("{}: {}", url, err))
+    }
+
+    // 本地漏洞库与上游的结果合并，同一漏洞（相同 CVE 或来源）只保留上游的记录
+    pub async fn query(
+        &self,
+        config: &Config,
+        packages: &[String],
+        updated_since: Option<&str>,
+    ) -> Map<String, Value> {
+        // 私有扩展不发送到上游
+        let public = packages
+            .iter()
+            .filter(|package| !check_package_in_white_list(package, &config.private_packages))
+            .cloned()
+            .collect::<Vec<String>>();
+        let mut upstream = match public.is_empty() {
+            true => HashMap::new(),
+            false => self
+                .fetch_upstream(&public, updated_since)
+                .await
+                .unwrap_or_else(|err| {
+                    eprintln!("failed to query security advisories: {}", err);
+                    HashMap::new()
+                }),
+        };
+
+        let mut result = Map::new();
+        for package in packages {
+            let mut advisories = upstream.remove(package).unwrap_or_default();
+            for advisory in self.local(package) {
+                let duplicated = advisories.iter().any(|existing| {
+                    (advisory.cve.is_some() && existing.cve == advisory.cve)
+                        || existing
+                            .sources
+                            .iter()
+                            .any(|source| source.remote_id == advisory.remote_id)
+                });
+                if !duplicated {
+                    advisories.push(advisory);
+                }
+            }
+            if !advisories.is_empty() {
+                result.insert(package.clone(), json!(advisories));
+            }
+        }
+        result
+    }
+
+    // 返回被移除的版本号
+    fn remove_affected(&self, package: &str, body: &str) -> Option<(String, Vec<String>)> {
+        let advisories = self.local(package);
+        if advisories.is_empty() {
+            return None;
+        }
+        retain_versions(package, body, |version| {
+            let normalized = version["version_normalized"].as_str().unwrap_or_default();
+            !advisories
+                .iter()
+                .any(|advisory| is_affected(&advisory.affected_versions, normalized))
+        })
+    }
+}
+
+// 需要移除受影响版本的扩展，元数据需要由本服务返回，不能跳转到第三方镜像
+pub fn blocks(config: &Config, package: &str) -> bool {
+    config.advisories.is_some()
+        && check_package_in_white_list(package, &config.security_block_packages)
+}
+
+// SECURITY_BLOCK_PACKAGES 中的扩展，从返回的元数据中移除本地漏洞库中受影响的版本
+pub async fn block_vulnerable(config: &Config, package: &str, response: Response) -> Response {
+    let advisories = match &config.advisories {
+        Some(advisories) if blocks(config, package) => advisories,
+        _ => return response,
+    };
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    request_helper::rewrite_body(response, |body| {
+        let (filtered, removed) = advisories.remove_affected(package, body)?;
+        eprintln!(
+            "blocked vulnerable versions of {}: {}",
+            package,
+            removed.join(",")
+        );
+        Some(filtered)
+    })
+    .await
+}
+
+// composer 以 POST 表单或查询参数传入 packages[] 和 updatedSince
+pub async fn api(config: Extension<Config>, RawQuery(query): RawQuery, body: Bytes) -> Response {
+    let advisories = match &config.advisories {
+        Some(advisories) => advisories,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "security advisories are not enabled" })),
+            )
+                .into_response()
+        }
+    };
+    let form = format!(
+        "{}&{}",
+        query.unwrap_or_default(),
+        String::from_utf8_lossy(&body)
+    );
+    let url = match Url::parse(&format!("http://localhost/?{}", form)) {
+        Ok(url) => url,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": err.to_string() })),
+            )
+                .into_response()
+        }
+    };
+    let mut packages = Vec::new();
+    let mut updated_since = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "packages[]" | "packages" => packages.push(value.to_lowercase()),
+            "updatedSince" => updated_since = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    packages.sort();
+    packages.dedup();
+    let result = advisories
+        .query(&config, &packages, updated_since.as_deref())
+        .await;
+    Json(json!({ "advisories": result })).into_response()
+}
+
+fn usage() {
+    eprintln!("usage: composer_mirror advisories import <security-advisories checkout>");
+}
+
+// 漏洞库管理子命令
+pub fn run(args: &[String]) {
+    let path =
+        env::var("SECURITY_ADVISORIES_PATH").unwrap_or_else(|_| "./advisories.json".to_string());
+    // 导入会替换原有的本地漏洞库，原文件损坏时同样可以导入
+    let advisories = Advisories::new(&path, None).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        Advisories {
+            path: PathBuf::from(&path),
+            upstream: None,
+            by_package: Mutex::new(HashMap::new()),
+        }
+    });
+    match args
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>()
+        .as_slice()
+    {
+        ["import", dir] => match advisories.import(Path::new(dir)) {
+            Ok(count) => eprintln!("imported {} advisories", count),
+            Err(err) => eprintln!("{}", err),
+        },
+        _ => usage(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_yaml_test() {
+        let content = r#"title:     "Cross-site scripting in the profiler"
+link:      https://symfony.com/cve-2021-1234
+cve:       CVE-2021-1234
+branches:
+    4.4.x:
+        time:     2021-05-01 10:00:00
+        versions: ['>=4.4.0', '<4.4.24']
+    5.x:
+        time:     "2021-05-02 10:00:00"
+        versions:
+            - '>=5.0.0'
+            - '<5.2.9'
+reference: composer://Symfony/Web-Profiler-Bundle
+"#;
+        let advisory =
+            parse_yaml(content, "symfony/web-profiler-bundle/CVE-2021-1234.yaml").unwrap();
+        assert_eq!("symfony/web-profiler-bundle", advisory.package_name);
+        assert_eq!("Cross-site scripting in the profiler", advisory.title);
+        assert_eq!(Some("CVE-2021-1234".to_string()), advisory.cve);
+        assert_eq!(">=4.4.0,<4.4.24|>=5.0.0,<5.2.9", advisory.affected_versions);
+        assert_eq!("2021-05-01 10:00:00", advisory.reported_at);
+        assert_eq!(
+            Some("https://packagist.org".to_string()),
+            advisory.composer_repository
+        );
+    }
+
+    #[test]
+    fn is_affected_test() {
+        let affected = ">=4.4.0,<4.4.24|>=5.0.0,<5.2.9";
+        assert!(is_affected(affected, "4.4.0.0"));
+        assert!(is_affected(affected, "4.4.23.0"));
+        assert!(!is_affected(affected, "4.4.24.0"));
+        assert!(is_affected(affected, "5.1.0.0-RC1"));
+        assert!(!is_affected(affected, "5.2.9.0"));
+        assert!(!is_affected(affected, "3.4.0.0"));
+        // < 不带稳定性时不包含该版本的预发布版本
+        assert!(!is_affected("<2.0", "2.0.0.0-beta1"));
+        assert!(is_affected("<=2.0", "2.0.0.0-beta1"));
+        assert!(!is_affected(affected, "dev-main"));
+    }
+}
@@ -0,0 +1,414 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use glob::Pattern;
+use rand::RngCore;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::secret::constant_time_eq;
+use crate::Config;
+
+// 与 composer auth.json 中的 http-basic、bearer 对应
+#[derive(Debug, PartialEq)]
+pub enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, value) = value.split_once(' ')?;
+        if scheme.eq_ignore_ascii_case("bearer") {
+            return Some(Self::Bearer(value.trim().to_string()));
+        }
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = String::from_utf8(STANDARD.decode(value.trim()).ok()?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        Some(Self::Basic {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Token {
+    pub name: String,
+    // 只保存 sha256 摘要，明文只在创建时输出一次
+    pub hash: String,
+    // 可访问的扩展，支持 * 泛型匹配，如 acme/*
+    pub scopes: Vec<String>,
+    pub created_at: String,
+}
+
+impl Token {
+    // 扩展名不区分大小写，手工编辑的令牌文件中可能有大写的范围
+    pub fn allows(&self, package: &str) -> bool {
+        let package = package.to_lowercase();
+        self.scopes.iter().any(|scope| {
+            Pattern::new(&scope.to_lowercase())
+                .map(|pattern| pattern.matches(&package))
+                .unwrap_or(false)
+        })
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+// 令牌保存在本地 JSON 文件中，文件修改后下次校验时自动重新读取，命令行增删令牌无需重启服务
+pub struct TokenStore {
+    path: PathBuf,
+    cached: Mutex<(Option<SystemTime>, Vec<Token>)>,
+}
+
+impl TokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            cached: Mutex::new((None, Vec::new())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(env::var("AUTH_TOKENS_PATH").unwrap_or_else(|_| "./tokens.json".to_string()))
+    }
+
+    pub fn tokens(&self) -> Vec<Token> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let mut cached = self.cached.lock().unwrap();
+        if modified.is_none() || cached.0 != modified {
+            let tokens = fs::read_to_string(&self.path)
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok())
+                .unwrap_or_default();
+            *cached = (modified, tokens);
+        }
+        cached.1.clone()
+    }
+
+    fn save(&self, tokens: &[Token]) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let content = serde_json::to_string_pretty(tokens).map_err(|err| err.to_string())?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| format!("failed to write {}: {}", self.path.display(), err))?;
+
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        *self.cached.lock().unwrap() = (modified, tokens.to_vec());
+        Ok(())
+    }
+
+    // 返回令牌明文，调用方负责展示给用户
+    pub fn add(&self, name: &str, scopes: Vec<String>) -> Result<String, String> {
+        if name.is_empty() || name.contains(':') {
+            return Err("token name must be non-empty and must not contain ':'".to_string());
+        }
+        if let Some(scope) = scopes.iter().find(|scope| Pattern::new(scope).is_err()) {
+            return Err(format!("invalid scope: {}", scope));
+        }
+        let scopes = scopes.iter().map(|scope| scope.to_lowercase()).collect();
+        let mut tokens = self.tokens();
+        if tokens.iter().any(|token| token.name == name) {
+            return Err(format!("token {} already exists", name));
+        }
+
+        let mut bytes = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let secret = hex::encode(bytes);
+        tokens.push(Token {
+            name: name.to_string(),
+            hash: hash_secret(&secret),
+            scopes,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        });
+        self.save(&tokens)?;
+        Ok(secret)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<bool, String> {
+        let mut tokens = self.tokens();
+        let count = tokens.len();
+        tokens.retain(|token| token.name != name);
+        if tokens.len() == count {
+            return Ok(false);
+        }
+        self.save(&tokens)?;
+        Ok(true)
+    }
+
+    // http-basic 的用户名为令牌名、密码为令牌；bearer 直接传令牌
+    pub fn authenticate(&self, credentials: &Credentials) -> Option<Token> {
+        let (name, secret) = match credentials {
+            Credentials::Basic { username, password } => (Some(username.as_str()), password),
+            Credentials::Bearer(secret) => (None, secret),
+        };
+        let hash = hash_secret(secret);
+        self.tokens().into_iter().find(|token| {
+            constant_time_eq(&token.hash, &hash) && name.is_none_or(|name| name == token.name)
+        })
+    }
+}
+
+// 与 Path 提取器一样按 UTF-8 做百分号解码，无效的转义原样保留
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' => bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// /p2/vendor/package.json、/p2/vendor/package~dev.json、/dists/vendor/package/...、/git/vendor/package.git/... 对应的扩展名
+fn requested_package(path: &str) -> Option<String> {
+    if let Some(rest) = path.strip_prefix("/p2/") {
+        let name = rest.strip_suffix(".json")?;
+        let name = name.strip_suffix("~dev").unwrap_or(name);
+        return Some(name.to_string());
+    }
+    if let Some(rest) = path.strip_prefix("/git/") {
+        let mut segments = rest.split('/');
+        let vendor = segments.next()?;
+        let name = segments.next()?.strip_suffix(".git")?;
+        return Some(format!("{}/{}", vendor, name));
+    }
+    let mut segments = path.strip_prefix("/dists/")?.split('/');
+    Some(format!("{}/{}", segments.next()?, segments.next()?))
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+// 私有扩展的元数据和 dist 需要令牌，令牌范围外的扩展按不存在处理
+pub async fn require_token<B>(
+    Extension(config): Extension<Config>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    // 路由处理的是解码后的路径，扩展名不区分大小写，需按同样的方式得到扩展名再检查
+    let path = match percent_decode(request.uri().path()) {
+        Some(path) => path,
+        None => return error(StatusCode::BAD_REQUEST, "invalid path"),
+    };
+    let package = match requested_package(&path).map(|package| package.to_lowercase()) {
+        Some(package) if crate::check_package_in_white_list(&package, &config.private_packages) => {
+            package
+        }
+        _ => return next.run(request).await,
+    };
+
+    let token = Credentials::from_headers(request.headers())
+        .and_then(|credentials| config.tokens.authenticate(&credentials));
+    match token {
+        Some(token) if token.allows(&package) => next.run(request).await,
+        Some(_) => error(StatusCode::NOT_FOUND, "package not found"),
+        None => {
+            let mut response = error(StatusCode::UNAUTHORIZED, "authentication required");
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"composer_mirror\""),
+            );
+            response
+        }
+    }
+}
+
+fn usage() {
+    eprintln!("usage: composer_mirror token add <name> <scope>...");
+    eprintln!("       composer_mirror token list");
+    eprintln!("       composer_mirror token remove <name>");
+}
+
+// 令牌管理子命令
+pub fn run(args: &[String]) {
+    let store = TokenStore::from_env();
+    match args
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>()
+        .as_slice()
+    {
+        ["add", name, scopes @ ..] if !scopes.is_empty() => {
+            let scopes = scopes.iter().map(|s| s.to_string()).collect();
+            match store.add(name, scopes) {
+                Ok(secret) => {
+                    println!("{}", secret);
+                    eprintln!(
+                        "token {} created, the secret above will not be shown again",
+                        name
+                    );
+                }
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        ["list"] => {
+            for token in store.tokens() {
+                println!(
+                    "{}\t{}\t{}",
+                    token.name,
+                    token.scopes.join(","),
+                    token.created_at
+                );
+            }
+        }
+        ["remove", name] => match store.remove(name) {
+            Ok(true) => eprintln!("token {} removed", name),
+            Ok(false) => eprintln!("token {} not found", name),
+            Err(err) => eprintln!("{}", err),
+        },
+        _ => usage(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_test() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer abc"),
+        );
+        assert_eq!(
+            Some(Credentials::Bearer("abc".to_string())),
+            Credentials::from_headers(&headers)
+        );
+
+        // ci:s3cr:et
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic Y2k6czNjcjpldA=="),
+        );
+        assert_eq!(
+            Some(Credentials::Basic {
+                username: "ci".to_string(),
+                password: "s3cr:et".to_string()
+            }),
+            Credentials::from_headers(&headers)
+        );
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Digest abc"),
+        );
+        assert_eq!(None, Credentials::from_headers(&headers));
+    }
+
+    #[test]
+    fn requested_package_test() {
+        assert_eq!(
+            Some("acme/lib".to_string()),
+            requested_package("/p2/acme/lib.json")
+        );
+        assert_eq!(
+            Some("acme/lib".to_string()),
+            requested_package("/p2/acme/lib~dev.json")
+        );
+        assert_eq!(
+            Some("acme/lib".to_string()),
+            requested_package("/dists/acme/lib/v1.0.0/abc.zip")
+        );
+        assert_eq!(
+            Some("acme/lib".to_string()),
+            requested_package("/git/acme/lib.git/info/refs")
+        );
+        assert_eq!(None, requested_package("/packages.json"));
+    }
+
+    #[test]
+    fn percent_decode_test() {
+        assert_eq!(
+            Some("/p2/acme/private.json".to_string()),
+            percent_decode("/p2/%61cme%2Fprivate.json")
+        );
+        assert_eq!(
+            Some("/p2/acme/100%.json".to_string()),
+            percent_decode("/p2/acme/100%.json")
+        );
+        assert_eq!(None, percent_decode("/p2/acme/%ff.json"));
+    }
+
+    #[test]
+    fn token_store_test() {
+        let path = env::temp_dir().join(format!(
+            "composer_mirror_tokens_{}.json",
+            std::process::id()
+        ));
+        let store = TokenStore::new(&path);
+        let secret = store.add("ci", vec!["Acme/*".to_string()]).unwrap();
+        assert!(store.add("ci", vec![]).is_err());
+        assert_eq!(vec!["acme/*".to_string()], store.tokens()[0].scopes);
+        assert!(!fs::read_to_string(&path).unwrap().contains(&secret));
+
+        let token = store
+            .authenticate(&Credentials::Bearer(secret.clone()))
+            .unwrap();
+        assert!(token.allows("acme/lib"));
+        assert!(!token.allows("other/lib"));
+        let edited = Token {
+            scopes: vec!["Acme/Lib".to_string()],
+            ..token
+        };
+        assert!(edited.allows("acme/lib"));
+        assert!(store
+            .authenticate(&Credentials::Basic {
+                username: "ci".to_string(),
+                password: secret.clone()
+            })
+            .is_some());
+        assert!(store
+            .authenticate(&Credentials::Basic {
+                username: "other".to_string(),
+                password: secret.clone()
+            })
+            .is_none());
+
+        assert!(store.remove("ci").unwrap());
+        assert!(store.authenticate(&Credentials::Bearer(secret)).is_none());
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,226 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+static CIRCUIT_BREAKERS: OnceLock<CircuitBreakers> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+struct Breaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    // 探测请求的开始时间，探测可能因客户端断开而被取消或一直没有响应，超过冷却时间后放行新的探测
+    probe_started_at: Option<Instant>,
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            probe_started_at: None,
+        }
+    }
+}
+
+// 每个上游（按域名区分）一个熔断器：
+// closed 连续失败达到阈值后 open，open 期间直接拒绝请求，
+// 冷却时间过后进入 half_open 只放行一个探测请求，成功则 closed，失败则重新 open，
+// 探测超过冷却时间仍没有结果时再放行一个探测请求
+pub struct CircuitBreakers {
+    breakers: Mutex<HashMap<String, Breaker>>,
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+impl CircuitBreakers {
+    pub fn global() -> &'static CircuitBreakers {
+        CIRCUIT_BREAKERS.get_or_init(CircuitBreakers::from_env)
+    }
+
+    pub fn from_env() -> Self {
+        let failure_threshold = env::var("CIRCUIT_BREAKER_FAILURE_THRESHOLD")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5);
+        let open_secs = env::var("CIRCUIT_BREAKER_OPEN_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(60);
+
+        Self::new(failure_threshold, Duration::from_secs(open_secs))
+    }
+
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            breakers: Mutex::new(HashMap::new()),
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+        }
+    }
+
+    pub fn allow(&self, upstream: &str) -> bool {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(upstream.to_string())
+            .or_insert_with(Breaker::new);
+
+        match breaker.state {
+            BreakerState::Closed => true,
+            BreakerState::Open => {
+                let cooled_down = breaker
+                    .opened_at
+                    .map(|opened_at| opened_at.elapsed() >= self.open_duration)
+                    .unwrap_or(true);
+                if cooled_down {
+                    breaker.state = BreakerState::HalfOpen;
+                    breaker.probe_started_at = Some(Instant::now());
+                    true
+                } else {
+                    false
+                }
+            }
+            BreakerState::HalfOpen => {
+                let probing = breaker
+                    .probe_started_at
+                    .is_some_and(|started_at| started_at.elapsed() < self.open_duration);
+                if probing {
+                    false
+                } else {
+                    breaker.probe_started_at = Some(Instant::now());
+                    true
+                }
+            }
+        }
+    }
+
+    pub fn record_success(&self, upstream: &str) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(upstream.to_string())
+            .or_insert_with(Breaker::new);
+
+        breaker.state = BreakerState::Closed;
+        breaker.consecutive_failures = 0;
+        breaker.opened_at = None;
+        breaker.probe_started_at = None;
+    }
+
+    pub fn record_failure(&self, upstream: &str) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(upstream.to_string())
+            .or_insert_with(Breaker::new);
+
+        breaker.consecutive_failures += 1;
+        breaker.probe_started_at = None;
+        if breaker.state == BreakerState::HalfOpen
+            || breaker.consecutive_failures >= self.failure_threshold
+        {
+            breaker.state = BreakerState::Open;
+            breaker.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let breakers = self.breakers.lock().unwrap();
+        let mut res = serde_json::Map::new();
+        for (upstream, breaker) in breakers.iter() {
+            let retry_in_secs = match (breaker.state, breaker.opened_at) {
+                (BreakerState::Open, Some(opened_at)) => {
+                    self.open_duration.saturating_sub(opened_at.elapsed()).as_secs()
+                }
+                _ => 0,
+            };
+            res.insert(
+                upstream.clone(),
+                json!({
+                    "state": breaker.state.as_str(),
+                    "consecutive_failures": breaker.consecutive_failures,
+                    "retry_in_secs": retry_in_secs,
+                }),
+            );
+        }
+        Value::Object(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(breakers: &CircuitBreakers, upstream: &str) -> Value {
+        breakers.snapshot()[upstream]["state"].clone()
+    }
+
+    #[test]
+    fn breaker_open_and_half_open_test() {
+        let breakers = CircuitBreakers::new(2, Duration::from_millis(50));
+        let upstream = "mirrors.cloud.tencent.com";
+
+        assert!(breakers.allow(upstream));
+        breakers.record_failure(upstream);
+        assert_eq!("closed", state(&breakers, upstream));
+        breakers.record_failure(upstream);
+        assert_eq!("open", state(&breakers, upstream));
+        assert!(!breakers.allow(upstream));
+
+        // 冷却时间过后，下一次请求作为探测放行，探测期间其他请求被拒绝
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breakers.allow(upstream));
+        assert_eq!("half_open", state(&breakers, upstream));
+        assert!(!breakers.allow(upstream));
+
+        breakers.record_success(upstream);
+        assert_eq!("closed", state(&breakers, upstream));
+        assert!(breakers.allow(upstream));
+    }
+
+    #[test]
+    fn abandoned_probe_test() {
+        let breakers = CircuitBreakers::new(1, Duration::from_millis(50));
+        let upstream = "repo.packagist.org";
+
+        breakers.record_failure(upstream);
+        std::thread::sleep(Duration::from_millis(60));
+        // 探测请求被取消，既没有成功也没有失败
+        assert!(breakers.allow(upstream));
+        assert!(!breakers.allow(upstream));
+
+        // 超过冷却时间后放行新的探测
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breakers.allow(upstream));
+        assert!(!breakers.allow(upstream));
+        breakers.record_success(upstream);
+        assert_eq!("closed", state(&breakers, upstream));
+    }
+
+    #[test]
+    fn breaker_stays_open_test() {
+        let breakers = CircuitBreakers::new(1, Duration::from_secs(3600));
+        let upstream = "mirrors.aliyun.com";
+
+        breakers.record_failure(upstream);
+        assert!(!breakers.allow(upstream));
+        assert_eq!("open", state(&breakers, upstream));
+    }
+}
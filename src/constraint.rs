@@ -0,0 +1,229 @@
+use std::cmp::Ordering;
+
+use crate::hosted::normalize_version;
+
+// 规范化后的版本：数字部分、稳定性（dev < alpha < beta < RC < 正式版 < patch）和稳定性后的编号
+pub type VersionKey = (Vec<u64>, u8, u64);
+
+const STABLE: u8 = 4;
+
+// dev 分支等无法比较的版本返回 None
+pub fn version_key(normalized: &str) -> Option<VersionKey> {
+    let (numbers, suffix) = match normalized.split_once('-') {
+        Some((numbers, suffix)) => (numbers, suffix),
+        None => (normalized, ""),
+    };
+    let numbers = numbers
+        .split('.')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    let split = suffix
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(suffix.len());
+    let (stability, number) = suffix.split_at(split);
+    let stability = match stability {
+        "dev" => 0,
+        "alpha" => 1,
+        "beta" => 2,
+        "RC" => 3,
+        "" => STABLE,
+        "patch" => 5,
+        _ => return None,
+    };
+    Some((numbers, stability, number.parse().unwrap_or(0)))
+}
+
+pub fn is_prerelease(key: &VersionKey) -> bool {
+    key.1 < STABLE
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+// 版本约束，外层为“或”，内层为“且”
+#[derive(Clone, Debug)]
+pub struct Constraint(Vec<Vec<(Operator, VersionKey)>>);
+
+fn key(version: &str) -> Option<VersionKey> {
+    normalize_version(version).as_deref().and_then(version_key)
+}
+
+// 与 composer 一样，下限和上限都按 -dev 比较，如 <2.0 不包含 2.0.0-beta1
+fn dev(mut key: VersionKey) -> VersionKey {
+    if key.1 == STABLE {
+        key.1 = 0;
+    }
+    key
+}
+
+// 第 position 段加一，之后的各段清零
+fn bump(key: &VersionKey, position: usize) -> VersionKey {
+    let mut numbers = key.0.clone();
+    numbers[position] += 1;
+    for number in numbers.iter_mut().skip(position + 1) {
+        *number = 0;
+    }
+    (numbers, 0, 0)
+}
+
+fn part_count(version: &str) -> usize {
+    let version = version.split('-').next().unwrap_or_default();
+    version.split('.').count().clamp(1, 4)
+}
+
+fn parse_single(constraint: &str) -> Option<Vec<(Operator, VersionKey)>> {
+    let constraint = constraint.split('@').next().unwrap_or_default().trim();
+    if constraint == "*" || constraint.is_empty() {
+        return Some(Vec::new());
+    }
+    if let Some(version) = constraint.strip_prefix('^') {
+        let lower = key(version)?;
+        let position = match (lower.0[0], lower.0[1], part_count(version)) {
+            (major, _, count) if major > 0 || count == 1 => 0,
+            (_, minor, count) if minor > 0 || count == 2 => 1,
+            _ => 2,
+        };
+        return Some(vec![
+            (Operator::GreaterOrEqual, dev(lower.clone())),
+            (Operator::Less, bump(&lower, position)),
+        ]);
+    }
+    if let Some(version) = constraint.strip_prefix('~') {
+        let lower = key(version)?;
+        let position = part_count(version).saturating_sub(2);
+        return Some(vec![
+            (Operator::GreaterOrEqual, dev(lower.clone())),
+            (Operator::Less, bump(&lower, position)),
+        ]);
+    }
+    if let Some(prefix) = constraint
+        .strip_suffix(".*")
+        .or_else(|| constraint.strip_suffix(".x"))
+    {
+        let lower = key(prefix)?;
+        let position = part_count(prefix) - 1;
+        return Some(vec![
+            (Operator::GreaterOrEqual, dev(lower.clone())),
+            (Operator::Less, bump(&lower, position)),
+        ]);
+    }
+
+    let (operator, version) = [
+        ("<=", Operator::LessOrEqual),
+        (">=", Operator::GreaterOrEqual),
+        ("!=", Operator::NotEqual),
+        ("<>", Operator::NotEqual),
+        ("==", Operator::Equal),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+        ("=", Operator::Equal),
+    ]
+    .iter()
+    .find_map(|(prefix, operator)| {
+        constraint
+            .strip_prefix(prefix)
+            .map(|version| (*operator, version.trim()))
+    })
+    .unwrap_or((Operator::Equal, constraint));
+    let target = key(version)?;
+    let target = match operator {
+        Operator::Less | Operator::GreaterOrEqual => dev(target),
+        _ => target,
+    };
+    Some(vec![(operator, target)])
+}
+
+// 区间写法 1.0 - 2.0，上限不完整时按下一个版本之前处理
+fn parse_range(lower: &str, upper: &str) -> Option<Vec<(Operator, VersionKey)>> {
+    let upper_key = key(upper)?;
+    let upper = match part_count(upper) {
+        count if count < 3 => (Operator::Less, bump(&upper_key, count - 1)),
+        _ => (Operator::LessOrEqual, upper_key),
+    };
+    Some(vec![(Operator::GreaterOrEqual, dev(key(lower)?)), upper])
+}
+
+impl Constraint {
+    // 支持 composer 的常用写法：||、逗号或空格、^、~、通配符、区间以及比较运算符，无法解析时返回 None
+    pub fn parse(constraint: &str) -> Option<Self> {
+        let mut groups = Vec::new();
+        for group in constraint
+            .split('|')
+            .filter(|group| !group.trim().is_empty())
+        {
+            if let Some((lower, upper)) = group.split_once(" - ") {
+                groups.push(parse_range(lower.trim(), upper.trim())?);
+                continue;
+            }
+            // 运算符和版本之间允许有空格，如 >= 7.1
+            let mut tokens = Vec::<String>::new();
+            for token in group.split([',', ' ']).filter(|token| !token.is_empty()) {
+                match tokens.last_mut() {
+                    Some(last) if last.chars().all(|c| "<>=!".contains(c)) => last.push_str(token),
+                    _ => tokens.push(token.to_string()),
+                }
+            }
+            let mut bounds = Vec::new();
+            for token in tokens.iter() {
+                bounds.extend(parse_single(token)?);
+            }
+            groups.push(bounds);
+        }
+        match groups.is_empty() {
+            true => None,
+            false => Some(Self(groups)),
+        }
+    }
+
+    pub fn matches(&self, version: &VersionKey) -> bool {
+        self.0.iter().any(|bounds| {
+            bounds.iter().all(|(operator, target)| {
+                let ordering = version.cmp(target);
+                match operator {
+                    Operator::Less => ordering == Ordering::Less,
+                    Operator::LessOrEqual => ordering != Ordering::Greater,
+                    Operator::Greater => ordering == Ordering::Greater,
+                    Operator::GreaterOrEqual => ordering != Ordering::Less,
+                    Operator::Equal => ordering == Ordering::Equal,
+                    Operator::NotEqual => ordering != Ordering::Equal,
+                }
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(constraint: &str, version: &str) -> bool {
+        Constraint::parse(constraint)
+            .unwrap()
+            .matches(&key(version).unwrap())
+    }
+
+    #[test]
+    fn constraint_test() {
+        assert!(matches("^7.2 || ^8.0", "8.3.1"));
+        assert!(!matches("^7.2 || ^8.0", "7.1.33"));
+        assert!(!matches("^0.3", "0.4.0"));
+        assert!(matches("~7.4", "7.9.0"));
+        assert!(!matches("~7.4.0", "7.5.0"));
+        assert!(matches("7.4.*", "7.4.33"));
+        assert!(!matches("7.4.*", "8.0.0"));
+        assert!(matches(">= 7.1, < 8.0", "7.4.0"));
+        assert!(matches(">=7.1 <8.0", "7.4.0"));
+        assert!(matches("7.3 - 8.1", "8.1.9"));
+        assert!(!matches("7.3 - 8.1", "8.2.0"));
+        assert!(matches("*", "5.3.0"));
+        assert!(Constraint::parse("dev-main").is_none());
+        assert!(version_key("dev-main").is_none());
+    }
+}
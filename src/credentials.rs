@@ -0,0 +1,187 @@
+use glob::Pattern;
+use reqwest::{RequestBuilder, Url};
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+static ORIGIN_CREDENTIALS: OnceLock<OriginCredentials> = OnceLock::new();
+
+// 与 composer auth.json 中的认证方式对应
+#[derive(Clone, PartialEq)]
+pub enum Credential {
+    GithubOauth(String),
+    GitlabToken(String),
+    HttpBasic(String, String),
+    Bearer(String),
+}
+
+impl Credential {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (kind, secret) = s.split_once(':')?;
+        match kind {
+            "github-oauth" => Some(Credential::GithubOauth(secret.to_string())),
+            "gitlab-token" => Some(Credential::GitlabToken(secret.to_string())),
+            "bearer" => Some(Credential::Bearer(secret.to_string())),
+            "http-basic" => {
+                let (username, password) = secret.split_once(':')?;
+                Some(Credential::HttpBasic(
+                    username.to_string(),
+                    password.to_string(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Credential::GithubOauth(token) => {
+                request.header("Authorization", format!("token {}", token))
+            }
+            Credential::GitlabToken(token) => request.header("PRIVATE-TOKEN", token),
+            Credential::HttpBasic(username, password) => {
+                request.basic_auth(username, Some(password))
+            }
+            Credential::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+// 日志和错误信息中不输出令牌、密码，http-basic 只保留用户名
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credential::GithubOauth(_) => f.debug_tuple("GithubOauth").field(&"***").finish(),
+            Credential::GitlabToken(_) => f.debug_tuple("GitlabToken").field(&"***").finish(),
+            Credential::HttpBasic(username, _) => f
+                .debug_tuple("HttpBasic")
+                .field(username)
+                .field(&"***")
+                .finish(),
+            Credential::Bearer(_) => f.debug_tuple("Bearer").field(&"***").finish(),
+        }
+    }
+}
+
+// 错误信息中只保留域名和认证方式
+fn redact(item: &str) -> String {
+    match item.split_once('=') {
+        Some((host, credential)) => match credential.split_once(':') {
+            Some((kind, _)) => format!("{}={}:***", host.trim(), kind.trim()),
+            None => format!("{}=***", host.trim()),
+        },
+        None => "***".to_string(),
+    }
+}
+
+pub struct OriginCredentials {
+    credentials: Vec<(Pattern, Credential)>,
+}
+
+impl OriginCredentials {
+    pub fn global() -> &'static OriginCredentials {
+        ORIGIN_CREDENTIALS
+            .get_or_init(|| OriginCredentials::from_env().unwrap_or_else(|err| panic!("{}", err)))
+    }
+
+    // 解析环境变量中的认证信息，配置有误时返回错误
+    pub fn init() -> Result<(), String> {
+        let credentials = OriginCredentials::from_env()?;
+        let _ = ORIGIN_CREDENTIALS.set(credentials);
+        Ok(())
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&env::var("ORIGIN_CREDENTIALS").unwrap_or_default())
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let credentials = s
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|item| {
+                let invalid = || format!("invalid ORIGIN_CREDENTIALS entry: {}", redact(item));
+                let (host, credential) = item.split_once('=').ok_or_else(invalid)?;
+                let pattern = Pattern::new(&host.trim().to_lowercase()).map_err(|_| invalid())?;
+                let credential = Credential::parse(credential.trim()).ok_or_else(invalid)?;
+                Ok((pattern, credential))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self { credentials })
+    }
+
+    pub fn credential_for(&self, url: &str) -> Option<&Credential> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_lowercase();
+        self.credentials
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&host))
+            .map(|(_, credential)| credential)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_for_test() {
+        let credentials = OriginCredentials::parse(
+            "api.github.com=github-oauth:ghp_xxx,gitlab.quansitech.com=gitlab-token:glpat-yyy,*.example.com=http-basic:user:p@ss:word",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(&Credential::GithubOauth("ghp_xxx".to_string())),
+            credentials.credential_for(
+                "https://api.github.com/repos/quansitech/think-core/zipball/35c34ca5"
+            )
+        );
+        assert_eq!(
+            Some(&Credential::GitlabToken("glpat-yyy".to_string())),
+            credentials.credential_for("https://gitlab.quansitech.com/api/v4/projects/1/repository/archive.zip")
+        );
+        assert_eq!(
+            Some(&Credential::HttpBasic("user".to_string(), "p@ss:word".to_string())),
+            credentials.credential_for("https://repo.example.com/dists/a.zip")
+        );
+        assert_eq!(None, credentials.credential_for("https://codeload.github.com/a/b"));
+    }
+
+    #[test]
+    fn invalid_entry_test() {
+        for s in [
+            "api.github.com",
+            "api.github.com=github-oauth",
+            "api.github.com=token:ghp_xxx",
+            "repo.example.com=http-basic:user",
+            "[=bearer:abc",
+        ] {
+            let err = OriginCredentials::parse(s).err().unwrap();
+            assert!(err.starts_with("invalid ORIGIN_CREDENTIALS entry"), "{}", s);
+            assert!(!err.contains("ghp_xxx") && !err.contains("abc"), "{}", err);
+        }
+        assert!(OriginCredentials::parse(" , ")
+            .unwrap()
+            .credentials
+            .is_empty());
+    }
+
+    #[test]
+    fn debug_redacts_secret_test() {
+        assert_eq!(
+            r#"GithubOauth("***")"#,
+            format!("{:?}", Credential::GithubOauth("ghp_xxx".to_string()))
+        );
+        assert_eq!(
+            r#"HttpBasic("user", "***")"#,
+            format!(
+                "{:?}",
+                Credential::HttpBasic("user".to_string(), "p@ss".to_string())
+            )
+        );
+        assert!(!format!("{:?}", Credential::Bearer("abc".to_string())).contains("abc"));
+    }
+}
@@ -21,4 +21,12 @@ impl<'a> Dist<'a> {
             dist_type,
         }
     }
+
+    // 自有存储中的对象名，回源上传和私有扩展上传共用
+    pub fn object_name(&self) -> String {
+        format!(
+            "{}/{}/{}.{}",
+            self.package.full_name, self.version, self.reference, self.dist_type
+        )
+    }
 }
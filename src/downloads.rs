@@ -0,0 +1,437 @@
+use axum::{
+    extract::{ConnectInfo, Path, Query},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{Days, Local, NaiveDate};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::hosted::is_valid_name;
+use crate::journal::client_ip;
+use crate::request_helper;
+use crate::{check_package_in_white_list, Config};
+
+// 按天的统计只保留最近 30 天
+const HISTORY_DAYS: u64 = 30;
+// 单次通知最多接受的条数，超出部分忽略
+const MAX_BATCH: usize = 500;
+// 等待转发的通知最多保留的条数，上游长时间不可用时丢弃最早的
+const MAX_PENDING: usize = 10000;
+
+#[derive(Debug, Deserialize)]
+pub struct Download {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Deserialize)]
+pub struct NotifyBatch {
+    #[serde(default)]
+    downloads: Vec<Download>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct PackageStats {
+    total: u64,
+    versions: BTreeMap<String, u64>,
+    // 日期 => 下载次数，日期格式为 2023-09-06
+    daily: BTreeMap<String, u64>,
+}
+
+impl PackageStats {
+    // 包含今天在内最近 days 天的下载次数
+    fn recent(&self, today: NaiveDate, days: u64) -> u64 {
+        let since = (today - Days::new(days - 1)).to_string();
+        self.daily.range(since..).map(|(_, count)| count).sum()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Stats {
+    packages: BTreeMap<String, PackageStats>,
+    clients: BTreeMap<String, u64>,
+}
+
+// composer 安装后通过 notify-batch 上报的下载次数，保存在本地 JSON 文件中
+pub struct DownloadStats {
+    path: PathBuf,
+    stats: Mutex<Stats>,
+    // 有未保存的统计，由后台任务定时写入文件
+    dirty: AtomicBool,
+    // 设置后定时把收到的通知合并转发到上游，如 https://packagist.org/downloads/
+    forward_url: Option<String>,
+    pending: Mutex<Vec<Value>>,
+}
+
+impl DownloadStats {
+    pub fn new(path: impl Into<PathBuf>, forward_url: Option<String>) -> Self {
+        let path = path.into();
+        let stats = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            stats: Mutex::new(stats),
+            dirty: AtomicBool::new(false),
+            forward_url,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    // 未设置 DOWNLOADS_STATS_PATH 时不开启
+    pub fn from_env() -> Option<Self> {
+        let path = env::var("DOWNLOADS_STATS_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())?;
+        let forward_url = env::var("NOTIFY_BATCH_FORWARD_URL")
+            .ok()
+            .filter(|url| !url.is_empty());
+        Some(Self::new(path, forward_url))
+    }
+
+    // 有变化时把统计写入文件，在阻塞线程中调用
+    pub fn flush(&self) -> Result<(), String> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let content = serde_json::to_string(&*self.stats.lock().unwrap());
+        let result = content
+            .map_err(|err| err.to_string())
+            .and_then(|content| self.save(content));
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    fn save(&self, content: String) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| format!("failed to write {}: {}", self.path.display(), err))
+    }
+
+    pub fn record(&self, client: Option<&str>, downloads: &[Download], today: NaiveDate) {
+        let day = today.to_string();
+        let oldest = (today - Days::new(HISTORY_DAYS - 1)).to_string();
+        let mut stats = self.stats.lock().unwrap();
+        for download in downloads {
+            let package = stats.packages.entry(download.name.clone()).or_default();
+            package.total += 1;
+            *package
+                .versions
+                .entry(download.version.clone())
+                .or_default() += 1;
+            *package.daily.entry(day.clone()).or_default() += 1;
+            package.daily.retain(|day, _| *day >= oldest);
+        }
+        if let Some(client) = client {
+            *stats.clients.entry(client.to_string()).or_default() += downloads.len() as u64;
+        }
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    // 超出 MAX_PENDING 时丢弃最早的通知
+    fn push_pending(pending: &mut Vec<Value>, downloads: impl IntoIterator<Item = Value>) {
+        pending.extend(downloads);
+        if pending.len() > MAX_PENDING {
+            pending.drain(..pending.len() - MAX_PENDING);
+        }
+    }
+
+    fn queue(&self, downloads: &[Download]) {
+        if self.forward_url.is_none() {
+            return;
+        }
+        Self::push_pending(
+            &mut self.pending.lock().unwrap(),
+            downloads
+                .iter()
+                .map(|download| json!({ "name": download.name, "version": download.version })),
+        );
+    }
+
+    // 把积累的通知合并成一次请求转发到上游，失败时放回队列等下次重试
+    async fn forward(&self) {
+        let url = match &self.forward_url {
+            Some(url) => url,
+            None => return,
+        };
+        let downloads = std::mem::take(&mut *self.pending.lock().unwrap());
+        if downloads.is_empty() {
+            return;
+        }
+        let body = json!({ "downloads": &downloads });
+        let result = match request_helper::post_json(url, &body).await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(response.status().to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = result {
+            eprintln!("failed to forward downloads to {}: {}", url, err);
+            // 失败的通知排在转发期间新收到的通知之前
+            let mut pending = self.pending.lock().unwrap();
+            let newer = std::mem::replace(&mut *pending, downloads);
+            Self::push_pending(&mut pending, newer);
+        }
+    }
+
+    fn package_summary(name: &str, package: &PackageStats, today: NaiveDate) -> Value {
+        json!({
+            "name": name,
+            "daily": package.recent(today, 1),
+            "weekly": package.recent(today, 7),
+            "total": package.total,
+        })
+    }
+
+    // 按 sort（daily、weekly、total）排序的下载最多的扩展和客户端
+    pub fn summary(&self, sort: &str, limit: usize, today: NaiveDate) -> Value {
+        let stats = self.stats.lock().unwrap();
+        let mut packages = stats
+            .packages
+            .iter()
+            .map(|(name, package)| Self::package_summary(name, package, today))
+            .collect::<Vec<Value>>();
+        packages.sort_by_key(|package| std::cmp::Reverse(package[sort].as_u64().unwrap_or(0)));
+        packages.truncate(limit);
+
+        let mut clients = stats.clients.iter().collect::<Vec<(&String, &u64)>>();
+        clients.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+        clients.truncate(limit);
+
+        json!({
+            "packages": packages,
+            "clients": clients
+                .into_iter()
+                .map(|(client, count)| json!({ "client": client, "total": count }))
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    pub fn total(&self, name: &str) -> u64 {
+        let stats = self.stats.lock().unwrap();
+        stats.packages.get(name).map(|package| package.total).unwrap_or(0)
+    }
+
+    pub fn package(&self, name: &str, today: NaiveDate) -> Option<Value> {
+        let stats = self.stats.lock().unwrap();
+        let package = stats.packages.get(name)?;
+        let mut summary = Self::package_summary(name, package, today);
+        summary["versions"] = json!(package.versions);
+        summary["history"] = json!(package.daily);
+        Some(summary)
+    }
+}
+
+// 按 DOWNLOADS_FLUSH_SECS 定时保存统计，默认 10 秒；按 NOTIFY_BATCH_FORWARD_SECS 定时转发，默认 300 秒
+pub fn spawn(stats: Arc<DownloadStats>) {
+    let flush_interval = env::var("DOWNLOADS_FLUSH_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(10);
+    let flushing = stats.clone();
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(flush_interval)).await;
+            let stats = flushing.clone();
+            let result = tokio::task::spawn_blocking(move || stats.flush()).await;
+            if let Ok(Err(err)) = result {
+                eprintln!("failed to save download stats: {}", err);
+            }
+        }
+    });
+
+    if stats.forward_url.is_none() {
+        return;
+    }
+    let interval = env::var("NOTIFY_BATCH_FORWARD_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(300);
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(interval)).await;
+            stats.forward().await;
+        }
+    });
+}
+
+fn not_enabled() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": "download stats are not enabled" })),
+    )
+        .into_response()
+}
+
+// composer 的 notify-batch，私有扩展只做统计不转发
+pub async fn notify_batch(
+    config: Extension<Config>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(batch): Json<NotifyBatch>,
+) -> Response {
+    let stats = match &config.downloads {
+        Some(stats) => stats,
+        None => return not_enabled(),
+    };
+    let downloads = batch
+        .downloads
+        .into_iter()
+        .take(MAX_BATCH)
+        .filter(|download| is_valid_name(&download.name) && download.version.len() <= 64)
+        .map(|download| Download {
+            name: download.name.to_lowercase(),
+            version: download.version,
+        })
+        .collect::<Vec<Download>>();
+    let client = client_ip(&headers, connect_info.as_ref());
+    stats.record(client.as_deref(), &downloads, Local::now().date_naive());
+
+    let public = downloads
+        .into_iter()
+        .filter(|download| {
+            !check_package_in_white_list(&download.name, &config.private_packages)
+                && !config
+                    .repositories
+                    .iter()
+                    .any(|repository| repository.matches(&download.name))
+        })
+        .collect::<Vec<Download>>();
+    stats.queue(&public);
+    Json(json!({ "status": "success" })).into_response()
+}
+
+pub async fn summary(
+    config: Extension<Config>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let stats = match &config.downloads {
+        Some(stats) => stats,
+        None => return not_enabled(),
+    };
+    let sort = params
+        .get("sort")
+        .map(|s| s.as_str())
+        .filter(|sort| ["daily", "weekly", "total"].contains(sort))
+        .unwrap_or("weekly");
+    let limit = params
+        .get("limit")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(50);
+    Json(stats.summary(sort, limit, Local::now().date_naive())).into_response()
+}
+
+pub async fn package(
+    Path((vendor, package)): Path<(String, String)>,
+    config: Extension<Config>,
+) -> Response {
+    let stats = match &config.downloads {
+        Some(stats) => stats,
+        None => return not_enabled(),
+    };
+    match stats.package(
+        &format!("{}/{}", vendor, package),
+        Local::now().date_naive(),
+    ) {
+        Some(summary) => Json(summary).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "package has no downloads" })),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn download_stats_test() {
+        let path = env::temp_dir().join(format!(
+            "composer_mirror_downloads_{}.json",
+            std::process::id()
+        ));
+        let stats = DownloadStats::new(&path, None);
+        let download = |name: &str, version: &str| Download {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        let today = NaiveDate::from_ymd_opt(2023, 9, 6).unwrap();
+        stats.record(
+            Some("10.0.0.1"),
+            &[download("acme/lib", "1.0.0.0")],
+            today - Days::new(40),
+        );
+        stats.record(
+            Some("10.0.0.1"),
+            &[download("acme/lib", "1.0.0.0")],
+            today - Days::new(3),
+        );
+        stats.record(
+            Some("10.0.0.2"),
+            &[
+                download("acme/lib", "1.1.0.0"),
+                download("acme/tool", "2.0.0.0"),
+            ],
+            today,
+        );
+
+        let summary = stats.summary("weekly", 10, today);
+        assert_eq!("acme/lib", summary["packages"][0]["name"]);
+        assert_eq!(1, summary["packages"][0]["daily"]);
+        assert_eq!(2, summary["packages"][0]["weekly"]);
+        assert_eq!(3, summary["packages"][0]["total"]);
+        assert_eq!(2, summary["clients"][0]["total"]);
+
+        // 超过 30 天的按天统计被清理，总数保留
+        let package = stats.package("acme/lib", today).unwrap();
+        assert_eq!(2, package["history"].as_object().unwrap().len());
+        assert_eq!(2, package["versions"]["1.0.0.0"]);
+
+        // 重新读取保存的文件
+        stats.flush().unwrap();
+        let reloaded = DownloadStats::new(&path, None);
+        assert_eq!(3, reloaded.package("acme/lib", today).unwrap()["total"]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn pending_limit_test() {
+        let stats = DownloadStats::new(
+            env::temp_dir().join("composer_mirror_downloads_pending.json"),
+            Some("http://127.0.0.1:1/downloads/".to_string()),
+        );
+        let downloads = (0..=MAX_PENDING)
+            .map(|i| Download {
+                name: "acme/lib".to_string(),
+                version: i.to_string(),
+            })
+            .collect::<Vec<Download>>();
+        stats.queue(&downloads);
+        let pending = stats.pending.lock().unwrap();
+        assert_eq!(MAX_PENDING, pending.len());
+        // 丢弃最早的一条
+        assert_eq!("1", pending[0]["version"]);
+    }
+}
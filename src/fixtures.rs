@@ -0,0 +1,228 @@
+use axum::http::{self, HeaderMap, HeaderName, HeaderValue, Method};
+use reqwest::{Request, Response as ReqwestResponse};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tokio::fs;
+
+static FIXTURES: OnceLock<Option<Fixtures>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixtureMode {
+    Record,
+    Replay,
+}
+
+// 录制经 request_helper 发出的上游请求和响应，回放时不再访问真实上游。
+// 同一请求按顺序编号，回放时依次返回，录制的次数用完后一直返回最后一次
+pub struct Fixtures {
+    mode: FixtureMode,
+    dir: PathBuf,
+    max_body_bytes: usize,
+    counters: Mutex<HashMap<String, usize>>,
+}
+
+impl Fixtures {
+    pub fn global() -> Option<&'static Fixtures> {
+        FIXTURES.get_or_init(Fixtures::from_env).as_ref()
+    }
+
+    fn from_env() -> Option<Self> {
+        let mode = match env::var("UPSTREAM_FIXTURES_MODE").unwrap_or_default().as_str() {
+            "record" => FixtureMode::Record,
+            "replay" => FixtureMode::Replay,
+            _ => return None,
+        };
+        let dir = env::var("UPSTREAM_FIXTURES_DIR").unwrap_or_else(|_| "./fixtures".to_string());
+        let max_body_bytes = env::var("UPSTREAM_FIXTURES_MAX_BODY")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1024 * 1024);
+
+        Some(Self::new(mode, PathBuf::from(dir), max_body_bytes))
+    }
+
+    pub fn new(mode: FixtureMode, dir: PathBuf, max_body_bytes: usize) -> Self {
+        Self {
+            mode,
+            dir,
+            max_body_bytes,
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    // 文件名带上方法和主机便于人工查找，再加上完整地址的摘要区分不同请求
+    fn key(method: &Method, url: &str) -> String {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default();
+        let digest = hex::encode(Sha256::digest(url.as_bytes()));
+        format!("{}_{}_{}", method, host, &digest[..16])
+    }
+
+    fn next_sequence(&self, key: &str) -> usize {
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters.entry(key.to_string()).or_insert(0);
+        *counter += 1;
+        *counter - 1
+    }
+
+    fn meta_path(&self, key: &str, sequence: usize) -> PathBuf {
+        self.dir.join(format!("{}.{}.json", key, sequence))
+    }
+
+    pub async fn record(
+        &self,
+        method: &Method,
+        url: &str,
+        result: Result<ReqwestResponse, reqwest::Error>,
+    ) -> Result<ReqwestResponse, reqwest::Error> {
+        let key = Self::key(method, url);
+        let sequence = self.next_sequence(&key);
+        let mut meta = json!({ "method": method.as_str(), "url": url });
+
+        let response = match result {
+            Ok(response) => response,
+            Err(err) => {
+                meta["error"] = json!(err.to_string());
+                self.write(&key, sequence, &meta, None).await;
+                return Err(err);
+            }
+        };
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        meta["status"] = json!(status.as_u16());
+        meta["headers"] = Value::Object(
+            headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), json!(value.to_str().ok()?)))
+                })
+                .collect::<Map<String, Value>>(),
+        );
+        meta["body_bytes"] = json!(body.len());
+        let stored_body = match body.len() <= self.max_body_bytes {
+            true => Some(&body[..]),
+            false => {
+                meta["body_omitted"] = json!(true);
+                None
+            }
+        };
+        self.write(&key, sequence, &meta, stored_body).await;
+
+        let mut rebuilt = http::Response::new(body.to_vec());
+        *rebuilt.status_mut() = status;
+        *rebuilt.headers_mut() = headers;
+        Ok(ReqwestResponse::from(rebuilt))
+    }
+
+    async fn write(&self, key: &str, sequence: usize, meta: &Value, body: Option<&[u8]>) {
+        let _ = fs::create_dir_all(&self.dir).await;
+        let meta_path = self.meta_path(key, sequence);
+        let _ = match body {
+            Some(body) => fs::write(meta_path.with_extension("body"), body).await,
+            None => fs::remove_file(meta_path.with_extension("body")).await,
+        };
+        if let Err(err) = fs::write(&meta_path, meta.to_string()).await {
+            eprintln!("failed to write fixture {}: {}", meta_path.display(), err);
+        }
+    }
+
+    async fn read(&self, key: &str, sequence: usize) -> Option<(Value, PathBuf)> {
+        for sequence in (0..=sequence).rev() {
+            let path = self.meta_path(key, sequence);
+            if let Ok(content) = fs::read_to_string(&path).await {
+                return Some((serde_json::from_str(&content).ok()?, path));
+            }
+        }
+        None
+    }
+
+    // 没有录制过的请求和录制时出错的请求都返回 Err
+    pub async fn replay(&self, request: &Request) -> Result<ReqwestResponse, String> {
+        let url = request.url().as_str();
+        let key = Self::key(request.method(), url);
+        let sequence = self.next_sequence(&key);
+        let (meta, path) = self
+            .read(&key, sequence)
+            .await
+            .ok_or_else(|| format!("no fixture recorded for {} {}", request.method(), url))?;
+        if let Some(err) = meta["error"].as_str() {
+            return Err(err.to_string());
+        }
+
+        let body = fs::read(path.with_extension("body")).await.unwrap_or_default();
+        let mut headers = HeaderMap::new();
+        for (name, value) in meta["headers"].as_object().into_iter().flatten() {
+            if let (Ok(name), Some(Ok(value))) = (
+                HeaderName::try_from(name.as_str()),
+                value.as_str().map(HeaderValue::try_from),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+        if meta["body_omitted"] == true {
+            headers.remove("content-length");
+        }
+
+        let mut response = http::Response::new(body);
+        *response.status_mut() = meta["status"]
+            .as_u64()
+            .and_then(|status| http::StatusCode::from_u16(status as u16).ok())
+            .unwrap_or(http::StatusCode::OK);
+        *response.headers_mut() = headers;
+        Ok(ReqwestResponse::from(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn record_and_replay_test() {
+        let dir = env::temp_dir().join(format!("composer_mirror_fixtures_{}", std::process::id()));
+        let url = "https://repo.packagist.org/p2/quansitech/think-core.json";
+        let client = reqwest::Client::new();
+
+        let recorder = Fixtures::new(FixtureMode::Record, dir.clone(), 4);
+        for body in ["{}", "too large"] {
+            let mut response = http::Response::new(body.to_string());
+            response
+                .headers_mut()
+                .insert("content-type", HeaderValue::from_static("application/json"));
+            let response = recorder
+                .record(&Method::GET, url, Ok(ReqwestResponse::from(response)))
+                .await
+                .unwrap();
+            // 录制后调用方仍能读到完整的响应体
+            assert_eq!(body, response.text().await.unwrap());
+        }
+
+        let player = Fixtures::new(FixtureMode::Replay, dir.clone(), 4);
+        let request = client.get(url).build().unwrap();
+        let response = player.replay(&request).await.unwrap();
+        assert_eq!("application/json", response.headers()["content-type"]);
+        assert_eq!("{}", response.text().await.unwrap());
+        // 超过大小限制的响应体不保存，录制次数用完后一直返回最后一次
+        for _ in 0..2 {
+            let response = player.replay(&request).await.unwrap();
+            assert_eq!("", response.text().await.unwrap());
+        }
+
+        let request = client.head(url).build().unwrap();
+        assert!(player.replay(&request).await.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,381 @@
+use glob::Pattern;
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::dist::Dist;
+use crate::hosted::{dist_path, is_valid_name, normalize_version, version_entry};
+use crate::lookup_error::NegativeCache;
+use crate::package::Package;
+use crate::Config;
+
+static BUILD_LOCKS: OnceLock<StdMutex<HashMap<String, Arc<Mutex<()>>>>> = OnceLock::new();
+
+// 同一个仓库同一时间只允许一个构建，避免重复打包上传，不同仓库可以同时构建
+fn build_lock(url: &str) -> Arc<Mutex<()>> {
+    BUILD_LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry(url.to_string())
+        .or_default()
+        .clone()
+}
+
+struct GitRef {
+    version: String,
+    version_normalized: String,
+    commit: String,
+    time: String,
+}
+
+pub(crate) async fn git(dir: Option<&Path>, args: &[&str]) -> Result<Vec<u8>, String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await
+        .map_err(|err| format!("failed to run git: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+// 仓库地址的最后两段，用于和 webhook 中的仓库名对应，如 https://gitea.example.com/acme/lib.git => acme/lib
+pub fn repository_name(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url).to_lowercase();
+    let segments = url.split(['/', ':']).collect::<Vec<&str>>();
+    segments[segments.len().saturating_sub(2)..].join("/")
+}
+
+pub fn find_repository(config: &Config, repository: &str) -> Option<String> {
+    let repository = repository.to_lowercase();
+    config
+        .git_repositories
+        .iter()
+        .find(|url| repository_name(url) == repository)
+        .cloned()
+}
+
+// 数字分支按 composer 的规则为 1.x-dev，其余分支为 dev-分支名
+fn branch_version(branch: &str) -> (String, String) {
+    let numbers = branch
+        .trim_start_matches(['v', 'V'])
+        .split('.')
+        .collect::<Vec<&str>>();
+    let numeric = numbers.len() <= 4
+        && numbers[0].bytes().all(|b| b.is_ascii_digit())
+        && numbers.iter().all(|part| {
+            !part.is_empty() && (*part == "x" || part.bytes().all(|b| b.is_ascii_digit()))
+        });
+    if !numeric {
+        return (format!("dev-{}", branch), format!("dev-{}", branch));
+    }
+
+    let mut normalized = numbers
+        .iter()
+        .map(|part| match *part {
+            "x" => "9999999",
+            part => part,
+        })
+        .collect::<Vec<&str>>();
+    normalized.resize(4, "9999999");
+    let version = match branch.ends_with(".x") {
+        true => format!("{}-dev", branch),
+        false => format!("{}.x-dev", branch),
+    };
+    (version, format!("{}-dev", normalized.join(".")))
+}
+
+// 标签需要是合法的版本号；带 / 的分支无法放进 dist 地址，跳过
+fn parse_ref(line: &str) -> Option<GitRef> {
+    let fields = line.split('\t').collect::<Vec<&str>>();
+    let (refname, object, peeled, time) = match fields.as_slice() {
+        [refname, object, peeled, time] => (*refname, *object, *peeled, *time),
+        _ => return None,
+    };
+    let (version, version_normalized) = match refname.strip_prefix("refs/tags/") {
+        Some(tag) => (tag.to_string(), normalize_version(tag)?),
+        None => {
+            let branch = refname.strip_prefix("refs/heads/")?;
+            if branch.contains('/') {
+                return None;
+            }
+            branch_version(branch)
+        }
+    };
+    let commit = match peeled.is_empty() {
+        true => object,
+        false => peeled,
+    };
+
+    Some(GitRef {
+        version,
+        version_normalized,
+        commit: commit.to_string(),
+        time: time.to_string(),
+    })
+}
+
+// 仓库以 mirror 方式克隆到 GIT_BUILD_DIR，之后只做增量更新
+async fn update_mirror(config: &Config, url: &str) -> Result<PathBuf, String> {
+    let digest = hex::encode(Sha256::digest(url.as_bytes()));
+    let dir = config.git_build_dir.join(format!("{}.git", &digest[..16]));
+    if dir.exists() {
+        git(Some(&dir), &["remote", "update", "--prune"]).await?;
+    } else {
+        tokio::fs::create_dir_all(&config.git_build_dir)
+            .await
+            .map_err(|err| err.to_string())?;
+        git(
+            None,
+            &["clone", "--mirror", "-q", url, &dir.to_string_lossy()],
+        )
+        .await?;
+    }
+    Ok(dir)
+}
+
+// git archive 直接写入临时文件，边读边计算 sha1，不把整个 zip 放在内存中
+async fn archive(dir: &Path, commit: &str) -> Result<(PathBuf, String), String> {
+    let path = dir.with_extension(format!("{}.zip", commit));
+    git(
+        Some(dir),
+        &[
+            "archive",
+            "--format=zip",
+            "-o",
+            &path.to_string_lossy(),
+            commit,
+        ],
+    )
+    .await?;
+
+    let shasum = async {
+        let mut file = tokio::fs::File::open(&path).await?;
+        let mut hasher = Sha1::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buffer).await? {
+                0 => return Ok(hex::encode(hasher.finalize())),
+                n => hasher.update(&buffer[..n]),
+            }
+        }
+    }
+    .await
+    .map_err(|err: std::io::Error| err.to_string());
+    if shasum.is_err() {
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+    Ok((path, shasum?))
+}
+
+// 构建单个仓库，返回生成了元数据的扩展
+pub async fn build(config: &Config, url: &str) -> Result<Vec<String>, String> {
+    let storage = config
+        .storage
+        .as_ref()
+        .ok_or_else(|| "storage is not configured".to_string())?;
+    let lock = build_lock(url);
+    let _guard = lock.lock().await;
+    let dir = update_mirror(config, url).await?;
+
+    let refs = git(
+        Some(&dir),
+        &[
+            "for-each-ref",
+            "--sort=-creatordate",
+            "--format=%(refname)%09%(objectname)%09%(*objectname)%09%(creatordate:iso-strict)",
+            "refs/tags",
+            "refs/heads",
+        ],
+    )
+    .await?;
+
+    let mut packages = HashMap::<String, Vec<(GitRef, Map<String, Value>)>>::new();
+    for git_ref in String::from_utf8_lossy(&refs).lines().filter_map(parse_ref) {
+        let composer = git(
+            Some(&dir),
+            &["show", &format!("{}:composer.json", git_ref.commit)],
+        )
+        .await
+        .ok()
+        .and_then(|content| serde_json::from_slice::<Value>(&content).ok());
+        let composer = match composer {
+            Some(Value::Object(composer)) => composer,
+            _ => continue,
+        };
+        let name = match composer.get("name").and_then(|name| name.as_str()) {
+            Some(name) if is_valid_name(name) => name.to_lowercase(),
+            _ => continue,
+        };
+        packages.entry(name).or_default().push((git_ref, composer));
+    }
+
+    for (name, refs) in packages.iter_mut() {
+        // 提交未变化的版本沿用已上传的 dist
+        let mut existing = HashMap::new();
+        for version in config.hosted.versions(name).await? {
+            if let (Some(version_name), Some(reference)) = (
+                version["version"].as_str(),
+                version["dist"]["reference"].as_str(),
+            ) {
+                existing.insert(
+                    (version_name.to_string(), reference.to_string()),
+                    version["dist"].clone(),
+                );
+            }
+        }
+
+        let (vendor, package) = name.split_once('/').unwrap();
+        let package = Package::new(vendor, package);
+        let mut versions = Vec::new();
+        for (git_ref, composer) in refs.drain(..) {
+            let dist = match existing.remove(&(git_ref.version.clone(), git_ref.commit.clone())) {
+                Some(dist) => dist,
+                None => {
+                    let (path, shasum) = archive(&dir, &git_ref.commit).await?;
+                    let dist = Dist::new(&package, &git_ref.version, &git_ref.commit, "zip");
+                    let uploaded = storage.put_file(&dist.object_name(), &path).await;
+                    let _ = tokio::fs::remove_file(&path).await;
+                    uploaded?;
+                    json!({
+                        "type": "zip",
+                        "url": dist_path(&dist),
+                        "reference": git_ref.commit,
+                        "shasum": shasum,
+                    })
+                }
+            };
+
+            let mut entry = version_entry(
+                composer,
+                name,
+                &git_ref.version,
+                &git_ref.version_normalized,
+            );
+            entry.insert(
+                "source".to_string(),
+                json!({ "type": "git", "url": url, "reference": git_ref.commit }),
+            );
+            entry.insert("dist".to_string(), dist);
+            entry.insert("time".to_string(), json!(git_ref.time));
+            versions.push(Value::Object(entry));
+        }
+
+        config.hosted.set_versions(name, versions).await?;
+        NegativeCache::global().purge(&Pattern::new(&Pattern::escape(name)).unwrap());
+    }
+    if let Some(index) = &config.package_index {
+        index.invalidate_local();
+    }
+
+    Ok(packages.into_keys().collect())
+}
+
+pub async fn build_all(config: &Config) {
+    for url in config.git_repositories.iter() {
+        match build(config, url).await {
+            Ok(packages) => println!("built {}: {}", url, packages.join(",")),
+            Err(err) => eprintln!("failed to build {}: {}", url, err),
+        }
+    }
+}
+
+// 按 GIT_BUILD_INTERVAL_SECS 定时构建，未设置或为 0 时不开启
+pub fn spawn(config: Config) {
+    let interval = env::var("GIT_BUILD_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(0);
+    if interval == 0 || config.git_repositories.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            build_all(&config).await;
+            sleep(Duration::from_secs(interval)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repository_name_test() {
+        assert_eq!(
+            "acme/lib",
+            repository_name("https://gitea.example.com/Acme/lib.git")
+        );
+        assert_eq!(
+            "acme/lib",
+            repository_name("git@gitea.example.com:acme/lib.git")
+        );
+        assert_eq!("git/lib", repository_name("/srv/git/lib.git/"));
+    }
+
+    #[test]
+    fn build_lock_test() {
+        let lock = build_lock("https://gitea.example.com/acme/lib.git");
+        assert!(Arc::ptr_eq(
+            &lock,
+            &build_lock("https://gitea.example.com/acme/lib.git")
+        ));
+        let _guard = lock.try_lock().unwrap();
+        // 其他仓库的构建不受影响
+        assert!(build_lock("https://gitea.example.com/acme/other.git")
+            .try_lock()
+            .is_ok());
+    }
+
+    #[test]
+    fn parse_ref_test() {
+        let git_ref = parse_ref("refs/tags/v1.2.0\taaa\tbbb\t2023-09-06T16:49:12+08:00").unwrap();
+        assert_eq!("v1.2.0", git_ref.version);
+        assert_eq!("1.2.0.0", git_ref.version_normalized);
+        // 附注标签使用指向的提交
+        assert_eq!("bbb", git_ref.commit);
+
+        let git_ref = parse_ref("refs/heads/main\taaa\t\t2023-09-06T16:49:12+08:00").unwrap();
+        assert_eq!("dev-main", git_ref.version);
+        assert_eq!("aaa", git_ref.commit);
+
+        assert_eq!(
+            (
+                "1.x-dev".to_string(),
+                "1.9999999.9999999.9999999-dev".to_string()
+            ),
+            branch_version("1.x")
+        );
+        assert_eq!(
+            (
+                "2.0.x-dev".to_string(),
+                "2.0.9999999.9999999-dev".to_string()
+            ),
+            branch_version("2.0")
+        );
+        assert!(parse_ref("refs/tags/not-a-version\taaa\t\t").is_none());
+        assert!(parse_ref("refs/heads/feature/x\taaa\t\t").is_none());
+    }
+}
@@ -0,0 +1,356 @@
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Path, RawQuery},
+    http::{HeaderMap, HeaderName, HeaderValue, Method},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+use crate::git_builder::git;
+use crate::hosted::is_valid_name;
+use crate::metadata_cache::MetadataCache;
+use crate::mirrors::packagist::Packagist;
+use crate::{check_package_in_white_list, Config};
+
+static GIT_MIRRORS: OnceLock<GitMirrors> = OnceLock::new();
+
+// 白名单扩展源码仓库的本地镜像，请求 info/refs 时按需克隆或更新
+struct GitMirrors {
+    refresh_interval: Duration,
+    refreshed: Mutex<HashMap<String, Instant>>,
+    // 同一个仓库同一时间只做一次克隆或更新
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl GitMirrors {
+    fn global() -> &'static GitMirrors {
+        GIT_MIRRORS.get_or_init(|| {
+            let secs = env::var("GIT_MIRROR_REFRESH_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300);
+            GitMirrors {
+                refresh_interval: Duration::from_secs(secs),
+                refreshed: Mutex::new(HashMap::new()),
+                locks: Mutex::new(HashMap::new()),
+            }
+        })
+    }
+
+    fn is_fresh(&self, package: &str) -> bool {
+        self.refreshed
+            .lock()
+            .unwrap()
+            .get(package)
+            .is_some_and(|at| at.elapsed() < self.refresh_interval)
+    }
+
+    fn lock(&self, package: &str) -> Arc<tokio::sync::Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap()
+            .entry(package.to_string())
+            .or_default()
+            .clone()
+    }
+
+    // 已有镜像更新失败时继续使用旧的镜像
+    async fn ensure(&self, config: &Config, package: &str) -> Result<(), String> {
+        if self.is_fresh(package) {
+            return Ok(());
+        }
+        let lock = self.lock(package);
+        let _guard = lock.lock().await;
+        if self.is_fresh(package) {
+            return Ok(());
+        }
+
+        let dir = config.git_mirror_dir.join(format!("{}.git", package));
+        if dir.exists() {
+            if let Err(err) = git(Some(&dir), &["remote", "update", "--prune"]).await {
+                eprintln!("failed to update git mirror of {}: {}", package, err);
+            }
+        } else {
+            let upstream = upstream_url(config, package).await?;
+            tokio::fs::create_dir_all(&config.git_mirror_dir)
+                .await
+                .map_err(|err| err.to_string())?;
+            git(
+                None,
+                &["clone", "--mirror", "-q", &upstream, &dir.to_string_lossy()],
+            )
+            .await?;
+        }
+        self.refreshed
+            .lock()
+            .unwrap()
+            .insert(package.to_string(), Instant::now());
+        Ok(())
+    }
+}
+
+// 源码仓库地址取自 packagist 元数据中最新版本的 source
+async fn upstream_url(config: &Config, package: &str) -> Result<String, String> {
+    let url = Packagist::new(config).get_package_meta_url(package);
+    let metadata = MetadataCache::global()
+        .fetch_metadata(package, &url)
+        .await
+        .map_err(|err| err.to_string())?;
+    metadata
+        .versions
+        .iter()
+        .filter_map(|version| version.source.as_ref())
+        .find(|source| source.source_type == "git")
+        .map(|source| source.url.clone())
+        .ok_or_else(|| format!("{} has no git source", package))
+}
+
+// serve 只为白名单扩展提供镜像
+fn is_mirrored(package: &str, white_list: &Vec<String>) -> bool {
+    is_valid_name(package) && check_package_in_white_list(package, white_list)
+}
+
+// 把元数据中 git 类型的 source.url 指向本服务的 /git/vendor/package.git，
+// 非白名单扩展不提供镜像，保留原地址
+pub fn rewrite_source_urls(body: &str, base_url: &str, white_list: &Vec<String>) -> Option<String> {
+    let mut root = serde_json::from_str::<Value>(body).ok()?;
+    for (package, versions) in root["packages"].as_object_mut()?.iter_mut() {
+        if !is_mirrored(package, white_list) {
+            continue;
+        }
+        let url = format!("{}/git/{}.git", base_url, package);
+        for version in versions.as_array_mut().into_iter().flatten() {
+            if let Some(source) = version.get_mut("source") {
+                if source.is_object() && source["type"] == "git" {
+                    source["url"] = Value::String(url.clone());
+                }
+            }
+        }
+    }
+    Some(root.to_string())
+}
+
+fn status_response(status: StatusCode, message: &str) -> Response {
+    (status, message.to_string()).into_response()
+}
+
+// git http-backend 的 CGI 输出：头部和正文之间为空行，读取到空行为止，正文留在 reader 中
+async fn read_cgi_head<R: AsyncBufRead + Unpin>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut head = Vec::new();
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line).await.ok()? == 0 {
+            return None;
+        }
+        if line == b"\r\n" || line == b"\n" {
+            return Some(head);
+        }
+        head.extend(line);
+    }
+}
+
+// 状态码在 Status 头中，其余为响应头
+fn parse_cgi_head(head: &[u8]) -> (StatusCode, HeaderMap) {
+    let mut status = StatusCode::OK;
+    let mut headers = HeaderMap::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        if name.eq_ignore_ascii_case("status") {
+            status = value
+                .split_whitespace()
+                .next()
+                .and_then(|code| code.parse().ok())
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .unwrap_or(StatusCode::BAD_GATEWAY);
+        } else if let (Ok(name), Ok(value)) =
+            (HeaderName::try_from(name), HeaderValue::try_from(value))
+        {
+            headers.append(name, value);
+        }
+    }
+    (status, headers)
+}
+
+// 只提供只读的 smart HTTP：GET info/refs?service=git-upload-pack 和 POST git-upload-pack
+pub async fn serve(
+    Path((vendor, repository, path)): Path<(String, String, String)>,
+    RawQuery(query): RawQuery,
+    method: Method,
+    headers: HeaderMap,
+    config: Extension<Config>,
+    body: Bytes,
+) -> Response {
+    let name = match repository.strip_suffix(".git") {
+        Some(name) => name,
+        None => return status_response(StatusCode::NOT_FOUND, "repository not found"),
+    };
+    let package = format!("{}/{}", vendor, name);
+    if config.git_mirror_url.is_none() || !is_mirrored(&package, &config.package_white_list) {
+        return status_response(StatusCode::NOT_FOUND, "repository not found");
+    }
+    let path = format!("/{}", path.trim_start_matches('/'));
+    let query = query.unwrap_or_default();
+    match (&method, path.as_str()) {
+        (&Method::GET, "/info/refs") if query == "service=git-upload-pack" => {}
+        (&Method::POST, "/git-upload-pack") => {}
+        _ => return status_response(StatusCode::FORBIDDEN, "only git-upload-pack is supported"),
+    }
+    // 客户端可能跳过 info/refs 直接 POST，例如镜像被清理后或使用 protocol v2 时
+    if let Err(err) = GitMirrors::global().ensure(&config, &package).await {
+        return status_response(StatusCode::BAD_GATEWAY, &err);
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut child = match Command::new("git")
+        .arg("http-backend")
+        .env("GIT_PROJECT_ROOT", &config.git_mirror_dir)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("PATH_INFO", format!("/{}.git{}", package, path))
+        .env("REQUEST_METHOD", method.as_str())
+        .env("QUERY_STRING", query)
+        .env("CONTENT_TYPE", header("content-type"))
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .env("HTTP_CONTENT_ENCODING", header("content-encoding"))
+        .env("GIT_PROTOCOL", header("git-protocol"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => return status_response(StatusCode::BAD_GATEWAY, &err.to_string()),
+    };
+
+    // 边写入请求体边读取输出，避免管道写满时互相等待
+    if let Some(mut stdin) = child.stdin.take() {
+        tokio::spawn(async move { stdin.write_all(&body).await });
+    }
+    let stderr = child.stderr.take().map(|mut stderr| {
+        tokio::spawn(async move {
+            let mut output = Vec::new();
+            let _ = stderr.read_to_end(&mut output).await;
+            output
+        })
+    });
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    let head = match read_cgi_head(&mut stdout).await {
+        Some(head) => head,
+        None => {
+            let _ = child.wait().await;
+            let message = match stderr {
+                Some(stderr) => String::from_utf8_lossy(&stderr.await.unwrap_or_default())
+                    .trim()
+                    .to_string(),
+                None => String::new(),
+            };
+            return status_response(
+                StatusCode::BAD_GATEWAY,
+                match message.is_empty() {
+                    true => "invalid git http-backend output",
+                    false => &message,
+                },
+            );
+        }
+    };
+    let (status, headers) = parse_cgi_head(&head);
+    // 客户端断开时 stdout 被关闭，git http-backend 随之退出
+    tokio::spawn(async move { child.wait().await });
+
+    // packfile 可能很大，边生成边返回，不在内存中缓存
+    let body = futures::stream::unfold(stdout, |mut stdout| async move {
+        let mut buffer = vec![0; 64 * 1024];
+        match stdout.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buffer.truncate(n);
+                Some((Ok(Bytes::from(buffer)), stdout))
+            }
+            Err(err) => Some((Err(err), stdout)),
+        }
+    });
+    (status, headers, StreamBody::new(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_source_urls_test() {
+        let body = r#"{"packages":{"acme/lib":[{"version":"v2.0.0","source":{"type":"git","url":"https://github.com/acme/lib.git","reference":"b"}},{"version":"v1.0.0"},{"version":"v0.1.0","source":{"type":"svn","url":"svn://example.com/lib","reference":"a"}}]},"minified":"composer/2.0"}"#;
+        let root = serde_json::from_str::<Value>(
+            &rewrite_source_urls(
+                body,
+                "https://mirror.example.com",
+                &vec!["acme/*".to_string()],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let versions = &root["packages"]["acme/lib"];
+        assert_eq!(
+            "https://mirror.example.com/git/acme/lib.git",
+            versions[0]["source"]["url"]
+        );
+        assert_eq!("b", versions[0]["source"]["reference"]);
+        assert!(versions[1].get("source").is_none());
+        assert_eq!("svn://example.com/lib", versions[2]["source"]["url"]);
+        assert_eq!("composer/2.0", root["minified"]);
+    }
+
+    #[test]
+    fn rewrite_whitelisted_source_urls_test() {
+        let body = r#"{"packages":{"other/lib":[{"version":"v1.0.0","source":{"type":"git","url":"https://github.com/other/lib.git","reference":"a"}}]}}"#;
+        let root = serde_json::from_str::<Value>(
+            &rewrite_source_urls(
+                body,
+                "https://mirror.example.com",
+                &vec!["acme/*".to_string()],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            "https://github.com/other/lib.git",
+            root["packages"]["other/lib"][0]["source"]["url"]
+        );
+    }
+
+    #[tokio::test]
+    async fn parse_cgi_output_test() {
+        let mut output: &[u8] =
+            b"Status: 403 Forbidden\r\nContent-Type: text/plain\r\n\r\nforbidden";
+        let (status, headers) = parse_cgi_head(&read_cgi_head(&mut output).await.unwrap());
+        assert_eq!(StatusCode::FORBIDDEN, status);
+        assert_eq!("text/plain", headers["content-type"]);
+        assert_eq!(b"forbidden", output);
+
+        let mut output: &[u8] =
+            b"Content-Type: application/x-git-upload-pack-advertisement\n\n001e";
+        let (status, _) = parse_cgi_head(&read_cgi_head(&mut output).await.unwrap());
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(b"001e", output);
+
+        let mut output: &[u8] = b"Content-Type: text/plain\r\n";
+        assert!(read_cgi_head(&mut output).await.is_none());
+    }
+}
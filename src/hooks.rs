@@ -0,0 +1,250 @@
+use axum::{
+    body::Bytes,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use glob::Pattern;
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::env;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::git_builder;
+use crate::lookup_error::NegativeCache;
+use crate::metadata_cache::MetadataCache;
+use crate::mirrors::packagist::Packagist;
+use crate::secret::constant_time_eq;
+use crate::watcher::{self, Release};
+use crate::Config;
+
+pub fn verify_github_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match signature
+        .strip_prefix("sha256=")
+        .and_then(|hex_signature| hex::decode(hex_signature).ok())
+    {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+pub fn verify_gitlab_token(expected: &str, token: &str) -> bool {
+    !expected.is_empty() && constant_time_eq(expected, token)
+}
+
+// 仓库名默认即为扩展名，不一致时通过 WEBHOOK_PACKAGE_MAP 指定，如 quansitech/think-core=tiderjian/think-core
+pub fn package_name(repository: &str) -> String {
+    let repository = repository.to_lowercase();
+    let mapped = env::var("WEBHOOK_PACKAGE_MAP")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|item| item.split_once('='))
+        .find(|(repo, _)| repo.trim().to_lowercase() == repository)
+        .map(|(_, package)| package.trim().to_string());
+
+    mapped.unwrap_or_else(|| {
+        // GitLab 的子群组路径只保留最后两段
+        let segments = repository.split('/').collect::<Vec<&str>>();
+        segments[segments.len().saturating_sub(2)..].join("/")
+    })
+}
+
+fn tag_name(git_ref: &str) -> Option<&str> {
+    git_ref.strip_prefix("refs/tags/")
+}
+
+// packagist 收到推送后需要一段时间才会更新元数据，新标签未出现时间隔重试
+async fn refresh_package(config: Config, package: String, tag: Option<String>) {
+    let cache = MetadataCache::global();
+    let url = Packagist::new(&config).get_package_meta_url(&package);
+    NegativeCache::global().purge(&Pattern::new(&Pattern::escape(&package)).unwrap());
+
+    let attempts = env::var("WEBHOOK_REFRESH_ATTEMPTS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(5);
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            sleep(Duration::from_secs(30)).await;
+        }
+        let metadata = match cache.refresh(&package, &url).await {
+            Ok(body) => match cache.index(&package, &body) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            },
+            Err(_) => continue,
+        };
+        let tag = match &tag {
+            Some(tag) => tag,
+            None => return,
+        };
+        let release = metadata.version(tag).and_then(Release::from_version);
+        if let Some(release) = release {
+            watcher::prefetch(config, &package, &release).await;
+            return;
+        }
+    }
+    eprintln!("webhook refresh of {} did not find {:?}", package, tag);
+}
+
+fn accept(config: Config, repository: &str, tag: Option<&str>) -> Response {
+    // 自建仓库直接重新构建，不经过 packagist
+    if let Some(url) = git_builder::find_repository(&config, repository) {
+        tokio::spawn({
+            let url = url.clone();
+            async move {
+                if let Err(err) = git_builder::build(&config, &url).await {
+                    eprintln!("failed to build {}: {}", url, err);
+                }
+            }
+        });
+        return (StatusCode::ACCEPTED, Json(json!({ "repository": url }))).into_response();
+    }
+
+    let package = package_name(repository);
+    tokio::spawn(refresh_package(
+        config,
+        package.clone(),
+        tag.map(|tag| tag.to_string()),
+    ));
+
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "package": package, "tag": tag })),
+    )
+        .into_response()
+}
+
+fn reject(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+pub async fn github(headers: HeaderMap, config: Extension<Config>, body: Bytes) -> Response {
+    let secret = match env::var("GITHUB_WEBHOOK_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret,
+        _ => return reject(StatusCode::NOT_FOUND, "github webhook is not enabled"),
+    };
+    let signature = headers
+        .get("x-hub-signature-256")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !verify_github_signature(&secret, &body, signature) {
+        return reject(StatusCode::UNAUTHORIZED, "invalid signature");
+    }
+
+    let payload = match serde_json::from_slice::<Value>(&body) {
+        Ok(payload) => payload,
+        Err(_) => return reject(StatusCode::BAD_REQUEST, "invalid payload"),
+    };
+    let event = headers
+        .get("x-github-event")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let repository = payload["repository"]["full_name"].as_str().unwrap_or_default();
+    if repository.is_empty() {
+        return reject(StatusCode::BAD_REQUEST, "repository not found in payload");
+    }
+
+    let tag = match event {
+        "ping" => return (StatusCode::OK, Json(json!({ "pong": true }))).into_response(),
+        "push" => payload["ref"].as_str().and_then(tag_name),
+        "create" if payload["ref_type"] == "tag" => payload["ref"].as_str(),
+        "release" => payload["release"]["tag_name"].as_str(),
+        _ => None,
+    };
+    accept(config.0, repository, tag)
+}
+
+pub async fn gitlab(headers: HeaderMap, config: Extension<Config>, body: Bytes) -> Response {
+    let expected = match env::var("GITLAB_WEBHOOK_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return reject(StatusCode::NOT_FOUND, "gitlab webhook is not enabled"),
+    };
+    let token = headers
+        .get("x-gitlab-token")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !verify_gitlab_token(&expected, token) {
+        return reject(StatusCode::UNAUTHORIZED, "invalid token");
+    }
+
+    let payload = match serde_json::from_slice::<Value>(&body) {
+        Ok(payload) => payload,
+        Err(_) => return reject(StatusCode::BAD_REQUEST, "invalid payload"),
+    };
+    let repository = payload["project"]["path_with_namespace"]
+        .as_str()
+        .unwrap_or_default();
+    if repository.is_empty() {
+        return reject(StatusCode::BAD_REQUEST, "project not found in payload");
+    }
+
+    let tag = payload["ref"].as_str().and_then(tag_name);
+    accept(config.0, repository, tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GITHUB_SECRET: &str = "It's a Secret to Everybody";
+    const GITHUB_PUSH_PAYLOAD: &str = r#"{"ref":"refs/tags/v12.31.0","before":"0000000000000000000000000000000000000000","after":"8f0c2a1e5d3b4a6c7e9f0a1b2c3d4e5f60718293","created":true,"deleted":false,"repository":{"id":123456,"name":"think-core","full_name":"quansitech/think-core","private":false,"html_url":"https://github.com/quansitech/think-core"},"pusher":{"name":"tiderjian"}}"#;
+    const GITHUB_PUSH_SIGNATURE: &str =
+        "sha256=3a37ffb1412ab0fd3f6f2fa90cda1809a72c5ea0f01c6e4a80cf736d501fc095";
+
+    #[test]
+    fn github_signature_test() {
+        // GitHub 文档中的示例
+        assert!(verify_github_signature(
+            GITHUB_SECRET,
+            b"Hello, World!",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        ));
+
+        assert!(verify_github_signature(
+            GITHUB_SECRET,
+            GITHUB_PUSH_PAYLOAD.as_bytes(),
+            GITHUB_PUSH_SIGNATURE
+        ));
+        assert!(!verify_github_signature(
+            "another secret",
+            GITHUB_PUSH_PAYLOAD.as_bytes(),
+            GITHUB_PUSH_SIGNATURE
+        ));
+        assert!(!verify_github_signature(
+            GITHUB_SECRET,
+            GITHUB_PUSH_PAYLOAD.replace("v12.31.0", "v12.32.0").as_bytes(),
+            GITHUB_PUSH_SIGNATURE
+        ));
+        assert!(!verify_github_signature(
+            GITHUB_SECRET,
+            GITHUB_PUSH_PAYLOAD.as_bytes(),
+            "sha1=3a37ffb1412ab0fd3f6f2fa90cda1809a72c5ea0"
+        ));
+    }
+
+    #[test]
+    fn gitlab_token_test() {
+        assert!(verify_gitlab_token("glwt-secret", "glwt-secret"));
+        assert!(!verify_gitlab_token("glwt-secret", "glwt-secreT"));
+        assert!(!verify_gitlab_token("glwt-secret", ""));
+    }
+
+    #[test]
+    fn package_name_test() {
+        let payload = serde_json::from_str::<Value>(GITHUB_PUSH_PAYLOAD).unwrap();
+        assert_eq!(
+            "quansitech/think-core",
+            package_name(payload["repository"]["full_name"].as_str().unwrap())
+        );
+        assert_eq!(Some("v12.31.0"), tag_name(payload["ref"].as_str().unwrap()));
+        assert_eq!("quansitech/send-msg", package_name("Group/Sub/quansitech/Send-Msg"));
+    }
+}
@@ -0,0 +1,559 @@
+use axum::{
+    body::Bytes,
+    extract::Query,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use glob::Pattern;
+use reqwest::StatusCode;
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::env;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::auth::Credentials;
+use crate::dist::Dist;
+use crate::lookup_error::{LookupError, NegativeCache};
+use crate::metadata::PackageMetadata;
+use crate::package::Package;
+use crate::request_helper;
+use crate::Config;
+
+// 只在根项目中生效的字段，不写入元数据
+const ROOT_ONLY_KEYS: [&str; 4] = [
+    "config",
+    "repositories",
+    "minimum-stability",
+    "prefer-stable",
+];
+
+#[derive(Debug)]
+pub enum PublishError {
+    Invalid(String),
+    Forbidden(String),
+    Storage(String),
+}
+
+impl PublishError {
+    pub fn into_response(self) -> Response {
+        let (status, message) = match self {
+            PublishError::Invalid(message) => (StatusCode::BAD_REQUEST, message),
+            PublishError::Forbidden(message) => (StatusCode::FORBIDDEN, message),
+            PublishError::Storage(message) => (StatusCode::BAD_GATEWAY, message),
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+impl std::fmt::Display for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublishError::Invalid(message)
+            | PublishError::Forbidden(message)
+            | PublishError::Storage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// 上传的 dist 压缩包，composer.json 可以在根目录，也可以在唯一的顶层目录下（如 GitHub 的 zipball）
+pub struct Archive {
+    pub name: String,
+    pub composer: Map<String, Value>,
+    pub shasum: String,
+    pub bytes: Vec<u8>,
+}
+
+impl Archive {
+    pub fn read(bytes: Vec<u8>) -> Result<Self, PublishError> {
+        let invalid = |message: String| PublishError::Invalid(message);
+        let mut zip = zip::ZipArchive::new(Cursor::new(&bytes))
+            .map_err(|err| invalid(format!("invalid zip archive: {}", err)))?;
+        let path = zip
+            .file_names()
+            .filter(|name| *name == "composer.json" || name.ends_with("/composer.json"))
+            .filter(|name| name.matches('/').count() <= 1)
+            .min_by_key(|name| name.matches('/').count())
+            .map(|name| name.to_string())
+            .ok_or_else(|| invalid("composer.json not found in archive".to_string()))?;
+
+        let mut content = String::new();
+        zip.by_name(&path)
+            .map_err(|err| invalid(err.to_string()))?
+            .read_to_string(&mut content)
+            .map_err(|err| invalid(format!("failed to read {}: {}", path, err)))?;
+        let composer = match serde_json::from_str::<Value>(&content) {
+            Ok(Value::Object(composer)) => composer,
+            _ => return Err(invalid(format!("{} is not a valid json object", path))),
+        };
+        let name = composer
+            .get("name")
+            .and_then(|name| name.as_str())
+            .filter(|name| is_valid_name(name))
+            .ok_or_else(|| invalid("composer.json has no valid package name".to_string()))?
+            .to_lowercase();
+
+        Ok(Self {
+            name,
+            composer,
+            shasum: hex::encode(Sha1::digest(&bytes)),
+            bytes,
+        })
+    }
+}
+
+// 扩展名会用作目录名，只允许 composer 规定的字符
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let parts = name.split('/').collect::<Vec<&str>>();
+    parts.len() == 2
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && !part.starts_with('.')
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        })
+}
+
+// 按 composer 的规则规范化版本号，如 v1.2 => 1.2.0.0，1.0.0-beta.2 => 1.0.0.0-beta2
+pub fn normalize_version(version: &str) -> Option<String> {
+    let version = version.trim_start_matches(['v', 'V']);
+    let (numbers, modifier) = match version.split_once('-') {
+        Some((numbers, modifier)) => (numbers, Some(modifier)),
+        None => (version, None),
+    };
+    let mut parts = numbers
+        .split('.')
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+    if parts.len() > 4
+        || parts
+            .iter()
+            .any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+    parts.resize(4, "0".to_string());
+    let normalized = parts.join(".");
+
+    let modifier = match modifier {
+        Some(modifier) => modifier.to_lowercase(),
+        None => return Some(normalized),
+    };
+    let split = modifier
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(modifier.len());
+    let (stability, number) = modifier.split_at(split);
+    let number = number.trim_start_matches(['.', '-']);
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let stability = match stability {
+        "alpha" | "a" => "alpha",
+        "beta" | "b" => "beta",
+        "rc" => "RC",
+        "patch" | "pl" | "p" => "patch",
+        _ => return None,
+    };
+    Some(format!("{}-{}{}", normalized, stability, number))
+}
+
+// 上传的私有扩展，每个扩展的元数据以 p2 格式保存在 HOSTED_PACKAGES_DIR/vendor/package.json
+pub struct HostedPackages {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl HostedPackages {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(env::var("HOSTED_PACKAGES_DIR").unwrap_or_else(|_| "./hosted".to_string()))
+    }
+
+    fn path(&self, package: &str) -> Option<PathBuf> {
+        is_valid_name(package).then(|| self.dir.join(format!("{}.json", package)))
+    }
+
+    // 未托管的扩展返回 None
+    pub async fn metadata(&self, package: &str) -> Option<String> {
+        fs::read_to_string(self.path(package)?).await.ok()
+    }
+
+    // 托管扩展的 dist 在自有存储中的对象名，未托管的扩展返回 None
+    pub async fn dist_object(
+        &self,
+        package: &str,
+        version: &str,
+        reference: &str,
+    ) -> Option<Result<String, LookupError>> {
+        let body = self.metadata(package).await?;
+        let object_name = PackageMetadata::parse(package, &body)
+            .ok()
+            .as_ref()
+            // 分支和标签可能指向同一提交，先按版本查找
+            .and_then(|metadata| {
+                metadata
+                    .version(version)
+                    .or_else(|| metadata.reference(reference))
+            })
+            .and_then(|found| {
+                let dist = found.dist.as_ref()?;
+                let (vendor, name) = package.split_once('/')?;
+                let package = Package::new(vendor, name);
+                Some(
+                    Dist::new(
+                        &package,
+                        &found.version,
+                        dist.reference.as_deref()?,
+                        &dist.dist_type,
+                    )
+                    .object_name(),
+                )
+            });
+        Some(object_name.ok_or_else(|| LookupError::VersionNotFound {
+            package: package.to_string(),
+            version: version.to_string(),
+            consulted: Vec::new(),
+        }))
+    }
+
+    pub(crate) async fn versions(&self, package: &str) -> Result<Vec<Value>, String> {
+        let body = match self.metadata(package).await {
+            Some(body) => body,
+            None => return Ok(Vec::new()),
+        };
+        let mut root = serde_json::from_str::<Value>(&body)
+            .map_err(|err| format!("failed to parse metadata of {}: {}", package, err))?;
+        match root["packages"][package].take() {
+            Value::Array(versions) => Ok(versions),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    async fn write(&self, package: &str, versions: Vec<Value>) -> Result<(), String> {
+        let path = self
+            .path(package)
+            .ok_or_else(|| format!("invalid package name: {}", package))?;
+        let body = json!({ "packages": { package: versions } }).to_string();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, body).await.map_err(|err| err.to_string())?;
+        fs::rename(&tmp, &path).await.map_err(|err| err.to_string())
+    }
+
+    // 写入新版本，同一版本重复发布时覆盖
+    pub async fn add_version(&self, package: &str, entry: Value) -> Result<(), String> {
+        let _guard = self.lock.lock().await;
+        let mut versions = self.versions(package).await?;
+        versions.retain(|version| version["version"] != entry["version"]);
+        versions.insert(0, entry);
+        self.write(package, versions).await
+    }
+
+    // 托管的全部扩展及其元数据的修改时间
+    pub async fn packages(&self) -> Vec<(String, SystemTime)> {
+        let mut packages = Vec::new();
+        let mut vendors = match fs::read_dir(&self.dir).await {
+            Ok(vendors) => vendors,
+            Err(_) => return packages,
+        };
+        while let Ok(Some(vendor)) = vendors.next_entry().await {
+            let mut files = match fs::read_dir(vendor.path()).await {
+                Ok(files) => files,
+                Err(_) => continue,
+            };
+            while let Ok(Some(file)) = files.next_entry().await {
+                let file_name = file.file_name().to_string_lossy().to_string();
+                let name = match file_name.strip_suffix(".json") {
+                    Some(name) => format!("{}/{}", vendor.file_name().to_string_lossy(), name),
+                    None => continue,
+                };
+                if let Ok(modified) = file.metadata().await.and_then(|m| m.modified()) {
+                    packages.push((name, modified));
+                }
+            }
+        }
+        packages
+    }
+
+    // 替换全部版本，仓库构建时使用
+    pub async fn set_versions(&self, package: &str, versions: Vec<Value>) -> Result<(), String> {
+        let _guard = self.lock.lock().await;
+        self.write(package, versions).await
+    }
+}
+
+// 托管扩展的 dist 地址指向本服务的 /dists 路由，下载时同样经过令牌检查，不暴露自有存储的地址
+pub(crate) fn dist_path(dist: &Dist) -> String {
+    format!(
+        "/dists/{}/{}/{}.{}",
+        dist.package.full_name, dist.version, dist.reference, dist.dist_type
+    )
+}
+
+// 返回元数据时按请求补全 dist 地址的前缀，同时替换旧版本中保存的自有存储地址
+pub(crate) fn rewrite_dist_urls(body: &str, base_url: &str) -> Option<String> {
+    let mut root = serde_json::from_str::<Value>(body).ok()?;
+    for (package, versions) in root["packages"].as_object_mut()?.iter_mut() {
+        let (vendor, name) = package.split_once('/')?;
+        let package = Package::new(vendor, name);
+        for version in versions.as_array_mut().into_iter().flatten() {
+            let path = match (
+                version["version"].as_str(),
+                version["dist"]["reference"].as_str(),
+                version["dist"]["type"].as_str(),
+            ) {
+                (Some(number), Some(reference), Some(dist_type)) => {
+                    dist_path(&Dist::new(&package, number, reference, dist_type))
+                }
+                _ => continue,
+            };
+            version["dist"]["url"] = json!(format!("{}{}", base_url, path));
+        }
+    }
+    Some(root.to_string())
+}
+
+// 自有存储支持签名时跳转到短期有效的签名地址，否则由本服务转发对象内容
+pub async fn dist_response(config: &Config, object_name: &str) -> Response {
+    let storage = match &config.storage {
+        Some(storage) => storage,
+        None => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "error": "storage is not configured" })),
+            )
+                .into_response()
+        }
+    };
+    if let Some(url) = storage.signed_url(object_name, Duration::from_secs(300)) {
+        return request_helper::redirect(&url);
+    }
+    match request_helper::try_get(&storage.url(object_name)).await {
+        Ok(response) => request_helper::stream_response(response),
+        Err(err) => (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+// 把 composer.json 转换为元数据中的一个版本
+pub(crate) fn version_entry(
+    mut composer: Map<String, Value>,
+    name: &str,
+    version: &str,
+    version_normalized: &str,
+) -> Map<String, Value> {
+    for key in ROOT_ONLY_KEYS {
+        composer.remove(key);
+    }
+    composer.insert("name".to_string(), json!(name));
+    composer.insert("version".to_string(), json!(version));
+    composer.insert("version_normalized".to_string(), json!(version_normalized));
+    composer
+}
+
+// 上传到自有存储并写入元数据，返回新版本的元数据
+pub async fn publish(
+    config: &Config,
+    archive: Archive,
+    version: Option<&str>,
+) -> Result<Value, PublishError> {
+    if !crate::check_package_in_white_list(&archive.name, &config.private_packages) {
+        return Err(PublishError::Forbidden(format!(
+            "{} is not listed in PRIVATE_PACKAGES",
+            archive.name
+        )));
+    }
+    let storage = config
+        .storage
+        .as_ref()
+        .ok_or_else(|| PublishError::Invalid("storage is not configured".to_string()))?;
+    let version = version
+        .or_else(|| archive.composer.get("version").and_then(|v| v.as_str()))
+        .ok_or_else(|| PublishError::Invalid("version is required".to_string()))?
+        .to_string();
+    let version_normalized = normalize_version(&version)
+        .ok_or_else(|| PublishError::Invalid(format!("invalid version: {}", version)))?;
+
+    let (vendor, name) = archive.name.split_once('/').unwrap();
+    let package = Package::new(vendor, name);
+    let dist = Dist::new(&package, &version, &archive.shasum, "zip");
+    let object_name = dist.object_name();
+    storage
+        .put(&object_name, archive.bytes)
+        .await
+        .map_err(PublishError::Storage)?;
+
+    let mut entry = version_entry(
+        archive.composer,
+        &archive.name,
+        &version,
+        &version_normalized,
+    );
+    entry.insert(
+        "dist".to_string(),
+        json!({
+            "type": "zip",
+            "url": dist_path(&dist),
+            "reference": archive.shasum,
+            "shasum": archive.shasum,
+        }),
+    );
+    entry.insert("time".to_string(), json!(chrono::Utc::now().to_rfc3339()));
+    let entry = Value::Object(entry);
+
+    config
+        .hosted
+        .add_version(&archive.name, entry.clone())
+        .await
+        .map_err(PublishError::Storage)?;
+    NegativeCache::global().purge(&Pattern::new(&Pattern::escape(&archive.name)).unwrap());
+    if let Some(index) = &config.package_index {
+        index.invalidate_local();
+    }
+    Ok(entry)
+}
+
+// POST /upload?version=1.0.0，请求体为 zip，令牌需要能访问该扩展
+pub async fn upload(
+    Extension(config): Extension<Config>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let token = match Credentials::from_headers(&headers)
+        .and_then(|credentials| config.tokens.authenticate(&credentials))
+    {
+        Some(token) => token,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "authentication required" })),
+            )
+                .into_response()
+        }
+    };
+    let archive = match Archive::read(body.to_vec()) {
+        Ok(archive) => archive,
+        Err(err) => return err.into_response(),
+    };
+    if !token.allows(&archive.name) {
+        return PublishError::Forbidden(format!(
+            "token is not allowed to publish {}",
+            archive.name
+        ))
+        .into_response();
+    }
+
+    match publish(&config, archive, params.get("version").map(|s| s.as_str())).await {
+        Ok(entry) => (StatusCode::CREATED, Json(entry)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+// upload 子命令：composer_mirror upload <zip> [version]
+pub async fn run(args: &[String], config: Config) {
+    let (path, version) = match args {
+        [path] => (path, None),
+        [path, version] => (path, Some(version.as_str())),
+        _ => {
+            eprintln!("usage: composer_mirror upload <zip> [version]");
+            return;
+        }
+    };
+    let bytes = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("failed to read {}: {}", path, err);
+            return;
+        }
+    };
+
+    match Archive::read(bytes) {
+        Ok(archive) => match publish(&config, archive, version).await {
+            Ok(entry) => println!("published {} {}", entry["name"], entry["version"]),
+            Err(err) => eprintln!("{}", err),
+        },
+        Err(err) => eprintln!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn normalize_version_test() {
+        assert_eq!(Some("1.2.0.0".to_string()), normalize_version("v1.2"));
+        assert_eq!(Some("1.0.3.0".to_string()), normalize_version("1.0.3"));
+        assert_eq!(
+            Some("1.0.0.0-beta2".to_string()),
+            normalize_version("1.0.0-beta.2")
+        );
+        assert_eq!(
+            Some("2.0.0.0-RC1".to_string()),
+            normalize_version("2.0.0-RC1")
+        );
+        assert_eq!(None, normalize_version("dev-master"));
+        assert_eq!(None, normalize_version("1.0.0-foo"));
+    }
+
+    #[test]
+    fn archive_test() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("acme-lib-abc/composer.json", FileOptions::default())
+            .unwrap();
+        zip.write_all(br#"{"name":"Acme/Lib","require":{"php":">=8.0"}}"#)
+            .unwrap();
+        zip.start_file(
+            "acme-lib-abc/vendor/x/composer.json",
+            FileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(b"{}").unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let archive = Archive::read(bytes.clone()).unwrap();
+        assert_eq!("acme/lib", archive.name);
+        assert_eq!(">=8.0", archive.composer["require"]["php"]);
+        assert_eq!(hex::encode(Sha1::digest(&bytes)), archive.shasum);
+
+        assert!(Archive::read(b"not a zip".to_vec()).is_err());
+        assert!(!is_valid_name("../etc"));
+        assert!(!is_valid_name("acme/../lib"));
+    }
+
+    #[test]
+    fn rewrite_dist_urls_test() {
+        // 旧版本保存的是自有存储的地址，同样改为 /dists 路由
+        let body = r#"{"packages":{"acme/lib":[{"version":"v1.0.0","dist":{"type":"zip","url":"https://cdn.example.com/acme/lib/v1.0.0/abc.zip","reference":"abc"}}]}}"#;
+        let root = serde_json::from_str::<Value>(
+            &rewrite_dist_urls(body, "https://mirror.example.com").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            "https://mirror.example.com/dists/acme/lib/v1.0.0/abc.zip",
+            root["packages"]["acme/lib"][0]["dist"]["url"]
+        );
+    }
+}
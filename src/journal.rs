@@ -0,0 +1,478 @@
+use axum::{
+    body::{boxed, BoxBody, Bytes, HttpBody},
+    extract::ConnectInfo,
+    http::{header::CONTENT_LENGTH, HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Local};
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static JOURNAL: OnceLock<Option<SyncSender<Value>>> = OnceLock::new();
+static TRUSTED_PROXIES: OnceLock<Vec<IpAddr>> = OnceLock::new();
+
+// 等待写入的日志条数上限，写入跟不上时丢弃新的日志而不是阻塞请求
+const JOURNAL_QUEUE_SIZE: usize = 10000;
+
+// 路由处理函数通过响应扩展告知中间件本次请求的路由决策，
+// 用于请求日志和监控指标
+#[derive(Clone, Copy)]
+pub struct RouteDecision(pub &'static str);
+
+// 策略1 本次请求中把 dist 上传到了自有存储
+#[derive(Clone, Copy)]
+pub struct StorageUploaded;
+
+struct JournalFile {
+    file: File,
+    size: u64,
+    opened_at: DateTime<Local>,
+}
+
+pub struct Journal {
+    path: PathBuf,
+    max_bytes: u64,
+    rotate_interval: Option<Duration>,
+    max_files: usize,
+    current: Option<JournalFile>,
+}
+
+impl Journal {
+    // 文件读写都是阻塞的，由单独的线程按顺序写入，请求处理中只把日志放入队列
+    fn global() -> Option<&'static SyncSender<Value>> {
+        JOURNAL
+            .get_or_init(|| Journal::from_env().map(Journal::spawn))
+            .as_ref()
+    }
+
+    fn spawn(mut self) -> SyncSender<Value> {
+        let (sender, receiver) = mpsc::sync_channel::<Value>(JOURNAL_QUEUE_SIZE);
+        thread::Builder::new()
+            .name("journal".to_string())
+            .spawn(move || {
+                for record in receiver {
+                    if let Err(err) = self.write(&record) {
+                        eprintln!("failed to write request journal: {}", err);
+                    }
+                }
+            })
+            .unwrap();
+        sender
+    }
+
+    fn from_env() -> Option<Self> {
+        let path = env::var("JOURNAL_PATH").ok().filter(|s| !s.is_empty())?;
+        let env_or = |key: &str, default: u64| {
+            env::var(key)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default)
+        };
+
+        Some(Self::new(
+            PathBuf::from(path),
+            env_or("JOURNAL_MAX_BYTES", 100 * 1024 * 1024),
+            match env_or("JOURNAL_ROTATE_SECS", 86400) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            env_or("JOURNAL_MAX_FILES", 7) as usize,
+        ))
+    }
+
+    pub fn new(
+        path: PathBuf,
+        max_bytes: u64,
+        rotate_interval: Option<Duration>,
+        max_files: usize,
+    ) -> Self {
+        Self {
+            path,
+            max_bytes,
+            rotate_interval,
+            max_files,
+            current: None,
+        }
+    }
+
+    fn open(&self) -> std::io::Result<JournalFile> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // 重启后沿用已有文件的创建时间，取不到时用修改时间，避免按时间轮转被重启推迟
+        let metadata = file.metadata()?;
+        let opened_at = match metadata.len() {
+            0 => Local::now(),
+            _ => metadata
+                .created()
+                .or_else(|_| metadata.modified())
+                .map(DateTime::<Local>::from)
+                .unwrap_or_else(|_| Local::now()),
+        };
+        Ok(JournalFile {
+            file,
+            size: metadata.len(),
+            opened_at,
+        })
+    }
+
+    fn should_rotate(&self, current: &JournalFile, incoming: u64) -> bool {
+        if current.size > 0 && current.size + incoming > self.max_bytes {
+            return true;
+        }
+        match self.rotate_interval {
+            Some(interval) => {
+                current.size > 0
+                    && (Local::now() - current.opened_at)
+                        .to_std()
+                        .map(|elapsed| elapsed >= interval)
+                        .unwrap_or(false)
+            }
+            None => false,
+        }
+    }
+
+    fn rotated_files(&self) -> Vec<PathBuf> {
+        let prefix = format!("{}.", self.path.file_name().unwrap().to_string_lossy());
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let mut files = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.file_name()
+                            .map(|name| name.to_string_lossy().starts_with(&prefix))
+                            .unwrap_or(false)
+                    })
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", Local::now().format("%Y%m%d%H%M%S%3f")));
+        fs::rename(&self.path, rotated)?;
+
+        let files = self.rotated_files();
+        if files.len() > self.max_files {
+            for file in &files[..files.len() - self.max_files] {
+                fs::remove_file(file)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, record: &Value) -> std::io::Result<()> {
+        let mut line = record.to_string();
+        line.push('\n');
+
+        if let Some(file) = self.current.as_ref() {
+            if self.should_rotate(file, line.len() as u64) {
+                self.current = None;
+                self.rotate()?;
+            }
+        }
+        if self.current.is_none() {
+            self.current = Some(self.open()?);
+        }
+
+        let file = self.current.as_mut().unwrap();
+        file.file.write_all(line.as_bytes())?;
+        file.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+// TRUSTED_PROXIES 中的反向代理，只有来自这些地址的请求才采用 x-forwarded-for、x-real-ip
+fn trusted_proxies() -> &'static [IpAddr] {
+    TRUSTED_PROXIES.get_or_init(|| {
+        env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect()
+    })
+}
+
+// 请求是否来自 TRUSTED_PROXIES 中的反向代理
+pub(crate) fn is_trusted_proxy(connect_info: Option<&ConnectInfo<SocketAddr>>) -> bool {
+    connect_info.is_some_and(|ConnectInfo(addr)| trusted_proxies().contains(&addr.ip()))
+}
+
+pub(crate) fn client_ip(headers: &HeaderMap, connect_info: Option<&ConnectInfo<SocketAddr>>) -> Option<String> {
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip())?;
+    Some(resolve_client_ip(headers, peer, trusted_proxies()).to_string())
+}
+
+// x-forwarded-for 从右往左跳过可信代理，第一个不可信的地址即客户端地址
+fn resolve_client_ip(headers: &HeaderMap, peer: IpAddr, trusted: &[IpAddr]) -> IpAddr {
+    if !trusted.contains(&peer) {
+        return peer;
+    }
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let forwarded = header("x-forwarded-for")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect::<Vec<IpAddr>>();
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !trusted.contains(ip))
+        .or_else(|| forwarded.first())
+        .copied()
+        .or_else(|| header("x-real-ip").and_then(|ip| ip.trim().parse().ok()))
+        .unwrap_or(peer)
+}
+
+// 统计实际发送的响应体字节数，响应体发送完或连接中断被丢弃时再写入日志
+struct CountingBody {
+    inner: BoxBody,
+    bytes: u64,
+    record: Option<Value>,
+    journal: SyncSender<Value>,
+}
+
+impl HttpBody for CountingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Ok(data))) = &poll {
+            self.bytes += data.len() as u64;
+        }
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+impl Drop for CountingBody {
+    fn drop(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            record["bytes"] = json!(self.bytes);
+            if let Err(TrySendError::Full(_)) = self.journal.try_send(record) {
+                eprintln!("request journal queue is full, record dropped");
+            }
+        }
+    }
+}
+
+// 从路径中解析出 package、version、reference
+fn parse_path(path: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let segments = path.trim_start_matches('/').split('/').collect::<Vec<&str>>();
+    match segments.as_slice() {
+        ["p2", vendor, package] => (
+            Some(format!("{}/{}", vendor, package.trim_end_matches(".json"))),
+            None,
+            None,
+        ),
+        ["dists", vendor, package, version, reference_and_type] => (
+            Some(format!("{}/{}", vendor, package)),
+            Some(version.to_string()),
+            reference_and_type
+                .split('.')
+                .next()
+                .map(|reference| reference.to_string()),
+        ),
+        _ => (None, None, None),
+    }
+}
+
+pub async fn record<B>(request: Request<B>, next: Next<B>) -> Response {
+    let journal = match Journal::global() {
+        Some(journal) => journal,
+        None => return next.run(request).await,
+    };
+
+    let start = Instant::now();
+    let timestamp = Local::now().to_rfc3339();
+    let path = request.uri().path().to_string();
+    let ip = client_ip(
+        request.headers(),
+        request.extensions().get::<ConnectInfo<SocketAddr>>(),
+    );
+
+    let mut response = next.run(request).await;
+
+    let (package, version, reference) = parse_path(&path);
+    let decision = response
+        .extensions()
+        .get::<RouteDecision>()
+        .map(|RouteDecision(decision)| *decision);
+    let upstream = response
+        .headers()
+        .get("location")
+        .and_then(|value| value.to_str().ok());
+
+    let record = json!({
+        "timestamp": timestamp,
+        "client_ip": ip,
+        "route": path,
+        "package": package,
+        "version": version,
+        "reference": reference,
+        "decision": decision,
+        "upstream": upstream,
+        "uploaded": response.extensions().get::<StorageUploaded>().is_some(),
+        "status": response.status().as_u16(),
+        "bytes": 0,
+        "latency_ms": start.elapsed().as_millis() as u64,
+    });
+
+    // 包装后的响应体大小未知，原本确定的大小改由 Content-Length 告知 hyper
+    if let Some(length) = response
+        .body()
+        .size_hint()
+        .exact()
+        .filter(|length| *length > 0)
+    {
+        response
+            .headers_mut()
+            .entry(CONTENT_LENGTH)
+            .or_insert_with(|| HeaderValue::from(length));
+    }
+    response.map(|inner| {
+        boxed(CountingBody {
+            inner,
+            bytes: 0,
+            record: Some(record),
+            journal: journal.clone(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_path_test() {
+        assert_eq!(
+            (
+                Some("tiderjian/think-core".to_string()),
+                Some("v12.30.0".to_string()),
+                Some("35c34ca5af137fa28b151de5b0d839d51c4a1fa9".to_string())
+            ),
+            parse_path("/dists/tiderjian/think-core/v12.30.0/35c34ca5af137fa28b151de5b0d839d51c4a1fa9.zip")
+        );
+        assert_eq!(
+            (Some("nesbot/carbon".to_string()), None, None),
+            parse_path("/p2/nesbot/carbon.json")
+        );
+        assert_eq!((None, None, None), parse_path("/packages.json"));
+    }
+
+    #[test]
+    fn resolve_client_ip_test() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2, 10.0.0.2".parse().unwrap());
+        headers.insert("x-real-ip", "3.3.3.3".parse().unwrap());
+
+        // 不可信的来源伪造的请求头被忽略
+        assert_eq!(ip("8.8.8.8"), resolve_client_ip(&headers, ip("8.8.8.8"), &trusted));
+        assert_eq!(ip("10.0.0.1"), resolve_client_ip(&headers, ip("10.0.0.1"), &[]));
+
+        // 客户端可以在 x-forwarded-for 前面任意添加地址，只取最后一个不可信的地址
+        assert_eq!(ip("2.2.2.2"), resolve_client_ip(&headers, ip("10.0.0.1"), &trusted));
+
+        headers.remove("x-forwarded-for");
+        assert_eq!(ip("3.3.3.3"), resolve_client_ip(&headers, ip("10.0.0.1"), &trusted));
+        headers.remove("x-real-ip");
+        assert_eq!(ip("10.0.0.1"), resolve_client_ip(&headers, ip("10.0.0.1"), &trusted));
+    }
+
+    #[tokio::test]
+    async fn counting_body_test() {
+        let chunks = vec![Ok::<_, std::io::Error>("abc"), Ok("defg")];
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let mut body = CountingBody {
+            inner: boxed(axum::body::StreamBody::new(futures::stream::iter(chunks))),
+            bytes: 0,
+            record: Some(json!({ "route": "/p2/nesbot/carbon.json" })),
+            journal: sender,
+        };
+        // 流式响应没有确定的大小，按实际发送的字节数记录
+        while let Some(chunk) = body.data().await {
+            chunk.unwrap();
+        }
+        assert!(receiver.try_recv().is_err());
+
+        drop(body);
+        assert_eq!(json!(7), receiver.try_recv().unwrap()["bytes"]);
+    }
+
+    #[test]
+    fn journal_reopen_keeps_opened_at_test() {
+        let dir = env::temp_dir().join(format!("composer_mirror_journal_reopen_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut journal = Journal::new(dir.join("journal.jsonl"), 1024, None, 2);
+        journal.write(&json!({ "route": "/p2/nesbot/carbon.json" })).unwrap();
+        let opened_at = journal.current.as_ref().unwrap().opened_at;
+        std::thread::sleep(Duration::from_millis(20));
+
+        // 重启后重新打开已有文件，不重新开始计时
+        let journal = Journal::new(dir.join("journal.jsonl"), 1024, None, 2);
+        let reopened = journal.open().unwrap();
+        assert!(reopened.opened_at - opened_at < chrono::Duration::milliseconds(20));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn journal_rotate_test() {
+        let dir = env::temp_dir().join(format!("composer_mirror_journal_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut journal = Journal::new(dir.join("journal.jsonl"), 64, None, 2);
+
+        for i in 0..10 {
+            journal.write(&json!({ "i": i, "route": "/p2/nesbot/carbon.json" })).unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        let content = fs::read_to_string(dir.join("journal.jsonl")).unwrap();
+        assert_eq!(1, content.lines().count());
+        assert_eq!(2, journal.rotated_files().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,38 @@
+use chrono::Local;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+static LAST_ERRORS: OnceLock<LastErrors> = OnceLock::new();
+
+// 记录每个扩展最近一次出错的原因，供管理接口查看
+pub struct LastErrors {
+    errors: Mutex<HashMap<String, (String, String)>>,
+}
+
+impl LastErrors {
+    pub fn global() -> &'static LastErrors {
+        LAST_ERRORS.get_or_init(|| LastErrors {
+            errors: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn record(&self, package: &str, message: impl Into<String>) {
+        self.errors.lock().unwrap().insert(
+            package.to_string(),
+            (Local::now().to_rfc3339(), message.into()),
+        );
+    }
+
+    pub fn get(&self, package: &str) -> Option<Value> {
+        self.errors
+            .lock()
+            .unwrap()
+            .get(package)
+            .map(|(time, message)| json!({ "time": time, "message": message }))
+    }
+
+    pub fn remove(&self, package: &str) {
+        self.errors.lock().unwrap().remove(package);
+    }
+}
@@ -0,0 +1,567 @@
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, Path},
+    http::HeaderMap,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, MethodRouter},
+    BoxError, Extension, Json, Router,
+};
+use glob::Pattern;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+mod admin;
+pub mod advisories;
+pub mod auth;
+mod circuit_breaker;
+mod constraint;
+mod credentials;
+pub mod dist;
+pub mod downloads;
+mod fixtures;
+mod git_builder;
+mod git_mirror;
+mod hooks;
+pub mod hosted;
+mod journal;
+mod last_error;
+mod lookup_error;
+mod metadata;
+mod metadata_cache;
+mod metrics;
+pub mod mirrors;
+pub mod package;
+pub mod package_index;
+mod packages_root;
+mod rate_limit;
+pub mod repositories;
+mod request_helper;
+mod secret;
+pub mod storage;
+mod ttl_cache;
+mod upstream_proxy;
+pub mod version_filter;
+mod warm;
+mod watcher;
+
+use crate::advisories::Advisories;
+use crate::auth::TokenStore;
+use crate::circuit_breaker::CircuitBreakers;
+use crate::credentials::OriginCredentials;
+use crate::dist::Dist;
+use crate::downloads::DownloadStats;
+use crate::hosted::HostedPackages;
+use crate::journal::RouteDecision;
+use crate::lookup_error::{LookupError, NegativeCache};
+use crate::mirrors::aliyun::Aliyun;
+use crate::mirrors::mirror::Mirror;
+use crate::mirrors::packagist::Packagist;
+use crate::mirrors::tencent::Tencent;
+use crate::package::Package;
+use crate::package_index::PackageIndex;
+use crate::upstream_proxy::ProxyRules;
+use crate::rate_limit::RateLimits;
+use crate::repositories::Repository;
+use crate::storage::{Qiniu, Storage};
+use crate::version_filter::VersionFilter;
+
+// 白名单扩展 dist 的获取策略，对应 PACKAGIST_STRATEGY 的 1 和 2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackagistStrategy {
+    StorageSelf,
+    CacheThirdSite,
+}
+
+// packages.json 中 metadata-url、dist-url 等地址的前缀
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublicUrl {
+    // 相对地址，由 composer 按仓库地址补全
+    Relative,
+    // 固定的对外地址，如 https://mirror.example.com
+    Fixed(String),
+    // 按请求的 Host 和 X-Forwarded-Proto 生成
+    FromHost,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub packages: String,
+    pub package_white_list: Vec<String>,
+    // packagist 的元数据地址，%package% 会被替换成扩展名
+    pub packages_meta_url_template: String,
+    pub strategy: PackagistStrategy,
+    // 策略2 的第三方加速地址
+    pub cache_site_list: Vec<String>,
+    // 非白名单扩展依次尝试的第三方镜像，默认为腾讯云、阿里云
+    pub mirrors: Vec<Arc<dyn Mirror>>,
+    // 策略1 上传 dist 的自有存储
+    pub storage: Option<Arc<dyn Storage>>,
+    // 需要令牌才能访问的私有扩展，支持 * 泛型匹配
+    pub private_packages: Vec<String>,
+    pub tokens: Arc<TokenStore>,
+    // 通过 /upload 或 upload 子命令上传的私有扩展
+    pub hosted: Arc<HostedPackages>,
+    // 需要构建元数据的 git 仓库，可以是本机路径或远程地址
+    pub git_repositories: Vec<String>,
+    pub git_build_dir: PathBuf,
+    // 设置后白名单扩展的 source.url 改为本服务的 git 镜像地址，如 https://mirror.example.com
+    pub git_mirror_url: Option<String>,
+    pub git_mirror_dir: PathBuf,
+    // 额外的上游 composer 仓库，匹配的扩展不再经过 packagist 和第三方镜像
+    pub repositories: Vec<Repository>,
+    pub public_url: PublicUrl,
+    // 作为 packagist 之外的私有仓库使用时，packages.json 中声明 available-package-patterns
+    pub private_repository: bool,
+    // 设置后 packages.json 的 notify-batch 指向本服务，记录下载统计
+    pub downloads: Option<Arc<DownloadStats>>,
+    // 设置后 packages.json 的 list、search 指向本服务
+    pub package_index: Option<Arc<PackageIndex>>,
+    // 设置后 packages.json 的 security-advisories 指向本服务
+    pub advisories: Option<Arc<Advisories>>,
+    // 从元数据中移除受漏洞影响的版本的扩展，支持 * 泛型匹配
+    pub security_block_packages: Vec<String>,
+    // 返回元数据前按规则移除版本
+    pub version_filters: Vec<VersionFilter>,
+}
+
+impl Config {
+    pub fn new(packages: String, package_white_list: Vec<String>) -> Self {
+        Self {
+            packages,
+            package_white_list,
+            packages_meta_url_template: String::from("https://repo.packagist.org/p2/%package%.json"),
+            strategy: PackagistStrategy::CacheThirdSite,
+            cache_site_list: Vec::new(),
+            mirrors: vec![Arc::new(Tencent::new()), Arc::new(Aliyun::new())],
+            storage: None,
+            private_packages: Vec::new(),
+            tokens: Arc::new(TokenStore::new("./tokens.json")),
+            hosted: Arc::new(HostedPackages::new("./hosted")),
+            git_repositories: Vec::new(),
+            git_build_dir: PathBuf::from("./repos"),
+            git_mirror_url: None,
+            git_mirror_dir: PathBuf::from("./git-mirrors"),
+            repositories: Vec::new(),
+            public_url: PublicUrl::Relative,
+            private_repository: false,
+            downloads: None,
+            package_index: None,
+            advisories: None,
+            security_block_packages: Vec::new(),
+            version_filters: Vec::new(),
+        }
+    }
+
+    // 从工作目录下的 packages.json 和环境变量读取配置，packages.json 不存在时忽略
+    pub fn from_env() -> Self {
+        let mut packages = String::new();
+        if let Ok(mut packages_file) = OpenOptions::new().read(true).open("./packages.json") {
+            packages_file.read_to_string(&mut packages).unwrap();
+        }
+
+        let package_white_list = env::var("PACKAGE_WHITE_LIST")
+            .unwrap()
+            .split(",")
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+
+        let mut config = Self::new(packages, package_white_list);
+        config.packages_meta_url_template = env::var("PACKAGES_META_URL_TEMPLATE").unwrap();
+        config.cache_site_list = env::var("CACHE_SITE_LIST")
+            .unwrap_or_default()
+            .split(",")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        config.private_packages = env::var("PRIVATE_PACKAGES")
+            .unwrap_or_default()
+            .split(",")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        config.tokens = Arc::new(TokenStore::from_env());
+        config.hosted = Arc::new(HostedPackages::from_env());
+        config.git_repositories = env::var("GIT_REPOSITORIES")
+            .unwrap_or_default()
+            .split(",")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        if let Ok(dir) = env::var("GIT_BUILD_DIR") {
+            config.git_build_dir = PathBuf::from(dir);
+        }
+        config.git_mirror_url = env::var("GIT_MIRROR_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .map(|url| url.trim_end_matches('/').to_string());
+        if let Ok(dir) = env::var("GIT_MIRROR_DIR") {
+            config.git_mirror_dir = PathBuf::from(dir);
+        }
+        if let Ok(path) = env::var("UPSTREAM_REPOSITORIES_PATH") {
+            config.repositories = repositories::load(&path).unwrap_or_else(|err| panic!("{}", err));
+        }
+        config.public_url = match env::var("PUBLIC_URL").unwrap_or_default().as_str() {
+            "" => PublicUrl::Relative,
+            "auto" => PublicUrl::FromHost,
+            url => PublicUrl::Fixed(url.trim_end_matches('/').to_string()),
+        };
+        config.private_repository = env::var("PRIVATE_REPOSITORY").is_ok_and(|s| s == "true");
+        config.downloads = DownloadStats::from_env().map(Arc::new);
+        config.package_index = PackageIndex::from_env().map(Arc::new);
+        config.advisories = Advisories::from_env()
+            .unwrap_or_else(|err| panic!("{}", err))
+            .map(Arc::new);
+        config.security_block_packages = env::var("SECURITY_BLOCK_PACKAGES")
+            .unwrap_or_default()
+            .split(",")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        if let Ok(path) = env::var("VERSION_FILTERS_PATH") {
+            config.version_filters = version_filter::load(&path).unwrap_or_else(|err| panic!("{}", err));
+        }
+        match env::var("PACKAGIST_STRATEGY").unwrap().as_str() {
+            "1" => {
+                config.strategy = PackagistStrategy::StorageSelf;
+                config.storage = Some(Arc::new(Qiniu::from_env()));
+            }
+            "2" => config.strategy = PackagistStrategy::CacheThirdSite,
+            _ => panic!("Unknown strategy"),
+        }
+        // 策略2 下配置了七牛云时同样可以上传私有扩展
+        if config.storage.is_none() && env::var("BUCKET").is_ok_and(|bucket| !bucket.is_empty()) {
+            config.storage = Some(Arc::new(Qiniu::from_env()));
+        }
+        config
+    }
+}
+
+pub struct MirrorServer {
+    config: Config,
+    router: Router,
+}
+
+impl MirrorServer {
+    pub fn builder() -> MirrorServerBuilder {
+        MirrorServerBuilder::default()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn into_router(self) -> Router {
+        self.router
+    }
+
+    // 启动新版本监测、git 仓库的定时构建、下载统计的保存和转发以及包名索引的刷新，
+    // 未设置 WATCH_INTERVAL_SECS、GIT_BUILD_INTERVAL_SECS、DOWNLOADS_STATS_PATH、PACKAGE_INDEX_PATH 时不做任何事
+    pub fn spawn_watcher(&self) {
+        watcher::spawn(self.config.clone());
+        git_builder::spawn(self.config.clone());
+        if let Some(downloads) = &self.config.downloads {
+            downloads::spawn(downloads.clone());
+        }
+        if let Some(package_index) = &self.config.package_index {
+            package_index::spawn(package_index.clone(), self.config.hosted.clone());
+        }
+    }
+
+    pub async fn build_repositories(&self) {
+        git_builder::build_all(&self.config).await;
+    }
+
+    pub async fn warm(&self, args: &[String]) {
+        warm::run(args, self.config.clone()).await;
+    }
+
+    pub async fn upload(&self, args: &[String]) {
+        hosted::run(args, self.config.clone()).await;
+    }
+
+    pub async fn serve(self, listener: TcpListener) -> Result<(), BoxError> {
+        axum::Server::from_tcp(listener)?
+            .serve(
+                self.router
+                    .into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct MirrorServerBuilder {
+    config: Option<Config>,
+    mirrors: Option<Vec<Arc<dyn Mirror>>>,
+    storage: Option<Arc<dyn Storage>>,
+    routes: Router,
+}
+
+impl MirrorServerBuilder {
+    // 不设置时使用 Config::from_env()
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    // 按添加顺序尝试，设置后替换配置中的镜像
+    pub fn mirror(mut self, mirror: impl Mirror + 'static) -> Self {
+        self.mirrors
+            .get_or_insert_with(Vec::new)
+            .push(Arc::new(mirror));
+        self
+    }
+
+    pub fn storage(mut self, storage: impl Storage + 'static) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    // 额外的路由同样经过监控、请求日志中间件，可通过 Extension<Config> 获取配置
+    pub fn route(mut self, path: &str, method_router: MethodRouter) -> Self {
+        self.routes = self.routes.route(path, method_router);
+        self
+    }
+
+    pub fn merge(mut self, router: Router) -> Self {
+        self.routes = self.routes.merge(router);
+        self
+    }
+
+    // 策略1 需要自有存储，未配置时返回错误
+    pub fn build(self) -> Result<MirrorServer, String> {
+        let mut config = self.config.unwrap_or_else(Config::from_env);
+        if let Some(mirrors) = self.mirrors {
+            config.mirrors = mirrors;
+        }
+        if let Some(storage) = self.storage {
+            config.storage = Some(storage);
+        }
+        if config.strategy == PackagistStrategy::StorageSelf && config.storage.is_none() {
+            return Err("PACKAGIST_STRATEGY=1 requires a storage backend".to_string());
+        }
+        // 受影响的版本只按本地漏洞库移除，只设置了 SECURITY_ADVISORIES_UPSTREAM 时不会生效
+        if !config.security_block_packages.is_empty()
+            && config
+                .advisories
+                .as_ref()
+                .is_none_or(|advisories| advisories.is_empty())
+        {
+            return Err(
+                "SECURITY_BLOCK_PACKAGES requires a local advisories database, import one with `advisories import`"
+                    .to_string(),
+            );
+        }
+        ProxyRules::init()?;
+        OriginCredentials::init()?;
+        packages_root::init();
+
+        let upload_max_bytes = env::var("UPLOAD_MAX_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(100 * 1024 * 1024);
+        // 仓库提交较多时 git-upload-pack 请求中的 have 列表可能超过默认的 2MB
+        let git_mirror_max_bytes = env::var("GIT_MIRROR_MAX_REQUEST_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(100 * 1024 * 1024);
+
+        let router = Router::new()
+            .route("/p2/*package_path", get(package_meta))
+            .route(
+                "/dists/:package1/:package2/:version/:reference_and_type",
+                get(dist_dispatcher),
+            )
+            .route(
+                "/git/:vendor/:repository/*path",
+                get(git_mirror::serve)
+                    .post(git_mirror::serve)
+                    .layer(DefaultBodyLimit::max(git_mirror_max_bytes)),
+            )
+            .route("/packages.json", get(packages_root::packages_meta))
+            .route("/downloads/", post(downloads::notify_batch))
+            .route("/packages/list.json", get(package_index::list))
+            .route("/search.json", get(package_index::search))
+            .route(
+                "/api/security-advisories/",
+                get(advisories::api).post(advisories::api),
+            )
+            .route("/status", get(status))
+            .route("/metrics", get(metrics::render))
+            .route("/hooks/github", post(hooks::github))
+            .route("/hooks/gitlab", post(hooks::gitlab))
+            .route(
+                "/upload",
+                post(hosted::upload).layer(DefaultBodyLimit::max(upload_max_bytes)),
+            )
+            .nest("/admin", admin::router())
+            .merge(self.routes)
+            .layer(middleware::from_fn(auth::require_token))
+            .layer(middleware::from_fn(metrics::track))
+            .layer(middleware::from_fn(journal::record))
+            .layer(Extension(config.clone()));
+
+        Ok(MirrorServer { config, router })
+    }
+}
+
+async fn dist_dispatcher(
+    Path((package1, package2, version, reference_and_type)): Path<(String, String, String, String)>,
+    config: Extension<Config>,
+) -> Response {
+    let reference = reference_and_type.split(".").collect::<Vec<&str>>()[0];
+    let dist_type = reference_and_type.split(".").collect::<Vec<&str>>()[1];
+    let package = Package::new(&package1, &package2);
+    let dist = Dist::new(&package, &version, reference, dist_type);
+
+    let packagist_mirror = Packagist::new(&config);
+
+    let negative_cache = NegativeCache::global();
+    if let Some(err) = negative_cache.get(&package.full_name, Some(&version)) {
+        let mut response = err.into_response();
+        response.extensions_mut().insert(RouteDecision("negative_cache"));
+        return response;
+    }
+
+    let mut consulted = Vec::new();
+    let hosted = config.hosted.dist_object(&package.full_name, &version, reference).await;
+    let repository = config.repositories.iter().find(|repository| repository.matches(&package.full_name));
+    let (decision, mut response) = match (hosted, repository) {
+        (Some(Ok(object_name)), _) => ("hosted", hosted::dist_response(&config, &object_name).await),
+        (Some(Err(err)), _) => ("hosted", err.into_response()),
+        (None, Some(repository)) => (
+            "repository",
+            repository.make_dist_response(&dist, config.storage.as_deref()).await,
+        ),
+        (None, None) => match check_package_in_white_list(&package.full_name, &config.package_white_list) {
+            true => ("packagist_whitelist", packagist_mirror.make_dist_response(&dist).await),
+            false => {
+                let mut routed = None;
+                for mirror in config.mirrors.iter() {
+                    consulted.push(mirror.get_dist_url(&dist));
+                    if mirror.check_dist(&dist).await {
+                        routed = Some((mirror.name(), mirror.make_dist_response(&dist).await));
+                        break;
+                    }
+                }
+                match routed {
+                    Some(routed) => routed,
+                    None => ("packagist_fallback", packagist_mirror.make_dist_response(&dist).await),
+                }
+            }
+        },
+    };
+
+    if let Some(err) = response.extensions().get::<LookupError>().cloned() {
+        let err = err.with_consulted(consulted);
+        negative_cache.insert(&err);
+        response = err.into_response();
+    }
+    response.extensions_mut().insert(RouteDecision(decision));
+    response
+}
+
+async fn status() -> Json<Value> {
+    Json(json!({
+        "breakers": CircuitBreakers::global().snapshot(),
+        "rate_limits": RateLimits::global().snapshot(),
+    }))
+}
+
+async fn package_meta(
+    Path(package_path): Path<String>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    config: Extension<Config>,
+) -> Response {
+    let package = package_path
+        .trim_end_matches(".json")
+        .trim_end_matches("~dev")
+        .to_string();
+    let serve_locally =
+        advisories::blocks(&config, &package) || version_filter::applies(&config, &package);
+    let base_url = packages_root::base_url(&config, &headers, connect_info.as_ref());
+    let response =
+        route_package_meta(Path(package_path), config.clone(), serve_locally, &base_url).await;
+    let response = advisories::block_vulnerable(&config, &package, response).await;
+    version_filter::apply(&config, &package, response).await
+}
+
+// serve_locally 为 true 时不跳转到第三方镜像，元数据由本服务返回，以便移除版本；
+// base_url 为托管扩展 dist 地址的前缀
+async fn route_package_meta(
+    Path(package_path): Path<String>,
+    config: Extension<Config>,
+    serve_locally: bool,
+    base_url: &str,
+) -> Response {
+    let headers = HeaderMap::new();
+    if !package_path.ends_with(".json") {
+        return (StatusCode::NOT_FOUND, headers, "").into_response();
+    }
+
+    let package_combine = package_path.trim_end_matches(".json");
+    let vendor = package_combine.split("/").collect::<Vec<&str>>()[0];
+    let package = package_combine.split("/").collect::<Vec<&str>>()[1];
+    let package = Package::new(vendor, package);
+
+    if let Some(body) = config.hosted.metadata(package_combine).await {
+        let body = hosted::rewrite_dist_urls(&body, base_url).unwrap_or(body);
+        let mut response = request_helper::json_response(body);
+        response.extensions_mut().insert(RouteDecision("hosted"));
+        return response;
+    }
+
+    let repository = config.repositories.iter().find(|repository| repository.matches(package_combine));
+    if let Some(repository) = repository {
+        let mut response = repository.make_package_response(package_combine).await;
+        response.extensions_mut().insert(RouteDecision("repository"));
+        return response;
+    }
+
+    let (decision, mut response) = match check_package_in_white_list(package_combine, &config.package_white_list) {
+        true => (
+            "packagist_whitelist",
+            Packagist::new(&config).make_package_response(&package).await,
+        ),
+        false if serve_locally => (
+            "packagist_local",
+            Packagist::new(&config).make_package_response(&package).await,
+        ),
+        false => {
+            let mut routed = None;
+            for mirror in config.mirrors.iter() {
+                if let Some(response) = mirror.make_package_response(&package).await {
+                    routed = Some((mirror.name(), response));
+                    break;
+                }
+            }
+            match routed {
+                Some(routed) => routed,
+                None => (
+                    "packagist_fallback",
+                    Packagist::new(&config).make_package_response(&package).await,
+                ),
+            }
+        }
+    };
+    response.extensions_mut().insert(RouteDecision(decision));
+    response
+}
+
+fn check_package_in_white_list(package: &str, white_list: &Vec<String>) -> bool {
+    for pattern in white_list {
+        if Pattern::new(pattern).unwrap().matches(package) {
+            return true;
+        }
+    }
+    false
+}
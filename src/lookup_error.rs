@@ -0,0 +1,186 @@
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use glob::Pattern;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::ttl_cache::TtlCache;
+
+static NEGATIVE_CACHE: OnceLock<NegativeCache> = OnceLock::new();
+
+// 查找包或版本失败的原因，consulted 为已经查询过的上游地址
+#[derive(Clone, Debug)]
+pub enum LookupError {
+    PackageNotFound {
+        package: String,
+        consulted: Vec<String>,
+    },
+    VersionNotFound {
+        package: String,
+        version: String,
+        consulted: Vec<String>,
+    },
+    Unavailable {
+        package: String,
+        message: String,
+    },
+}
+
+impl LookupError {
+    pub fn with_consulted(self, mut upstreams: Vec<String>) -> Self {
+        match self {
+            LookupError::PackageNotFound { package, consulted } => {
+                upstreams.extend(consulted);
+                LookupError::PackageNotFound {
+                    package,
+                    consulted: upstreams,
+                }
+            }
+            LookupError::VersionNotFound {
+                package,
+                version,
+                consulted,
+            } => {
+                upstreams.extend(consulted);
+                LookupError::VersionNotFound {
+                    package,
+                    version,
+                    consulted: upstreams,
+                }
+            }
+            unavailable => unavailable,
+        }
+    }
+
+    // 404 响应中附带错误本身，dist_dispatcher 据此补充自己查询过的镜像并写入负缓存
+    pub fn into_response(self) -> Response {
+        let message = self.to_string();
+        match &self {
+            LookupError::PackageNotFound { consulted, .. }
+            | LookupError::VersionNotFound { consulted, .. } => {
+                let mut response = (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "error": message, "consulted": consulted })),
+                )
+                    .into_response();
+                response.extensions_mut().insert(self);
+                response
+            }
+            LookupError::Unavailable { .. } => {
+                (StatusCode::BAD_GATEWAY, Json(json!({ "error": message }))).into_response()
+            }
+        }
+    }
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::PackageNotFound { package, .. } => {
+                write!(f, "package {} not found", package)
+            }
+            LookupError::VersionNotFound {
+                package, version, ..
+            } => write!(f, "version {} of {} not found", version, package),
+            LookupError::Unavailable { package, message } => {
+                write!(f, "metadata of {} unavailable: {}", package, message)
+            }
+        }
+    }
+}
+
+// 不存在的包、版本在短时间内直接返回 404，不再请求上游
+pub struct NegativeCache {
+    cache: TtlCache<(String, String), LookupError>,
+    ttl: Duration,
+}
+
+impl NegativeCache {
+    pub fn global() -> &'static NegativeCache {
+        NEGATIVE_CACHE.get_or_init(|| {
+            let ttl = env::var("NEGATIVE_CACHE_TTL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60);
+            NegativeCache {
+                cache: TtlCache::new(10000),
+                ttl: Duration::from_secs(ttl),
+            }
+        })
+    }
+
+    fn key(error: &LookupError) -> Option<(String, String)> {
+        match error {
+            LookupError::PackageNotFound { package, .. } => {
+                Some((package.clone(), String::new()))
+            }
+            LookupError::VersionNotFound {
+                package, version, ..
+            } => Some((package.clone(), version.clone())),
+            LookupError::Unavailable { .. } => None,
+        }
+    }
+
+    pub fn get(&self, package: &str, version: Option<&str>) -> Option<LookupError> {
+        self.cache
+            .get(&(package.to_string(), String::new()))
+            .or_else(|| {
+                version.and_then(|version| {
+                    self.cache
+                        .get(&(package.to_string(), version.to_string()))
+                })
+            })
+    }
+
+    pub fn insert(&self, error: &LookupError) {
+        if let Some(key) = Self::key(error) {
+            self.cache.insert(key, error.clone(), self.ttl);
+        }
+    }
+
+    pub fn purge(&self, package: &Pattern) -> usize {
+        self.cache.remove_where(|(cached, _)| package.matches(cached))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_cache_test() {
+        let cache = NegativeCache {
+            cache: TtlCache::new(10),
+            ttl: Duration::from_secs(60),
+        };
+        cache.insert(&LookupError::VersionNotFound {
+            package: "a/b".to_string(),
+            version: "1.0.0".to_string(),
+            consulted: vec![],
+        });
+        cache.insert(&LookupError::Unavailable {
+            package: "c/d".to_string(),
+            message: "timeout".to_string(),
+        });
+        assert!(cache.get("a/b", Some("1.0.0")).is_some());
+        assert!(cache.get("a/b", Some("2.0.0")).is_none());
+        assert!(cache.get("a/b", None).is_none());
+        assert!(cache.get("c/d", None).is_none());
+
+        // 整个包不存在时任何版本都命中
+        cache.insert(&LookupError::PackageNotFound {
+            package: "e/f".to_string(),
+            consulted: vec![],
+        });
+        assert!(cache.get("e/f", Some("2.0.0")).is_some());
+
+        assert_eq!(1, cache.purge(&Pattern::new("a/*").unwrap()));
+        assert!(cache.get("a/b", Some("1.0.0")).is_none());
+    }
+}
@@ -0,0 +1,410 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+// p2 元数据中单个版本的信息，未建模的字段保留在 extra 中
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Version {
+    #[serde(default)]
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_normalized: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub package_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "string_list"
+    )]
+    pub license: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dist: Option<DistInfo>,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "links"
+    )]
+    pub require: BTreeMap<String, String>,
+    #[serde(
+        rename = "require-dev",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "links"
+    )]
+    pub require_dev: BTreeMap<String, String>,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "links"
+    )]
+    pub conflict: BTreeMap<String, String>,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "links"
+    )]
+    pub replace: BTreeMap<String, String>,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "links"
+    )]
+    pub provide: BTreeMap<String, String>,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "links"
+    )]
+    pub suggest: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Source {
+    #[serde(rename = "type", default)]
+    pub source_type: String,
+    #[serde(default, deserialize_with = "nullable_string")]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+impl Source {
+    // 去掉仓库地址末尾的 .git，如 https://github.com/quansitech/think-core
+    pub fn repository_url(&self) -> &str {
+        self.url.strip_suffix(".git").unwrap_or(&self.url)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DistInfo {
+    #[serde(rename = "type", default)]
+    pub dist_type: String,
+    #[serde(default, deserialize_with = "nullable_string")]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shasum: Option<String>,
+}
+
+impl Version {
+    pub fn reference(&self) -> Option<&str> {
+        self.dist
+            .as_ref()
+            .and_then(|dist| dist.reference.as_deref())
+            .or_else(|| self.source.as_ref().and_then(|source| source.reference.as_deref()))
+            .filter(|reference| !reference.is_empty())
+    }
+}
+
+// php 的空数组会被编码成 []，依赖列表可能是 {} 也可能是 []
+fn links<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, constraint)| Some((name.clone(), constraint.as_str()?.to_string())))
+        .collect())
+}
+
+// 部分仓库的 url 为 null，视为空字符串
+fn nullable_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// license 可能是字符串也可能是数组
+fn string_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => vec![s],
+        Value::Array(items) => items
+            .into_iter()
+            .filter_map(|item| item.as_str().map(|s| s.to_string()))
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+// 展开 composer/2.0 的精简格式：每个版本只记录与上一个版本不同的字段，"__unset" 表示删除该字段
+pub fn expand_minified(versions: Vec<Value>) -> Vec<Value> {
+    let mut expanded = Vec::with_capacity(versions.len());
+    let mut current: Option<Map<String, Value>> = None;
+    for version in versions {
+        let fields = match version {
+            Value::Object(fields) => fields,
+            _ => continue,
+        };
+        let next = match current.take() {
+            None => fields,
+            Some(mut previous) => {
+                for (key, value) in fields {
+                    if value == "__unset" {
+                        previous.remove(&key);
+                    } else {
+                        previous.insert(key, value);
+                    }
+                }
+                previous
+            }
+        };
+        expanded.push(Value::Object(next.clone()));
+        current = Some(next);
+    }
+    expanded
+}
+
+// 只保留 keep 返回 true 的版本，返回新的元数据和被移除的版本号，没有版本被移除时返回 None
+pub fn retain_versions(
+    package: &str,
+    body: &str,
+    mut keep: impl FnMut(&Value) -> bool,
+) -> Option<(String, Vec<String>)> {
+    let mut root = serde_json::from_str::<Value>(body).ok()?;
+    let versions = match root["packages"][package].take() {
+        Value::Array(versions) => versions,
+        _ => return None,
+    };
+    let versions = match root["minified"].as_str() {
+        Some("composer/2.0") => expand_minified(versions),
+        _ => versions,
+    };
+
+    let mut removed = Vec::new();
+    let mut kept = Vec::new();
+    for version in versions {
+        if keep(&version) {
+            kept.push(version);
+        } else {
+            removed.push(version["version"].as_str().unwrap_or_default().to_string());
+        }
+    }
+    if removed.is_empty() {
+        return None;
+    }
+    // 移除版本后不再是精简格式
+    if let Some(root) = root.as_object_mut() {
+        root.remove("minified");
+    }
+    root["packages"][package] = Value::Array(kept);
+    Some((root.to_string(), removed))
+}
+
+// 单个扩展的全部版本，按 version、version_normalized 和 reference 建立索引
+#[derive(Debug, Default)]
+pub struct PackageMetadata {
+    pub versions: Vec<Version>,
+    by_version: HashMap<String, usize>,
+    by_normalized: HashMap<String, usize>,
+    by_reference: HashMap<String, usize>,
+}
+
+impl PackageMetadata {
+    pub fn new(versions: Vec<Version>) -> Self {
+        let mut metadata = Self::default();
+        for (index, version) in versions.iter().enumerate() {
+            metadata
+                .by_version
+                .entry(version.version.clone())
+                .or_insert(index);
+            if let Some(normalized) = &version.version_normalized {
+                metadata
+                    .by_normalized
+                    .entry(normalized.clone())
+                    .or_insert(index);
+            }
+            if let Some(reference) = version.reference() {
+                metadata
+                    .by_reference
+                    .entry(reference.to_string())
+                    .or_insert(index);
+            }
+        }
+        metadata.versions = versions;
+        metadata
+    }
+
+    pub fn parse(package: &str, body: &str) -> serde_json::Result<Self> {
+        let mut root = serde_json::from_str::<Value>(body)?;
+        let versions = match root["packages"][package].take() {
+            Value::Array(versions) => versions,
+            _ => Vec::new(),
+        };
+        let versions = match root["minified"].as_str() {
+            Some("composer/2.0") => expand_minified(versions),
+            _ => versions,
+        };
+        let versions = versions
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<serde_json::Result<Vec<Version>>>()?;
+        Ok(Self::new(versions))
+    }
+
+    pub fn version(&self, version: &str) -> Option<&Version> {
+        self.by_version
+            .get(version)
+            .or_else(|| self.by_normalized.get(version))
+            .map(|index| &self.versions[*index])
+    }
+
+    pub fn reference(&self, reference: &str) -> Option<&Version> {
+        self.by_reference
+            .get(reference)
+            .map(|index| &self.versions[*index])
+    }
+
+    // 按版本号查找，同一版本号有多个构建时（如 dev 分支）用 reference 选择
+    pub fn find(&self, version: &str, reference: &str) -> Option<&Version> {
+        let found = self.version(version)?;
+        if found.reference() == Some(reference) {
+            return Some(found);
+        }
+        let same_version = |candidate: &&Version| {
+            candidate.version == found.version
+                && candidate.version_normalized == found.version_normalized
+        };
+        self.reference(reference)
+            .filter(same_version)
+            .or(Some(found))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIFIED: &str = r#"{
+        "packages": {
+            "quansitech/think-core": [
+                {
+                    "name": "quansitech/think-core",
+                    "version": "v12.31.0",
+                    "version_normalized": "12.31.0.0",
+                    "license": ["MIT"],
+                    "source": {"type": "git", "url": "https://github.com/quansitech/think-core.git", "reference": "8f0c2a1e"},
+                    "dist": {"type": "zip", "url": "https://api.github.com/repos/quansitech/think-core/zipball/8f0c2a1e", "reference": "8f0c2a1e", "shasum": ""},
+                    "require": {"php": ">=7.2"},
+                    "suggest": {"ext-redis": "*"}
+                },
+                {
+                    "version": "v12.30.0",
+                    "version_normalized": "12.30.0.0",
+                    "source": {"type": "git", "url": "https://github.com/quansitech/think-core.git", "reference": "3b1d7c9a"},
+                    "dist": {"type": "zip", "url": "https://api.github.com/repos/quansitech/think-core/zipball/3b1d7c9a", "reference": "3b1d7c9a", "shasum": ""},
+                    "suggest": "__unset"
+                },
+                {
+                    "version": "dev-master",
+                    "version_normalized": "dev-master",
+                    "require": []
+                }
+            ]
+        },
+        "minified": "composer/2.0"
+    }"#;
+
+    #[test]
+    fn expand_minified_test() {
+        let metadata = PackageMetadata::parse("quansitech/think-core", MINIFIED).unwrap();
+        assert_eq!(3, metadata.versions.len());
+
+        let version = metadata.version("v12.30.0").unwrap();
+        assert_eq!("quansitech/think-core", version.name);
+        assert_eq!(vec!["MIT"], version.license);
+        assert_eq!(
+            Some(">=7.2"),
+            version.require.get("php").map(|s| s.as_str())
+        );
+        assert!(version.suggest.is_empty());
+
+        // 未改变的 source、dist 沿用上一个版本
+        let dev = metadata.version("dev-master").unwrap();
+        assert_eq!(Some("3b1d7c9a"), dev.reference());
+        assert!(dev.require.is_empty());
+    }
+
+    #[test]
+    fn lookup_test() {
+        let metadata = PackageMetadata::parse("quansitech/think-core", MINIFIED).unwrap();
+        assert_eq!("v12.31.0", metadata.version("12.31.0.0").unwrap().version);
+        assert_eq!("v12.30.0", metadata.reference("3b1d7c9a").unwrap().version);
+        // reference 属于其它版本时仍以版本号为准
+        assert_eq!(
+            "v12.30.0",
+            metadata.find("v12.30.0", "8f0c2a1e").unwrap().version
+        );
+        assert_eq!(
+            "v12.30.0",
+            metadata.find("v12.30.0", "unknown").unwrap().version
+        );
+        assert!(metadata.find("v1.0.0", "unknown").is_none());
+        assert!(metadata.find("v1.0.0", "8f0c2a1e").is_none());
+
+        // 同一 dev 版本的多个构建按 reference 区分
+        let body = r#"{"packages":{"acme/lib":[
+            {"version":"dev-main","dist":{"type":"zip","url":"","reference":"new"}},
+            {"version":"dev-main","dist":{"type":"zip","url":"","reference":"old"}}
+        ]}}"#;
+        let metadata = PackageMetadata::parse("acme/lib", body).unwrap();
+        let find = |reference: &str| metadata.find("dev-main", reference).unwrap().reference();
+        assert_eq!(Some("old"), find("old"));
+        assert_eq!(Some("new"), find("unknown"));
+        assert!(PackageMetadata::parse("other/package", MINIFIED)
+            .unwrap()
+            .versions
+            .is_empty());
+    }
+
+    #[test]
+    fn null_reference_test() {
+        let body = r#"{"packages":{"acme/lib":[{"version":"dev-main","source":{"type":"git","url":"https://github.com/acme/lib.git","reference":null},"dist":{"type":"zip","url":"https://example.com/acme/lib.zip","reference":null}},{"version":"dev-legacy","source":{"type":"git","url":null,"reference":"abc"},"dist":{"type":"zip","url":null,"reference":"abc"}}]}}"#;
+        let metadata = PackageMetadata::parse("acme/lib", body).unwrap();
+        let version = metadata.version("dev-main").unwrap();
+        assert_eq!(None, version.source.as_ref().unwrap().reference);
+        assert_eq!(None, version.reference());
+
+        // url 为 null 时视为空字符串
+        let version = metadata.version("dev-legacy").unwrap();
+        assert_eq!("", version.source.as_ref().unwrap().url);
+        assert_eq!("", version.dist.as_ref().unwrap().url);
+        assert_eq!(Some("abc"), version.reference());
+    }
+
+    #[test]
+    fn repository_url_test() {
+        let source = Source {
+            url: "https://git.example.com/team.github/think-core.git".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            "https://git.example.com/team.github/think-core",
+            source.repository_url()
+        );
+        let source = Source {
+            url: "https://github.com/quansitech/think-core".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            "https://github.com/quansitech/think-core",
+            source.repository_url()
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+
+use crate::credentials::Credential;
+use crate::hosted::is_valid_name;
+use crate::last_error::LastErrors;
+use crate::lookup_error::LookupError;
+use crate::metadata::PackageMetadata;
+use crate::metrics::Metrics;
+use crate::request_helper;
+use crate::ttl_cache::TtlCache;
+
+static METADATA_CACHE: OnceLock<Arc<MetadataCache>> = OnceLock::new();
+static REPOSITORY_CACHES: OnceLock<Mutex<HashMap<String, Arc<MetadataCache>>>> = OnceLock::new();
+
+// p2 元数据的磁盘缓存，按 包名.json 存放在 METADATA_CACHE_DIR/p2 下，
+// 服务进程和 warm 等命令行模式共用同一份缓存；解析后的索引按内容摘要缓存在内存中
+pub struct MetadataCache {
+    dir: PathBuf,
+    ttl: Duration,
+    // 最多缓存 METADATA_PARSED_CACHE_SIZE 个扩展的索引，满了之后淘汰最早解析的
+    parsed: TtlCache<String, (u64, Arc<PackageMetadata>)>,
+}
+
+// 解析结果的有效期，过期后按需重新解析
+const PARSED_TTL: Duration = Duration::from_secs(3600);
+
+fn base_dir() -> PathBuf {
+    PathBuf::from(env::var("METADATA_CACHE_DIR").unwrap_or_else(|_| "./cache".to_string()))
+}
+
+impl MetadataCache {
+    pub fn global() -> &'static MetadataCache {
+        Self::packagist()
+    }
+
+    pub fn packagist() -> &'static Arc<MetadataCache> {
+        METADATA_CACHE.get_or_init(|| Arc::new(MetadataCache::from_env(base_dir().join("p2"))))
+    }
+
+    // 上游仓库的元数据单独缓存在 repositories/仓库名 下，与 packagist 的同名扩展互不覆盖
+    pub fn repository(name: &str) -> Arc<MetadataCache> {
+        // 仓库名用作目录名，只保留字母、数字、- 和 _
+        let name = name.replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_',
+            "_",
+        );
+        REPOSITORY_CACHES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .entry(name.clone())
+            .or_insert_with(|| {
+                Arc::new(MetadataCache::from_env(
+                    base_dir().join("repositories").join(name),
+                ))
+            })
+            .clone()
+    }
+
+    // packagist 和磁盘上已有缓存的上游仓库，供管理接口和包索引遍历
+    pub async fn sources() -> Vec<Arc<MetadataCache>> {
+        let mut sources = vec![Self::packagist().clone()];
+        if let Ok(mut entries) = fs::read_dir(base_dir().join("repositories")).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                sources.push(Self::repository(&entry.file_name().to_string_lossy()));
+            }
+        }
+        sources
+    }
+
+    fn from_env(dir: PathBuf) -> Self {
+        let ttl = env::var("METADATA_CACHE_TTL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(300);
+        let parsed_size = env::var("METADATA_PARSED_CACHE_SIZE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1000);
+
+        Self {
+            dir,
+            ttl: Duration::from_secs(ttl),
+            parsed: TtlCache::new(parsed_size),
+        }
+    }
+
+    // 包名会拼接成缓存文件路径，不合法的包名（如含 ..）返回 None，避免读写缓存目录之外的文件
+    fn path(&self, package: &str) -> Option<PathBuf> {
+        is_valid_name(package.trim_end_matches("~dev"))
+            .then(|| self.dir.join(format!("{}.json", package)))
+    }
+
+    async fn read(&self, package: &str) -> Option<(String, bool)> {
+        let path = self.path(package)?;
+        let modified = fs::metadata(&path).await.ok()?.modified().ok()?;
+        let fresh = SystemTime::now()
+            .duration_since(modified)
+            .map(|age| age < self.ttl)
+            .unwrap_or(true);
+        let body = fs::read_to_string(&path).await.ok()?;
+        Some((body, fresh))
+    }
+
+    pub async fn get(&self, package: &str) -> Option<String> {
+        self.read(package).await.map(|(body, _)| body)
+    }
+
+    // 缓存目录下的所有包名
+    pub async fn cached_packages(&self) -> Vec<String> {
+        let mut packages = Vec::new();
+        let mut vendors = match fs::read_dir(&self.dir).await {
+            Ok(vendors) => vendors,
+            Err(_) => return packages,
+        };
+        while let Ok(Some(vendor)) = vendors.next_entry().await {
+            let mut files = match fs::read_dir(vendor.path()).await {
+                Ok(files) => files,
+                Err(_) => continue,
+            };
+            while let Ok(Some(file)) = files.next_entry().await {
+                let name = file.file_name().to_string_lossy().to_string();
+                if let Some(package) = name.strip_suffix(".json") {
+                    packages.push(format!(
+                        "{}/{}",
+                        vendor.file_name().to_string_lossy(),
+                        package
+                    ));
+                }
+            }
+        }
+        packages.sort();
+        packages
+    }
+
+    pub async fn info(&self, package: &str) -> Option<Value> {
+        let metadata = fs::metadata(self.path(package)?).await.ok()?;
+        let modified = metadata.modified().ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        Some(json!({
+            "bytes": metadata.len(),
+            "age_secs": age.as_secs(),
+            "fresh": age < self.ttl,
+        }))
+    }
+
+    pub async fn invalidate(&self, package: &str) -> bool {
+        self.parsed.remove_where(|cached| cached == package);
+        match self.path(package) {
+            Some(path) => fs::remove_file(path).await.is_ok(),
+            None => false,
+        }
+    }
+
+    pub async fn put(&self, package: &str, body: &str) {
+        let path = match self.path(package) {
+            Some(path) => path,
+            None => return,
+        };
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent).await;
+        }
+        // 先写入临时文件再改名，读取方不会读到写了一半的文件
+        let tmp = path.with_extension(format!("{}.tmp", rand::random::<u32>()));
+        let result = match fs::write(&tmp, body).await {
+            Ok(_) => fs::rename(&tmp, &path).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp).await;
+            eprintln!("failed to write metadata cache {}: {}", path.display(), err);
+        }
+    }
+
+    // 缓存未过期时直接返回，否则从上游拉取；上游不可用时退回到过期的缓存
+    pub async fn fetch(&self, package: &str, url: &str) -> Result<String, LookupError> {
+        self.fetch_with(package, url, None).await
+    }
+
+    // 需要认证的上游仓库使用，credential 附加在拉取元数据的请求上
+    pub async fn fetch_with(
+        &self,
+        package: &str,
+        url: &str,
+        credential: Option<&Credential>,
+    ) -> Result<String, LookupError> {
+        let cached = self.read(package).await;
+        let hit = matches!(cached, Some((_, true)));
+        Metrics::global().record_cache_lookup("metadata", hit);
+        if let Some((body, true)) = cached {
+            return Ok(body);
+        }
+
+        match self.refresh_with(package, url, credential).await {
+            Err(LookupError::Unavailable { .. }) if cached.is_some() => {
+                Ok(cached.map(|(body, _)| body).unwrap())
+            }
+            res => res,
+        }
+    }
+
+    pub async fn fetch_metadata(
+        &self,
+        package: &str,
+        url: &str,
+    ) -> Result<Arc<PackageMetadata>, LookupError> {
+        let body = self.fetch(package, url).await?;
+        self.index(package, &body)
+    }
+
+    // 内容未变化时复用上次解析的结果
+    pub fn index(&self, package: &str, body: &str) -> Result<Arc<PackageMetadata>, LookupError> {
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let digest = hasher.finish();
+        if let Some((cached, metadata)) = self.parsed.get(&package.to_string()) {
+            if cached == digest {
+                return Ok(metadata);
+            }
+        }
+
+        let metadata = PackageMetadata::parse(package, body).map_err(|err| {
+            LookupError::Unavailable {
+                package: package.to_string(),
+                message: format!("invalid metadata: {}", err),
+            }
+        })?;
+        let metadata = Arc::new(metadata);
+        self.parsed
+            .insert(package.to_string(), (digest, metadata.clone()), PARSED_TTL);
+        Ok(metadata)
+    }
+
+    pub async fn refresh(&self, package: &str, url: &str) -> Result<String, LookupError> {
+        self.refresh_with(package, url, None).await
+    }
+
+    pub async fn refresh_with(
+        &self,
+        package: &str,
+        url: &str,
+        credential: Option<&Credential>,
+    ) -> Result<String, LookupError> {
+        let unavailable = |message: String| {
+            LastErrors::global().record(package, message.clone());
+            LookupError::Unavailable {
+                package: package.to_string(),
+                message,
+            }
+        };
+
+        let response = request_helper::try_get_with(url, credential)
+            .await
+            .map_err(|err| unavailable(format!("{}: {}", url, err)))?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => {
+                return Err(LookupError::PackageNotFound {
+                    package: package.to_string(),
+                    consulted: vec![url.to_string()],
+                })
+            }
+            status => return Err(unavailable(format!("{} responded {}", url, status))),
+        }
+        let body = response
+            .text()
+            .await
+            .map_err(|err| unavailable(format!("{}: {}", url, err)))?;
+        self.put(package, &body).await;
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_test() {
+        let cache = MetadataCache {
+            dir: PathBuf::from("/tmp/cache/p2"),
+            ttl: Duration::from_secs(300),
+            parsed: TtlCache::new(10),
+        };
+        assert_eq!(
+            Some(PathBuf::from("/tmp/cache/p2/acme/lib.json")),
+            cache.path("acme/lib")
+        );
+        assert_eq!(
+            Some(PathBuf::from("/tmp/cache/p2/acme/lib~dev.json")),
+            cache.path("acme/lib~dev")
+        );
+        for package in [
+            "../../etc/passwd",
+            "acme/../../x",
+            "acme/..",
+            "/etc/passwd",
+            "acme",
+        ] {
+            assert_eq!(None, cache.path(package), "{}", package);
+        }
+    }
+
+    #[tokio::test]
+    async fn put_and_index_test() {
+        let dir = env::temp_dir().join(format!("composer_mirror_metadata_{}", std::process::id()));
+        let cache = MetadataCache {
+            dir: dir.clone(),
+            ttl: Duration::from_secs(300),
+            parsed: TtlCache::new(2),
+        };
+        let body = |package: &str| format!(r#"{{"packages":{{"{}":[]}}}}"#, package);
+        cache.put("acme/lib", &body("acme/lib")).await;
+        assert_eq!(Some(body("acme/lib")), cache.get("acme/lib").await);
+        // 临时文件已改名
+        assert_eq!(vec!["acme/lib".to_string()], cache.cached_packages().await);
+        let mut files = std::fs::read_dir(dir.join("acme")).unwrap();
+        assert!(files.all(|file| !file.unwrap().path().to_string_lossy().ends_with(".tmp")));
+
+        // 解析结果的数量有上限
+        let first = cache.index("acme/a", &body("acme/a")).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.index("acme/a", &body("acme/a")).unwrap()));
+        cache.index("acme/b", &body("acme/b")).unwrap();
+        cache.index("acme/c", &body("acme/c")).unwrap();
+        assert!(!Arc::ptr_eq(&first, &cache.index("acme/a", &body("acme/a")).unwrap()));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
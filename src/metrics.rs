@@ -0,0 +1,270 @@
+use axum::{
+    http::{HeaderMap, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use reqwest::{StatusCode, Url};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::journal::RouteDecision;
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub http_requests_in_flight: IntGaugeVec,
+    pub dist_decisions: IntCounterVec,
+    pub cache_lookups: IntCounterVec,
+    pub speed_test_wins: IntCounterVec,
+    pub qiniu_uploads: IntCounterVec,
+    pub qiniu_upload_bytes: IntCounter,
+}
+
+impl Metrics {
+    pub fn global() -> &'static Metrics {
+        METRICS.get_or_init(Metrics::new)
+    }
+
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("composer_mirror".to_string()), None).unwrap();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency by route",
+            ),
+            &["route"],
+        )
+        .unwrap();
+        let http_requests_in_flight = IntGaugeVec::new(
+            Opts::new("http_requests_in_flight", "HTTP requests being served"),
+            &["route"],
+        )
+        .unwrap();
+        let dist_decisions = IntCounterVec::new(
+            Opts::new(
+                "dist_decisions_total",
+                "Upstream chosen by dist_dispatcher",
+            ),
+            &["decision"],
+        )
+        .unwrap();
+        let cache_lookups = IntCounterVec::new(
+            Opts::new("cache_lookups_total", "Cache lookups by cache and result"),
+            &["cache", "result"],
+        )
+        .unwrap();
+        let speed_test_wins = IntCounterVec::new(
+            Opts::new(
+                "speed_test_wins_total",
+                "Speed test winners by accelerator",
+            ),
+            &["accelerator"],
+        )
+        .unwrap();
+        let qiniu_uploads = IntCounterVec::new(
+            Opts::new("qiniu_uploads_total", "Qiniu uploads by result"),
+            &["result"],
+        )
+        .unwrap();
+        let qiniu_upload_bytes = IntCounter::new(
+            "qiniu_upload_bytes_total",
+            "Bytes uploaded to Qiniu",
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(http_requests_in_flight.clone()))
+            .unwrap();
+        registry.register(Box::new(dist_decisions.clone())).unwrap();
+        registry.register(Box::new(cache_lookups.clone())).unwrap();
+        registry.register(Box::new(speed_test_wins.clone())).unwrap();
+        registry.register(Box::new(qiniu_uploads.clone())).unwrap();
+        registry
+            .register(Box::new(qiniu_upload_bytes.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            http_requests_in_flight,
+            dist_decisions,
+            cache_lookups,
+            speed_test_wins,
+            qiniu_uploads,
+            qiniu_upload_bytes,
+        }
+    }
+
+    pub fn record_cache_lookup(&self, cache: &str, hit: bool) {
+        let result = match hit {
+            true => "hit",
+            false => "miss",
+        };
+        self.cache_lookups.with_label_values(&[cache, result]).inc();
+    }
+
+    // 加速地址形如 https://ghps.cc/https://github.com/...，只取加速站点的域名作为标签
+    pub fn record_speed_test_win(&self, url: &str) {
+        let accelerator = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_else(|| "unknown".to_string());
+        self.speed_test_wins
+            .with_label_values(&[&accelerator])
+            .inc();
+    }
+
+    pub fn record_qiniu_upload(&self, success: bool, bytes: usize) {
+        match success {
+            true => {
+                self.qiniu_uploads.with_label_values(&["success"]).inc();
+                self.qiniu_upload_bytes.inc_by(bytes as u64);
+            }
+            false => self.qiniu_uploads.with_label_values(&["failure"]).inc(),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+pub fn route_label(path: &str) -> &'static str {
+    if path.starts_with("/p2/") {
+        "/p2"
+    } else if path.starts_with("/dists/") {
+        "/dists"
+    } else if path.starts_with("/git/") {
+        "/git"
+    } else if path == "/packages/list.json" || path == "/search.json" {
+        "/search"
+    } else if path == "/api/security-advisories/" {
+        "/api/security-advisories"
+    } else if path == "/downloads/" {
+        "/downloads"
+    } else if path == "/packages.json" {
+        "/packages.json"
+    } else if path == "/status" {
+        "/status"
+    } else if path == "/metrics" {
+        "/metrics"
+    } else {
+        "other"
+    }
+}
+
+// 正在处理的请求数，请求结束或被取消（客户端断开时 future 被丢弃）时都会减一
+struct InFlight(IntGauge);
+
+impl InFlight {
+    fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+pub async fn track<B>(request: Request<B>, next: Next<B>) -> Response {
+    let metrics = Metrics::global();
+    let route = route_label(request.uri().path());
+    let start = Instant::now();
+
+    let in_flight = InFlight::new(metrics.http_requests_in_flight.with_label_values(&[route]));
+    let response = next.run(request).await;
+    drop(in_flight);
+
+    metrics
+        .http_request_duration
+        .with_label_values(&[route])
+        .observe(start.elapsed().as_secs_f64());
+    metrics
+        .http_requests
+        .with_label_values(&[route, response.status().as_str()])
+        .inc();
+    if let Some(RouteDecision(decision)) = response.extensions().get::<RouteDecision>() {
+        if route == "/dists" {
+            metrics.dist_decisions.with_label_values(&[decision]).inc();
+        }
+    }
+
+    response
+}
+
+pub async fn render() -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    (StatusCode::OK, headers, Metrics::global().render()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_label_test() {
+        assert_eq!("/p2", route_label("/p2/tiderjian/think-core.json"));
+        assert_eq!(
+            "/dists",
+            route_label("/dists/nesbot/carbon/2.70.0/d3298b38ea8612e5f77d38d1a99438e42f70341d.zip")
+        );
+        assert_eq!("/packages.json", route_label("/packages.json"));
+        assert_eq!("other", route_label("/favicon.ico"));
+    }
+
+    #[test]
+    fn in_flight_test() {
+        let gauge = IntGauge::new("in_flight_test", "test").unwrap();
+        let in_flight = InFlight::new(gauge.clone());
+        assert_eq!(1, gauge.get());
+        drop(in_flight);
+        assert_eq!(0, gauge.get());
+    }
+
+    #[tokio::test]
+    async fn cancelled_in_flight_test() {
+        let gauge = IntGauge::new("cancelled_in_flight_test", "test").unwrap();
+        let task = {
+            let gauge = gauge.clone();
+            tokio::spawn(async move {
+                let _in_flight = InFlight::new(gauge);
+                std::future::pending::<()>().await;
+            })
+        };
+        while gauge.get() == 0 {
+            tokio::task::yield_now().await;
+        }
+        task.abort();
+        let _ = task.await;
+        assert_eq!(0, gauge.get());
+    }
+}
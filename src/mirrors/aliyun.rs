@@ -1,32 +1,51 @@
+use async_trait::async_trait;
 use axum::{
     http::{HeaderMap, HeaderName, HeaderValue},
     response::{IntoResponse, Response},
 };
-use reqwest::{Client, StatusCode};
+use reqwest::StatusCode;
 
 use crate::dist::Dist;
+use crate::mirrors::availability::DistAvailability;
+use crate::mirrors::mirror::Mirror;
 //use crate::package::Package;
 
 #[derive(Clone)]
-pub struct Aliyun<'a> {
-    //packages_meta_url_template: &'a str,
-    dist_url_template: &'a str,
+pub struct Aliyun {
+    //packages_meta_url_template: String,
+    dist_url_template: String,
 }
 
-impl<'a> Aliyun<'a> {
+impl Aliyun {
     pub fn new() -> Self {
+        Self::with_base_url("https://mirrors.aliyun.com/composer")
+    }
+
+    pub fn with_base_url(base_url: &str) -> Self {
         Self {
-            //packages_meta_url_template: "https://mirrors.aliyun.com/composer/p2/%package%.json",
-            dist_url_template:
-                "https://mirrors.aliyun.com/composer/dists/%package%/%reference%.%dist_type%",
+            //packages_meta_url_template: format!("{}/p2/%package%.json", base_url),
+            dist_url_template: format!("{}/dists/%package%/%reference%.%dist_type%", base_url),
         }
     }
+}
 
-    pub fn get_dist_url(&self, dist: &Dist) -> String {
+impl Default for Aliyun {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Mirror for Aliyun {
+    fn name(&self) -> &'static str {
+        "aliyun"
+    }
+
+    fn get_dist_url(&self, dist: &Dist) -> String {
         self.dist_url_template
             .replace("%package%", &dist.package.full_name)
-            .replace("%reference%", &dist.reference)
-            .replace("%dist_type%", &dist.dist_type)
+            .replace("%reference%", dist.reference)
+            .replace("%dist_type%", dist.dist_type)
     }
 
     // pub async fn make_package_response(&self, package: &Package<'a>) -> Response {
@@ -41,23 +60,12 @@ impl<'a> Aliyun<'a> {
     //     (StatusCode::TEMPORARY_REDIRECT, headers, "").into_response()
     // }
 
-    pub async fn check_dist(&self, dist: &Dist<'a>) -> bool {
+    async fn check_dist(&self, dist: &Dist) -> bool {
         let url = self.get_dist_url(dist);
-        let client = Client::new();
-        let response = client.head(&url).send().await;
-        match response {
-            Ok(response) => {
-                if response.status() == StatusCode::OK {
-                    true
-                } else {
-                    false
-                }
-            }
-            Err(_) => false,
-        }
+        DistAvailability::global().check("aliyun", &url).await
     }
 
-    pub async fn make_dist_response(&self, dist: &Dist<'a>) -> Response {
+    async fn make_dist_response(&self, dist: &Dist) -> Response {
         let url = self.get_dist_url(dist);
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -0,0 +1,94 @@
+use glob::Pattern;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::metrics::Metrics;
+use crate::request_helper;
+use crate::ttl_cache::TtlCache;
+
+static DIST_AVAILABILITY: OnceLock<DistAvailability> = OnceLock::new();
+
+// 记录各镜像上 dist 是否存在，命中缓存时跳过 HEAD 请求
+pub struct DistAvailability {
+    cache: TtlCache<(String, String), bool>,
+    positive_ttl: Duration,
+    negative_ttl: Duration,
+}
+
+impl DistAvailability {
+    pub fn global() -> &'static DistAvailability {
+        DIST_AVAILABILITY.get_or_init(DistAvailability::from_env)
+    }
+
+    fn from_env() -> Self {
+        let env_or = |key: &str, default: u64| {
+            env::var(key)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            cache: TtlCache::new(env_or("DIST_CHECK_CACHE_SIZE", 100000) as usize),
+            positive_ttl: Duration::from_secs(env_or("DIST_CHECK_POSITIVE_TTL", 86400)),
+            negative_ttl: Duration::from_secs(env_or("DIST_CHECK_NEGATIVE_TTL", 600)),
+        }
+    }
+
+    // 镜像 dist 地址中都包含 /vendor/package/
+    fn url_matches(url: &str, package: &Pattern) -> bool {
+        let segments = url.split('/').collect::<Vec<&str>>();
+        segments
+            .windows(2)
+            .any(|pair| package.matches(&format!("{}/{}", pair[0], pair[1])))
+    }
+
+    pub fn entries(&self, package: &Pattern) -> Vec<Value> {
+        self.cache
+            .entries()
+            .into_iter()
+            .filter(|((_, url), _, _)| Self::url_matches(url, package))
+            .map(|((mirror, url), available, expires_in)| {
+                json!({
+                    "mirror": mirror,
+                    "url": url,
+                    "available": available,
+                    "expires_in_secs": expires_in.as_secs(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn purge(&self, package: &Pattern) -> usize {
+        self.cache
+            .remove_where(|(_, url)| Self::url_matches(url, package))
+    }
+
+    pub async fn check(&self, mirror: &str, url: &str) -> bool {
+        let key = (mirror.to_string(), url.to_string());
+        let cached = self.cache.get(&key);
+        Metrics::global().record_cache_lookup("dist_availability", cached.is_some());
+        if let Some(available) = cached {
+            return available;
+        }
+
+        match request_helper::head(url).await {
+            Ok(response) => {
+                let available = response.status() == StatusCode::OK;
+                // 上游 5xx 不代表 dist 不存在，不缓存
+                if !response.status().is_server_error() {
+                    let ttl = match available {
+                        true => self.positive_ttl,
+                        false => self.negative_ttl,
+                    };
+                    self.cache.insert(key, available, ttl);
+                }
+                available
+            }
+            Err(_) => false,
+        }
+    }
+}
@@ -4,9 +4,20 @@ use axum::response::Response;
 use crate::dist::Dist;
 use crate::package::Package;
 
+// 非白名单扩展依次尝试的第三方镜像
 #[async_trait]
-pub trait Mirror {
-    async fn make_package_response(&self, package: &Package) -> Response;
+pub trait Mirror: Send + Sync {
+    // 记录在请求日志和监控中的路由决策名称
+    fn name(&self) -> &'static str;
+
+    fn get_dist_url(&self, dist: &Dist) -> String;
+
+    // 返回 None 表示该镜像不提供元数据，交给下一个镜像处理
+    async fn make_package_response(&self, _package: &Package) -> Option<Response> {
+        None
+    }
+
     async fn check_dist(&self, dist: &Dist) -> bool;
+
     async fn make_dist_response(&self, dist: &Dist) -> Response;
 }
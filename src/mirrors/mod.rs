@@ -1,3 +1,5 @@
 pub mod aliyun;
-pub mod packagist;
+pub(crate) mod availability;
+pub mod mirror;
+pub(crate) mod packagist;
 pub mod tencent;
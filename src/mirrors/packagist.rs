@@ -1,12 +1,19 @@
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
+use reqwest::StatusCode;
 
-use std::env;
+use std::sync::Arc;
 
 mod packagist_strategy;
 
 use crate::dist::Dist;
+use crate::git_mirror;
+use crate::lookup_error::NegativeCache;
+use crate::metadata_cache::MetadataCache;
+use crate::mirrors::mirror::Mirror;
 use crate::package::Package;
 use crate::request_helper;
+use crate::storage::Storage;
+use crate::{Config, PackagistStrategy};
 
 use self::packagist_strategy::{
     cache_third_site::CacheThirdSiteStrategy, storage_self::StorageSelfStrategy,
@@ -15,33 +22,86 @@ use self::packagist_strategy::{
 #[derive(Clone)]
 pub struct Packagist{
     packages_meta_url_template: String,
+    strategy: PackagistStrategy,
+    cache_site_list: Vec<String>,
+    mirrors: Vec<Arc<dyn Mirror>>,
+    storage: Option<Arc<dyn Storage>>,
+    git_mirror_url: Option<String>,
+    package_white_list: Vec<String>,
 }
 
 impl Packagist{
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         Self {
-            packages_meta_url_template: env::var("PACKAGES_META_URL_TEMPLATE").unwrap(),
+            packages_meta_url_template: config.packages_meta_url_template.clone(),
+            strategy: config.strategy,
+            cache_site_list: config.cache_site_list.clone(),
+            mirrors: config.mirrors.clone(),
+            storage: config.storage.clone(),
+            git_mirror_url: config.git_mirror_url.clone(),
+            package_white_list: config.package_white_list.clone(),
         }
     }
 
+    // 未配置自有存储时返回错误，MirrorServerBuilder::build 会提前检查
+    fn storage(&self) -> Result<&dyn Storage, String> {
+        self.storage
+            .as_deref()
+            .ok_or_else(|| "PACKAGIST_STRATEGY=1 requires a storage backend".to_string())
+    }
+
+    pub fn get_package_meta_url(&self, package: &str) -> String {
+        self.packages_meta_url_template.replace("%package%", package)
+    }
+
     pub async fn make_package_response<'a>(&self, package: &Package<'a>) -> Response {
-        let url = self
-            .packages_meta_url_template
-            .replace("%package%", &package.full_name);
-        request_helper::proxy(&url).await
+        let url = self.get_package_meta_url(&package.full_name);
+        let negative_cache = NegativeCache::global();
+        if let Some(err) = negative_cache.get(&package.full_name, None) {
+            return err.into_response();
+        }
+
+        match MetadataCache::global().fetch(&package.full_name, &url).await {
+            Ok(body) => match &self.git_mirror_url {
+                Some(base_url) => request_helper::json_response(
+                    git_mirror::rewrite_source_urls(&body, base_url, &self.package_white_list)
+                        .unwrap_or(body),
+                ),
+                None => request_helper::json_response(body),
+            },
+            Err(err) => {
+                negative_cache.insert(&err);
+                err.into_response()
+            }
+        }
     }
 
-    pub async fn make_dist_response<'a>(&self, dist: &Dist<'a>) -> Response {
-        let strategy: i32 = env::var("PACKAGIST_STRATEGY").unwrap().parse().unwrap();
+    pub async fn reupload_dist<'a>(&self, dist: &Dist<'a>) -> Result<String, String> {
+        StorageSelfStrategy::new(dist, self.packages_meta_url_template.to_string(), self.storage()?)
+            .reupload()
+            .await
+    }
 
-        match strategy {
-            1 => {
-                StorageSelfStrategy::new(dist, self.packages_meta_url_template.to_string())
-                    .run()
-                    .await
+    pub async fn make_dist_response<'a>(&self, dist: &Dist<'a>) -> Response {
+        match self.strategy {
+            PackagistStrategy::StorageSelf => match self.storage() {
+                Ok(storage) => {
+                    StorageSelfStrategy::new(dist, self.packages_meta_url_template.to_string(), storage)
+                        .run()
+                        .await
+                }
+                Err(message) => (StatusCode::SERVICE_UNAVAILABLE, message).into_response(),
+            },
+            PackagistStrategy::CacheThirdSite => {
+                CacheThirdSiteStrategy::new(
+                    dist,
+                    self.packages_meta_url_template.to_string(),
+                    &self.cache_site_list,
+                    &self.mirrors,
+                )
+                .run()
+                .await
             }
-            2 => CacheThirdSiteStrategy::new(dist, self.packages_meta_url_template.to_string()).run().await,
-            _ => panic!("Unknown strategy"),
         }
     }
 }
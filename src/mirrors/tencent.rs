@@ -1,36 +1,56 @@
+use async_trait::async_trait;
 use axum::{
     http::{HeaderMap, HeaderName, HeaderValue},
     response::{IntoResponse, Response},
 };
-use reqwest::{Client, StatusCode};
+use reqwest::StatusCode;
 
 use crate::dist::Dist;
+use crate::mirrors::availability::DistAvailability;
+use crate::mirrors::mirror::Mirror;
 use crate::package::Package;
 
 #[derive(Clone)]
-pub struct Tencent<'a> {
-    packages_meta_url_template: &'a str,
-    dist_url_template: &'a str,
+pub struct Tencent {
+    packages_meta_url_template: String,
+    dist_url_template: String,
 }
 
-impl<'a> Tencent<'a> {
+impl Tencent {
     pub fn new() -> Self {
+        Self::with_base_url("https://mirrors.cloud.tencent.com/repository/composer")
+    }
+
+    pub fn with_base_url(base_url: &str) -> Self {
         Self {
-            packages_meta_url_template: "https://mirrors.cloud.tencent.com/repository/composer/p/%package%.json",
-            dist_url_template: "https://mirrors.cloud.tencent.com/repository/composer/%package%/%version%/%combine%.%dist_type%"
+            packages_meta_url_template: format!("{}/p/%package%.json", base_url),
+            dist_url_template: format!("{}/%package%/%version%/%combine%.%dist_type%", base_url),
         }
     }
+}
+
+impl Default for Tencent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Mirror for Tencent {
+    fn name(&self) -> &'static str {
+        "tencent"
+    }
 
-    pub fn get_dist_url(&self, dist: &Dist) -> String {
+    fn get_dist_url(&self, dist: &Dist) -> String {
         let combine = format!("{}/{}", dist.package.full_name, dist.version).replace("/", "-");
         self.dist_url_template
             .replace("%package%", &dist.package.full_name)
-            .replace("%version%", &dist.version)
+            .replace("%version%", dist.version)
             .replace("%combine%", &combine)
-            .replace("%dist_type%", &dist.dist_type)
+            .replace("%dist_type%", dist.dist_type)
     }
 
-    pub async fn make_package_response(&self, package: &Package<'a>) -> Response {
+    async fn make_package_response(&self, package: &Package) -> Option<Response> {
         let url = self
             .packages_meta_url_template
             .replace("%package%", &package.full_name);
@@ -39,26 +59,15 @@ impl<'a> Tencent<'a> {
             HeaderName::from_static("location"),
             HeaderValue::try_from(url).unwrap(),
         );
-        (StatusCode::TEMPORARY_REDIRECT, headers, "").into_response()
+        Some((StatusCode::TEMPORARY_REDIRECT, headers, "").into_response())
     }
 
-    pub async fn check_dist(&self, dist: &Dist<'a>) -> bool {
+    async fn check_dist(&self, dist: &Dist) -> bool {
         let url = self.get_dist_url(dist);
-        let client = Client::new();
-        let response = client.head(&url).send().await;
-        match response {
-            Ok(response) => {
-                if response.status() == StatusCode::OK {
-                    true
-                } else {
-                    false
-                }
-            }
-            Err(_) => false,
-        }
+        DistAvailability::global().check("tencent", &url).await
     }
 
-    pub async fn make_dist_response(&self, dist: &Dist<'a>) -> Response {
+    async fn make_dist_response(&self, dist: &Dist) -> Response {
         let url = self.get_dist_url(dist);
         let mut headers = HeaderMap::new();
         headers.insert(
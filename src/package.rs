@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 pub struct Package<'a> {
     pub vendor: &'a str,
     pub package: &'a str,
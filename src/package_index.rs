@@ -0,0 +1,393 @@
+use axum::{
+    extract::Query,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use glob::Pattern;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::auth::{Credentials, Token};
+use crate::hosted::HostedPackages;
+use crate::metadata::PackageMetadata;
+use crate::metadata_cache::MetadataCache;
+use crate::request_helper;
+use crate::{check_package_in_white_list, Config};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageList {
+    package_names: Vec<String>,
+}
+
+// 已缓存元数据的扩展和托管扩展，包名 => (描述, 源码地址)
+type LocalPackages = HashMap<String, (String, String)>;
+
+// 包名索引：packagist 的 list.json、已缓存元数据的扩展以及私有扩展，
+// 用于在本地提供 /packages/list.json 和 /search.json，请求时只读内存中的索引
+pub struct PackageIndex {
+    path: PathBuf,
+    list_url: String,
+    // packagist 的全部包名，定时刷新并保存到 path
+    names: Mutex<Arc<Vec<String>>>,
+    // 第一次请求时建立，之后按 PACKAGE_INDEX_LOCAL_REFRESH_SECS 定时重建
+    local: Mutex<Option<Arc<LocalPackages>>>,
+}
+
+impl PackageIndex {
+    pub fn new(path: impl Into<PathBuf>, list_url: &str) -> Self {
+        let path = path.into();
+        let names = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<PackageList>(&content).ok())
+            .map(|list| list.package_names)
+            .unwrap_or_default();
+        Self {
+            path,
+            list_url: list_url.to_string(),
+            names: Mutex::new(Arc::new(names)),
+            local: Mutex::new(None),
+        }
+    }
+
+    // 未设置 PACKAGE_INDEX_PATH 时不开启
+    pub fn from_env() -> Option<Self> {
+        let path = env::var("PACKAGE_INDEX_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())?;
+        let list_url = env::var("PACKAGIST_LIST_URL")
+            .unwrap_or_else(|_| "https://packagist.org/packages/list.json".to_string());
+        Some(Self::new(path, &list_url))
+    }
+
+    // 从 packagist 拉取全部包名，失败时继续使用上次的结果
+    pub async fn refresh(&self) -> Result<usize, String> {
+        let response = request_helper::try_get(&self.list_url)
+            .await
+            .map_err(|err| format!("{}: {}", self.list_url, err))?;
+        if !response.status().is_success() {
+            return Err(format!("{} responded {}", self.list_url, response.status()));
+        }
+        let list = response
+            .json::<PackageList>()
+            .await
+            .map_err(|err| format!("{}: {}", self.list_url, err))?;
+        let names = list
+            .package_names
+            .into_iter()
+            .map(|name| name.to_lowercase())
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect::<Vec<String>>();
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, json!({ "packageNames": names }).to_string())
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| format!("failed to write {}: {}", self.path.display(), err))?;
+        let count = names.len();
+        *self.names.lock().unwrap() = Arc::new(names);
+        Ok(count)
+    }
+
+    // 重新读取已缓存元数据的扩展和托管扩展，返回扩展数
+    pub async fn refresh_local(&self, hosted: &HostedPackages) -> usize {
+        let mut local = LocalPackages::new();
+        let mut cached = Vec::new();
+        for cache in MetadataCache::sources().await {
+            cached.extend(cache.cached_packages().await);
+        }
+        let hosted_names = hosted.packages().await.into_iter().map(|(name, _)| name);
+        for name in cached.into_iter().chain(hosted_names) {
+            if let Entry::Vacant(entry) = local.entry(name) {
+                let details = describe(hosted, entry.key()).await;
+                entry.insert(details);
+            }
+        }
+        let count = local.len();
+        *self.local.lock().unwrap() = Some(Arc::new(local));
+        count
+    }
+
+    // 托管扩展变化后调用，下次请求时重建
+    pub fn invalidate_local(&self) {
+        *self.local.lock().unwrap() = None;
+    }
+
+    async fn local(&self, hosted: &HostedPackages) -> Arc<LocalPackages> {
+        if let Some(local) = self.local.lock().unwrap().clone() {
+            return local;
+        }
+        self.refresh_local(hosted).await;
+        self.local.lock().unwrap().clone().unwrap_or_default()
+    }
+
+    // 描述和源码地址，packagist 上未缓存的扩展只有包名
+    async fn details(&self, hosted: &HostedPackages, name: &str) -> (String, String) {
+        self.local(hosted)
+            .await
+            .get(name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // 满足 predicate 的包名，私有扩展只对令牌范围内的请求可见
+    pub async fn matching(
+        &self,
+        config: &Config,
+        token: Option<&Token>,
+        predicate: impl Fn(&str) -> bool,
+    ) -> BTreeSet<String> {
+        let packagist = self.names.lock().unwrap().clone();
+        let mut names = packagist
+            .iter()
+            .filter(|name| predicate(name))
+            .cloned()
+            .collect::<BTreeSet<String>>();
+        let local = self.local(&config.hosted).await;
+        names.extend(local.keys().filter(|name| predicate(name)).cloned());
+        names.retain(|name| {
+            !check_package_in_white_list(name, &config.private_packages)
+                || token.is_some_and(|token| token.allows(name))
+        });
+        names
+    }
+}
+
+// 按 PACKAGE_INDEX_REFRESH_SECS 定时刷新 packagist 的包名，默认每天一次；
+// 按 PACKAGE_INDEX_LOCAL_REFRESH_SECS 定时重建本地扩展的索引，默认 300 秒
+pub fn spawn(index: Arc<PackageIndex>, hosted: Arc<HostedPackages>) {
+    let interval = env::var("PACKAGE_INDEX_REFRESH_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(86400);
+    let local_interval = env::var("PACKAGE_INDEX_LOCAL_REFRESH_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(300);
+    let local_index = index.clone();
+    tokio::spawn(async move {
+        loop {
+            local_index.refresh_local(&hosted).await;
+            sleep(Duration::from_secs(local_interval)).await;
+        }
+    });
+    tokio::spawn(async move {
+        loop {
+            match index.refresh().await {
+                Ok(count) => println!("package index refreshed: {} packages", count),
+                Err(err) => eprintln!("failed to refresh package index: {}", err),
+            }
+            sleep(Duration::from_secs(interval)).await;
+        }
+    });
+}
+
+fn not_enabled() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": "package index is not enabled" })),
+    )
+        .into_response()
+}
+
+fn token(config: &Config, headers: &HeaderMap) -> Option<Token> {
+    Credentials::from_headers(headers)
+        .and_then(|credentials| config.tokens.authenticate(&credentials))
+}
+
+// /packages/list.json?vendor=acme 或 ?filter=acme/*，与 packagist 相同
+pub async fn list(
+    config: Extension<Config>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let index = match &config.package_index {
+        Some(index) => index,
+        None => return not_enabled(),
+    };
+    let vendor = params
+        .get("vendor")
+        .map(|vendor| format!("{}/", vendor.to_lowercase()));
+    let filter = match params
+        .get("filter")
+        .map(|filter| Pattern::new(&filter.to_lowercase()))
+    {
+        Some(Ok(filter)) => Some(filter),
+        Some(Err(err)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": err.to_string() })),
+            )
+                .into_response()
+        }
+        None => None,
+    };
+
+    let names = index
+        .matching(&config, token(&config, &headers).as_ref(), |name| {
+            vendor
+                .as_ref()
+                .is_none_or(|vendor| name.starts_with(vendor))
+                && filter.as_ref().is_none_or(|filter| filter.matches(name))
+        })
+        .await
+        .into_iter()
+        .collect::<Vec<String>>();
+    Json(json!({ "packageNames": names })).into_response()
+}
+
+// 描述和源码地址取自托管扩展或已缓存的元数据
+async fn describe(hosted: &HostedPackages, name: &str) -> (String, String) {
+    let mut body = hosted.metadata(name).await;
+    if body.is_none() {
+        for cache in MetadataCache::sources().await {
+            body = cache.get(name).await;
+            if body.is_some() {
+                break;
+            }
+        }
+    }
+    let metadata = body.and_then(|body| PackageMetadata::parse(name, &body).ok());
+    let version = metadata
+        .as_ref()
+        .and_then(|metadata| metadata.versions.first());
+    let description = version
+        .and_then(|version| version.description.clone())
+        .unwrap_or_default();
+    let repository = version
+        .and_then(|version| version.source.as_ref())
+        .map(|source| source.repository_url().to_string())
+        .unwrap_or_default();
+    (description, repository)
+}
+
+// 搜索结果排序：包名完全相同、以关键词开头、其余按字母顺序
+fn rank(name: &str, query: &str) -> u8 {
+    if name == query {
+        0
+    } else if name.starts_with(query)
+        || name
+            .split_once('/')
+            .is_some_and(|(_, n)| n.starts_with(query))
+    {
+        1
+    } else {
+        2
+    }
+}
+
+// /search.json?q=monolog&page=1&per_page=15，按空格分隔的关键词都出现在包名中即匹配
+pub async fn search(
+    config: Extension<Config>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let index = match &config.package_index {
+        Some(index) => index,
+        None => return not_enabled(),
+    };
+    let query = params
+        .get("q")
+        .map(|q| q.trim().to_lowercase())
+        .unwrap_or_default();
+    let terms = query.split_whitespace().collect::<Vec<&str>>();
+    let page = params
+        .get("page")
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|page| *page > 0)
+        .unwrap_or(1);
+    let per_page = params
+        .get("per_page")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(15)
+        .clamp(1, 100);
+
+    let mut matches = index
+        .matching(&config, token(&config, &headers).as_ref(), |name| {
+            terms.iter().all(|term| name.contains(term))
+        })
+        .await
+        .into_iter()
+        .collect::<Vec<String>>();
+    matches.sort_by_key(|name| rank(name, &query));
+
+    let total = matches.len();
+    let mut results = Vec::new();
+    let skip = (page - 1).saturating_mul(per_page);
+    for name in matches.iter().skip(skip).take(per_page) {
+        let (description, repository) = index.details(&config.hosted, name).await;
+        let downloads = config
+            .downloads
+            .as_ref()
+            .map(|downloads| downloads.total(name))
+            .unwrap_or(0);
+        // 私有扩展没有 packagist 页面
+        let url = match check_package_in_white_list(name, &config.private_packages) {
+            true => String::new(),
+            false => format!("https://packagist.org/packages/{}", name),
+        };
+        results.push(json!({
+            "name": name,
+            "description": description,
+            "url": url,
+            "repository": repository,
+            "downloads": downloads,
+            "favers": 0,
+        }));
+    }
+
+    let mut body = json!({ "results": results, "total": total });
+    if page.saturating_mul(per_page) < total {
+        let next = Url::parse_with_params(
+            "http://localhost/search.json",
+            &[
+                ("q", query.clone()),
+                ("page", (page + 1).to_string()),
+                ("per_page", per_page.to_string()),
+            ],
+        )
+        .unwrap();
+        body["next"] = json!(format!("/search.json?{}", next.query().unwrap_or_default()));
+    }
+    Json(body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_test() {
+        let mut names = vec![
+            "acme/logger-monolog",
+            "monolog/monolog",
+            "acme/monolog-bridge",
+        ];
+        names.sort_by_key(|name| rank(name, "monolog/monolog"));
+        assert_eq!("monolog/monolog", names[0]);
+        names.sort_by_key(|name| rank(name, "monolog"));
+        assert_eq!(
+            vec![
+                "monolog/monolog",
+                "acme/monolog-bridge",
+                "acme/logger-monolog"
+            ],
+            names
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{
+    extract::ConnectInfo,
+    http::{header, HeaderMap},
+    response::Response,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use crate::journal::is_trusted_proxy;
+use crate::request_helper;
+use crate::{Config, PublicUrl};
+
+static STARTED_AT: OnceLock<SystemTime> = OnceLock::new();
+
+// packages.json 文件中原样保留的字段，其余字段由配置生成
+const PASSTHROUGH_KEYS: [&str; 4] = ["info", "warning", "warning-versions", "security-advisories"];
+// 本服务未开启对应功能时沿用上游的值
+const FALLBACK_KEYS: [&str; 3] = ["notify-batch", "list", "search"];
+
+// 服务启动时调用，作为 last-update 的下限
+pub(crate) fn init() {
+    STARTED_AT.get_or_init(SystemTime::now);
+}
+
+// 生成地址的前缀，为空时使用相对地址；Host 和 X-Forwarded-Proto 只采用 TRUSTED_PROXIES 转发的请求
+pub(crate) fn base_url(
+    config: &Config,
+    headers: &HeaderMap,
+    connect_info: Option<&ConnectInfo<SocketAddr>>,
+) -> String {
+    match &config.public_url {
+        PublicUrl::Relative => String::new(),
+        PublicUrl::Fixed(url) => url.clone(),
+        PublicUrl::FromHost if is_trusted_proxy(connect_info) => {
+            host_url(headers).unwrap_or_default()
+        }
+        PublicUrl::FromHost => String::new(),
+    }
+}
+
+fn host_url(headers: &HeaderMap) -> Option<String> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let host = header(header::HOST.as_str())?;
+    Some(format!(
+        "{}://{}",
+        header("x-forwarded-proto").unwrap_or("http"),
+        host
+    ))
+}
+
+// 私有仓库只声明自己提供的扩展，composer 不会再为其它扩展请求本服务
+async fn available_package_patterns(config: &Config) -> Vec<String> {
+    let mut patterns = config.private_packages.clone();
+    for repository in config.repositories.iter() {
+        patterns.extend(repository.packages.iter().cloned());
+    }
+    for (package, _) in config.hosted.packages().await {
+        if !crate::check_package_in_white_list(&package, &patterns) {
+            patterns.push(package);
+        }
+    }
+    patterns.dedup();
+    patterns
+}
+
+// 托管扩展最近一次变更的时间，没有托管扩展时为服务启动时间
+async fn last_update(config: &Config) -> DateTime<Utc> {
+    let started_at = *STARTED_AT.get_or_init(SystemTime::now);
+    config
+        .hosted
+        .packages()
+        .await
+        .into_iter()
+        .map(|(_, modified)| modified)
+        .fold(started_at, |latest, modified| latest.max(modified))
+        .into()
+}
+
+async fn document(config: &Config, base_url: &str) -> Map<String, Value> {
+    let upstream = serde_json::from_str::<Map<String, Value>>(&config.packages).unwrap_or_default();
+    let mut root = upstream
+        .iter()
+        .filter(|(key, _)| {
+            PASSTHROUGH_KEYS.contains(&key.as_str()) || FALLBACK_KEYS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<Map<String, Value>>();
+
+    root.insert("packages".to_string(), json!([]));
+    root.insert(
+        "metadata-url".to_string(),
+        json!(format!("{}/p2/%package%.json", base_url)),
+    );
+    root.insert(
+        "mirrors".to_string(),
+        json!([{
+            "dist-url": format!("{}/dists/%package%/%prettyVersion%/%reference%.%type%", base_url),
+            "preferred": true,
+        }]),
+    );
+    if config.downloads.is_some() {
+        root.insert(
+            "notify-batch".to_string(),
+            json!(format!("{}/downloads/", base_url)),
+        );
+    }
+    if config.package_index.is_some() {
+        root.insert(
+            "list".to_string(),
+            json!(format!("{}/packages/list.json", base_url)),
+        );
+        root.insert(
+            "search".to_string(),
+            json!(format!("{}/search.json?q=%query%", base_url)),
+        );
+    }
+    if config.advisories.is_some() {
+        root.insert(
+            "security-advisories".to_string(),
+            json!({
+                "api-url": format!("{}/api/security-advisories/", base_url),
+                "metadata": false,
+                "query-all": true,
+            }),
+        );
+    }
+    if config.private_repository {
+        root.insert(
+            "available-package-patterns".to_string(),
+            json!(available_package_patterns(config).await),
+        );
+    }
+    root.insert(
+        "last-update".to_string(),
+        json!(last_update(config)
+            .await
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()),
+    );
+    root
+}
+
+pub async fn packages_meta(
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    config: Extension<Config>,
+) -> Response {
+    let base_url = base_url(&config, &headers, connect_info.as_ref());
+    let root = document(&config, &base_url).await;
+    request_helper::json_response(Value::Object(root).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[tokio::test]
+    async fn document_test() {
+        let mut config = Config::new(
+            r#"{"info":"hello","provider-includes":{},"notify-batch":"https://packagist.org/downloads/"}"#
+                .to_string(),
+            Vec::new(),
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("mirror.example.com"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        let client = ConnectInfo("203.0.113.1:50000".parse::<SocketAddr>().unwrap());
+        assert_eq!("", base_url(&config, &headers, Some(&client)));
+        // 不是可信代理转发的请求不采用 Host
+        config.public_url = PublicUrl::FromHost;
+        assert_eq!("", base_url(&config, &headers, Some(&client)));
+        assert_eq!("", base_url(&config, &headers, None));
+        assert_eq!(
+            Some("https://mirror.example.com".to_string()),
+            host_url(&headers)
+        );
+
+        config.private_packages = vec!["acme/*".to_string()];
+        config.private_repository = true;
+        let root = document(&config, "https://mirror.example.com").await;
+        assert_eq!("hello", root["info"]);
+        assert!(root.get("provider-includes").is_none());
+        // 未开启下载统计时沿用上游的 notify-batch
+        assert_eq!("https://packagist.org/downloads/", root["notify-batch"]);
+        assert_eq!(
+            "https://mirror.example.com/p2/%package%.json",
+            root["metadata-url"]
+        );
+        assert_eq!(json!(["acme/*"]), root["available-package-patterns"]);
+    }
+}
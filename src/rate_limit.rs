@@ -0,0 +1,124 @@
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static RATE_LIMITS: OnceLock<RateLimits> = OnceLock::new();
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quota {
+    pub limit: Option<u64>,
+    pub remaining: u64,
+    pub reset_at: u64,
+}
+
+impl Quota {
+    // 兼容 GitHub 的 X-RateLimit-* 和 GitLab 的 RateLimit-*
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                headers
+                    .get(*name)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+            })
+        };
+
+        let remaining = header(&["x-ratelimit-remaining", "ratelimit-remaining"])?;
+        let limit = header(&["x-ratelimit-limit", "ratelimit-limit"]);
+        let reset_at = header(&["x-ratelimit-reset", "ratelimit-reset"])
+            .or_else(|| header(&["retry-after"]).map(|secs| now() + secs))
+            .unwrap_or(0);
+
+        Some(Self {
+            limit,
+            remaining,
+            reset_at,
+        })
+    }
+
+    pub fn wait_time(&self) -> Duration {
+        Duration::from_secs(self.reset_at.saturating_sub(now()))
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0 && self.reset_at > now()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// 记录各个源站最近一次返回的剩余配额，供 /status 查看
+pub struct RateLimits {
+    quotas: Mutex<HashMap<String, Quota>>,
+}
+
+impl RateLimits {
+    pub fn global() -> &'static RateLimits {
+        RATE_LIMITS.get_or_init(|| RateLimits {
+            quotas: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn update(&self, host: &str, headers: &HeaderMap) -> Option<Quota> {
+        let quota = Quota::from_headers(headers)?;
+        self.quotas
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), quota.clone());
+        Some(quota)
+    }
+
+    pub fn get(&self, host: &str) -> Option<Quota> {
+        self.quotas.lock().unwrap().get(host).cloned()
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let quotas = self.quotas.lock().unwrap();
+        let mut res = serde_json::Map::new();
+        for (host, quota) in quotas.iter() {
+            res.insert(
+                host.clone(),
+                json!({
+                    "limit": quota.limit,
+                    "remaining": quota.remaining,
+                    "reset_at": quota.reset_at,
+                }),
+            );
+        }
+        Value::Object(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn quota_from_headers_test() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit", HeaderValue::from_static("60"));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("0"));
+        headers.insert("x-ratelimit-reset", HeaderValue::from_static("4102444800"));
+
+        let quota = Quota::from_headers(&headers).unwrap();
+        assert_eq!(Some(60), quota.limit);
+        assert_eq!(0, quota.remaining);
+        assert!(quota.is_exhausted());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("ratelimit-remaining", HeaderValue::from_static("1999"));
+        let quota = Quota::from_headers(&headers).unwrap();
+        assert_eq!(1999, quota.remaining);
+        assert!(!quota.is_exhausted());
+
+        assert_eq!(None, Quota::from_headers(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,235 @@
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::fs;
+use std::sync::Arc;
+
+use crate::credentials::Credential;
+use crate::dist::Dist;
+use crate::journal::StorageUploaded;
+use crate::last_error::LastErrors;
+use crate::lookup_error::{LookupError, NegativeCache};
+use crate::metadata_cache::MetadataCache;
+use crate::metrics::Metrics;
+use crate::request_helper;
+use crate::storage::Storage;
+
+// redirect: 直接跳转到上游元数据中的 dist 地址；storage: 下载后上传到自有存储
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistMode {
+    #[default]
+    Redirect,
+    Storage,
+}
+
+// 额外的上游 composer 仓库，packages 匹配的扩展从该仓库获取元数据和 dist
+#[derive(Clone, Debug, Deserialize)]
+pub struct Repository {
+    pub name: String,
+    // p2 元数据地址，%package% 会被替换成扩展名
+    pub url: String,
+    // 支持 * 泛型匹配，如 wpackagist-plugin/*
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub dist: DistMode,
+    // 写法与 ORIGIN_CREDENTIALS 相同，如 http-basic:user:password，同时用于元数据和 dist 的下载
+    #[serde(default)]
+    pub auth: Option<String>,
+}
+
+impl Repository {
+    pub fn new(name: &str, url: &str, packages: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            packages: packages.iter().map(|s| s.to_string()).collect(),
+            dist: DistMode::Redirect,
+            auth: None,
+        }
+    }
+
+    pub fn matches(&self, package: &str) -> bool {
+        crate::check_package_in_white_list(package, &self.packages)
+    }
+
+    fn credential(&self) -> Option<Credential> {
+        self.auth.as_deref().and_then(Credential::parse)
+    }
+
+    pub fn metadata_cache(&self) -> Arc<MetadataCache> {
+        MetadataCache::repository(&self.name)
+    }
+
+    pub fn metadata_url(&self, package: &str) -> String {
+        self.url.replace("%package%", package)
+    }
+
+    pub async fn make_package_response(&self, package: &str) -> Response {
+        let negative_cache = NegativeCache::global();
+        if let Some(err) = negative_cache.get(package, None) {
+            return err.into_response();
+        }
+
+        let url = self.metadata_url(package);
+        match self
+            .metadata_cache()
+            .fetch_with(package, &url, self.credential().as_ref())
+            .await
+        {
+            Ok(body) => request_helper::json_response(body),
+            Err(err) => {
+                negative_cache.insert(&err);
+                err.into_response()
+            }
+        }
+    }
+
+    // 忽略缓存有效期，立即从该仓库重新拉取元数据
+    pub async fn refresh(&self, package: &str) -> Result<String, LookupError> {
+        let url = self.metadata_url(package);
+        self.metadata_cache()
+            .refresh_with(package, &url, self.credential().as_ref())
+            .await
+    }
+
+    async fn origin_dist_url(&self, dist: &Dist<'_>) -> Result<String, LookupError> {
+        let package = &dist.package.full_name;
+        let url = self.metadata_url(package);
+        let cache = self.metadata_cache();
+        let body = cache
+            .fetch_with(package, &url, self.credential().as_ref())
+            .await?;
+        cache
+            .index(package, &body)?
+            .find(dist.version, dist.reference)
+            .and_then(|version| version.dist.as_ref())
+            .map(|dist| dist.url.clone())
+            .ok_or_else(|| LookupError::VersionNotFound {
+                package: package.to_string(),
+                version: dist.version.to_string(),
+                consulted: vec![url],
+            })
+    }
+
+    async fn upload(
+        &self,
+        storage: &dyn Storage,
+        object_name: &str,
+        origin: &str,
+    ) -> Result<(), String> {
+        let response = request_helper::get_origin_with(origin, self.credential().as_ref())
+            .await
+            .map_err(|err| format!("{}: {}", origin, err))?;
+        if !response.status().is_success() {
+            return Err(format!("{} responded {}", origin, response.status()));
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|err| format!("{}: {}", origin, err))?
+            .to_vec();
+        let size = bytes.len();
+        let res = storage.put(object_name, bytes).await;
+        Metrics::global().record_qiniu_upload(res.is_ok(), size);
+        res
+    }
+
+    pub async fn make_dist_response(
+        &self,
+        dist: &Dist<'_>,
+        storage: Option<&dyn Storage>,
+    ) -> Response {
+        let origin = match self.origin_dist_url(dist).await {
+            Ok(origin) => origin,
+            Err(err) => return err.into_response(),
+        };
+        let storage = match (self.dist, storage) {
+            // 需要认证的 dist 由本服务带上认证信息下载后转发，不把客户端跳转到源站
+            (DistMode::Redirect, _) if self.credential().is_some() => {
+                return match request_helper::get_origin_with(&origin, self.credential().as_ref())
+                    .await
+                {
+                    Ok(response) => request_helper::stream_response(response),
+                    Err(err) => (
+                        StatusCode::BAD_GATEWAY,
+                        Json(json!({ "error": format!("{}: {}", origin, err) })),
+                    )
+                        .into_response(),
+                };
+            }
+            (DistMode::Redirect, _) => return request_helper::redirect(&origin),
+            (DistMode::Storage, Some(storage)) => storage,
+            (DistMode::Storage, None) => {
+                let message = format!("repository {} requires a storage backend", self.name);
+                return (StatusCode::BAD_GATEWAY, Json(json!({ "error": message })))
+                    .into_response();
+            }
+        };
+
+        let object_name = dist.object_name();
+        if storage.exists(&object_name).await {
+            return request_helper::redirect(&storage.url(&object_name));
+        }
+        match self.upload(storage, &object_name, &origin).await {
+            Ok(()) => {
+                let mut response = request_helper::redirect(&storage.url(&object_name));
+                response.extensions_mut().insert(StorageUploaded);
+                response
+            }
+            Err(err) => {
+                LastErrors::global().record(&dist.package.full_name, err.clone());
+                (StatusCode::BAD_GATEWAY, Json(json!({ "error": err }))).into_response()
+            }
+        }
+    }
+}
+
+// 从 JSON 文件读取仓库列表，按顺序匹配
+pub fn load(path: &str) -> Result<Vec<Repository>, String> {
+    let content =
+        fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+    let repositories = serde_json::from_str::<Vec<Repository>>(&content)
+        .map_err(|err| format!("failed to parse {}: {}", path, err))?;
+    for repository in repositories.iter() {
+        if repository.auth.is_some() && repository.credential().is_none() {
+            return Err(format!("invalid auth of repository {}", repository.name));
+        }
+    }
+    Ok(repositories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repository_test() {
+        let repositories = serde_json::from_str::<Vec<Repository>>(
+            r#"[
+                {"name":"wpackagist","url":"https://wpackagist.org/p2/%package%.json","packages":["wpackagist-plugin/*","wpackagist-theme/*"]},
+                {"name":"partner","url":"https://satis.example.com/p2/%package%.json","packages":["partner/*"],"dist":"storage","auth":"http-basic:user:p@ss"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(DistMode::Redirect, repositories[0].dist);
+        assert!(repositories[0].matches("wpackagist-plugin/akismet"));
+        assert!(!repositories[0].matches("partner/lib"));
+        assert_eq!(
+            "https://wpackagist.org/p2/wpackagist-plugin/akismet.json",
+            repositories[0].metadata_url("wpackagist-plugin/akismet")
+        );
+        assert_eq!(DistMode::Storage, repositories[1].dist);
+        assert_eq!(
+            Some(Credential::HttpBasic(
+                "user".to_string(),
+                "p@ss".to_string()
+            )),
+            repositories[1].credential()
+        );
+    }
+}
@@ -1,26 +1,351 @@
 use axum::{
+    body::HttpBody,
     http::{HeaderMap, HeaderName, HeaderValue},
     response::{IntoResponse, Response},
 };
 use futures::StreamExt;
-use reqwest::{Client, Response as ReqwestResponse, StatusCode};
+use rand::Rng;
+use reqwest::redirect::Policy;
+use reqwest::{
+    Client, ClientBuilder, Proxy, RequestBuilder, Response as ReqwestResponse, StatusCode, Url,
+};
+use std::env;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::circuit_breaker::CircuitBreakers;
+use crate::credentials::{Credential, OriginCredentials};
+use crate::fixtures::{FixtureMode, Fixtures};
+use crate::rate_limit::RateLimits;
+use crate::upstream_proxy::ProxyRules;
+
+fn env_secs(name: &str, default: u64) -> Duration {
+    let secs = env::var(name)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default);
+    Duration::from_secs(secs)
+}
+
+// 上游卡住时请求按超时失败，计入熔断，不会一直占用连接；
+// 总超时包含读取响应体的时间，需要大于下载最大 dist 所需的时间
+fn client_builder() -> ClientBuilder {
+    let rules = ProxyRules::global();
+    let mut builder = Client::builder()
+        .connect_timeout(env_secs("UPSTREAM_CONNECT_TIMEOUT_SECS", 10))
+        .timeout(env_secs("UPSTREAM_TIMEOUT_SECS", 300));
+    if !rules.is_empty() {
+        builder = builder.proxy(Proxy::custom(move |url| rules.proxy_for(url)));
+    }
+    builder
+}
 
 pub fn create_client() -> Client {
-    Client::builder().build().unwrap()
+    client_builder().build().unwrap()
+}
+
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.host_str() == b.host_str() && a.port_or_known_default() == b.port_or_known_default()
+}
+
+// 带认证的请求只在同一主机内跟随跳转，PRIVATE-TOKEN 等自定义请求头不会被 reqwest 去掉，
+// 跳到其他主机时停下，由 follow_cross_host 不带认证地继续请求
+fn create_credentialed_client() -> Client {
+    client_builder()
+        .redirect(Policy::custom(|attempt| {
+            if attempt.previous().len() >= 10 {
+                return attempt.error("too many redirects");
+            }
+            match attempt.previous().last() {
+                Some(previous) if !same_origin(previous, attempt.url()) => attempt.stop(),
+                _ => attempt.follow(),
+            }
+        }))
+        .build()
+        .unwrap()
+}
+
+// 跳到其他主机的响应改为不带认证地请求跳转地址
+async fn follow_cross_host(response: ReqwestResponse) -> Result<ReqwestResponse, RequestError> {
+    if !response.status().is_redirection() {
+        return Ok(response);
+    }
+    let location = response
+        .headers()
+        .get("location")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|location| response.url().join(location).ok());
+    let location = match location {
+        Some(location) if !same_origin(response.url(), &location) => location.to_string(),
+        _ => return Ok(response),
+    };
+    let request = create_client()
+        .get(&location)
+        .header("User-Agent", USER_AGENT);
+    send_with_breaker(&location, request).await
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    CircuitOpen(String),
+    RateLimited(String, u64),
+    Http(reqwest::Error),
+    Fixture(String),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::CircuitOpen(upstream) => write!(f, "circuit breaker open for {}", upstream),
+            RequestError::RateLimited(upstream, reset_in_secs) => write!(
+                f,
+                "rate limit exceeded for {}, reset in {} seconds",
+                upstream, reset_in_secs
+            ),
+            RequestError::Http(err) => write!(f, "{}", err),
+            RequestError::Fixture(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36 Edg/116.0.1938.69";
+
+// 同一主机的不同端口视为不同的上游
+fn upstream_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            let host = url.host_str()?;
+            Some(match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            })
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+fn retry_times() -> u32 {
+    env::var("UPSTREAM_RETRY_TIMES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(2)
+}
+
+fn retry_base_delay() -> Duration {
+    let millis = env::var("UPSTREAM_RETRY_BASE_MS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(200);
+    Duration::from_millis(millis)
+}
+
+// 指数退避 + 全抖动，避免所有请求在同一时间重试
+fn backoff_delay(attempt: u32) -> Duration {
+    let max = retry_base_delay().saturating_mul(2u32.saturating_pow(attempt));
+    let millis = max.as_millis() as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+}
+
+fn is_upstream_failure(result: &Result<ReqwestResponse, reqwest::Error>) -> bool {
+    match result {
+        Ok(response) => response.status().is_server_error(),
+        Err(_) => true,
+    }
+}
+
+async fn send_with_breaker(
+    url: &str,
+    request: RequestBuilder,
+) -> Result<ReqwestResponse, RequestError> {
+    let (client, request) = request.build_split();
+    let request = request.map_err(RequestError::Http)?;
+    let fixtures = Fixtures::global();
+    if let Some(fixtures) = fixtures.filter(|fixtures| fixtures.mode() == FixtureMode::Replay) {
+        return fixtures.replay(&request).await.map_err(RequestError::Fixture);
+    }
+
+    let upstream = upstream_of(url);
+    let breakers = CircuitBreakers::global();
+    if !breakers.allow(&upstream) {
+        return Err(RequestError::CircuitOpen(upstream));
+    }
+
+    let method = request.method().clone();
+    let mut result = client.execute(request).await;
+    match is_upstream_failure(&result) {
+        true => breakers.record_failure(&upstream),
+        false => breakers.record_success(&upstream),
+    }
+    if let Some(fixtures) = fixtures {
+        result = fixtures.record(&method, url, result).await;
+    }
+    result.map_err(RequestError::Http)
+}
+
+pub async fn try_get(url: &str) -> Result<ReqwestResponse, RequestError> {
+    try_get_with(url, None).await
+}
+
+pub async fn try_get_with(
+    url: &str,
+    credential: Option<&Credential>,
+) -> Result<ReqwestResponse, RequestError> {
+    let credential = match credential {
+        Some(credential) => credential,
+        None => {
+            let client = create_client();
+            return send_with_retry(url, || client.get(url).header("User-Agent", USER_AGENT)).await;
+        }
+    };
+    let client = create_credentialed_client();
+    let response = send_with_retry(url, || {
+        credential.apply(client.get(url).header("User-Agent", USER_AGENT))
+    })
+    .await?;
+    follow_cross_host(response).await
+}
+
+// 以表单 POST，同样经过熔断、录制回放和重试
+pub async fn post_form(url: &str, form: &[(&str, &str)]) -> Result<ReqwestResponse, RequestError> {
+    let client = create_client();
+    send_with_retry(url, || {
+        client
+            .post(url)
+            .header("User-Agent", USER_AGENT)
+            .form(form)
+    })
+    .await
 }
 
-pub async fn get(url: &str) -> ReqwestResponse {
+// 以 JSON POST，同 post_form
+pub async fn post_json(
+    url: &str,
+    body: &serde_json::Value,
+) -> Result<ReqwestResponse, RequestError> {
     let client = create_client();
+    send_with_retry(url, || {
+        client
+            .post(url)
+            .header("User-Agent", USER_AGENT)
+            .json(body)
+    })
+    .await
+}
+
+// 5xx、429 和网络错误时按 UPSTREAM_RETRY_TIMES 重试
+async fn send_with_retry(
+    url: &str,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<ReqwestResponse, RequestError> {
+    let retry_times = retry_times();
+    let mut attempt = 0;
+
+    loop {
+        let result = send_with_breaker(url, request()).await;
+
+        let retryable = match &result {
+            Ok(response) => {
+                response.status().is_server_error()
+                    || response.status() == StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(RequestError::Http(_)) => true,
+            Err(_) => false,
+        };
+        if !retryable || attempt >= retry_times {
+            return result;
+        }
+
+        sleep(backoff_delay(attempt)).await;
+        attempt += 1;
+    }
+}
+
+fn rate_limit_max_wait() -> Duration {
+    let secs = env::var("ORIGIN_RATE_LIMIT_MAX_WAIT_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(60);
+    Duration::from_secs(secs)
+}
+
+fn retry_after(response: &ReqwestResponse) -> Option<Duration> {
+    response
+        .headers()
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+// 下载源站 dist，按域名附加 ORIGIN_CREDENTIALS 中的认证信息，
+// 触发限流时在 ORIGIN_RATE_LIMIT_MAX_WAIT_SECS 内等待配额恢复后重试一次
+pub async fn get_origin(url: &str) -> Result<ReqwestResponse, RequestError> {
+    get_origin_with(url, None).await
+}
+
+// credential 为空时使用 ORIGIN_CREDENTIALS 中该域名的认证信息
+pub async fn get_origin_with(
+    url: &str,
+    credential: Option<&Credential>,
+) -> Result<ReqwestResponse, RequestError> {
+    let host = upstream_of(url);
+    let rate_limits = RateLimits::global();
+    let max_wait = rate_limit_max_wait();
+
+    if let Some(quota) = rate_limits.get(&host) {
+        if quota.is_exhausted() {
+            let wait = quota.wait_time();
+            if wait > max_wait {
+                return Err(RequestError::RateLimited(host, wait.as_secs()));
+            }
+            sleep(wait).await;
+        }
+    }
+
+    let credential = credential.or_else(|| OriginCredentials::global().credential_for(url));
+    let client = match credential {
+        Some(_) => create_credentialed_client(),
+        None => create_client(),
+    };
+    let mut waited = false;
+    loop {
+        let mut request = client.get(url).header("User-Agent", USER_AGENT);
+        if let Some(credential) = credential {
+            request = credential.apply(request);
+        }
+        let response = send_with_breaker(url, request).await?;
 
-    client.get(url)
-    .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36 Edg/116.0.1938.69")
-    .send().await.unwrap()
+        let quota = rate_limits.update(&host, response.headers());
+        let limited = response.status() == StatusCode::TOO_MANY_REQUESTS
+            || (response.status() == StatusCode::FORBIDDEN
+                && quota.as_ref().map(|quota| quota.remaining == 0).unwrap_or(false));
+        if !limited {
+            return match credential {
+                Some(_) => follow_cross_host(response).await,
+                None => Ok(response),
+            };
+        }
+
+        let wait = retry_after(&response)
+            .or_else(|| quota.map(|quota| quota.wait_time()))
+            .unwrap_or(max_wait);
+        if waited || wait > max_wait {
+            return Err(RequestError::RateLimited(host, wait.as_secs()));
+        }
+        eprintln!(
+            "rate limit exceeded for {}, retry in {} seconds",
+            host,
+            wait.as_secs()
+        );
+        sleep(wait).await;
+        waited = true;
+    }
 }
 
-pub async fn head(url: &str) -> Result<ReqwestResponse, reqwest::Error> {
+pub async fn head(url: &str) -> Result<ReqwestResponse, RequestError> {
     let client = create_client();
 
-    client.head(url).send().await
+    send_with_breaker(url, client.head(url)).await
 }
 
 pub async fn speed_test(url: String) -> Option<(String, u128)> {
@@ -29,7 +354,10 @@ pub async fn speed_test(url: String) -> Option<(String, u128)> {
     match response {
         Ok(response) => {
             if response.status() == StatusCode::OK {
-                let req_response: ReqwestResponse = get(&url).await;
+                let req_response = match try_get(&url).await {
+                    Ok(req_response) => req_response,
+                    Err(_) => return None,
+                };
 
                 let start = std::time::Instant::now();
                 let mut buffer = Vec::new();
@@ -44,7 +372,7 @@ pub async fn speed_test(url: String) -> Option<(String, u128)> {
                             Ok(item) => item,
                             Err(_) => break,
                         };
-                        if item.len() == 0 {
+                        if item.is_empty() {
                             break;
                         }
                         buffer.extend(item);
@@ -69,12 +397,52 @@ pub async fn speed_test(url: String) -> Option<(String, u128)> {
     
 }
 
-pub async fn proxy(url: &str) -> Response {
-    let reqwest_response = get(url).await;
-    
-    let resp_headers = reqwest_response.headers().clone();
-    let body: String = reqwest_response.text().await.unwrap();
-    (StatusCode::OK, resp_headers, body).into_response()
+pub fn json_response(body: String) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/json"),
+    );
+    (StatusCode::OK, headers, body).into_response()
+}
+
+// 读取完整的响应体交给 rewrite 修改，返回 None 时原样返回
+pub async fn rewrite_body(
+    response: Response,
+    rewrite: impl FnOnce(&str) -> Option<String>,
+) -> Response {
+    let (parts, mut body) = response.into_parts();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => bytes.extend_from_slice(&chunk),
+            Err(_) => return (StatusCode::BAD_GATEWAY, "failed to read metadata").into_response(),
+        }
+    }
+    match rewrite(&String::from_utf8_lossy(&bytes)) {
+        Some(body) => {
+            let mut response = json_response(body);
+            response.extensions_mut().extend(parts.extensions);
+            response
+        }
+        None => Response::from_parts(parts, axum::body::boxed(axum::body::Full::from(bytes))),
+    }
+}
+
+// 把上游的响应原样转发给客户端，响应体边下载边返回
+pub fn stream_response(response: ReqwestResponse) -> Response {
+    let mut headers = HeaderMap::new();
+    for name in ["content-type", "content-length"] {
+        if let Some(value) = response.headers().get(name) {
+            headers.insert(HeaderName::from_static(name), value.clone());
+        }
+    }
+    (
+        response.status(),
+        headers,
+        axum::body::StreamBody::new(response.bytes_stream()),
+    )
+        .into_response()
 }
 
 pub fn redirect(url: &str) -> Response {
@@ -91,6 +459,7 @@ mod tests {
     use super::*;
 
     #[tokio::test]
+    #[ignore = "needs network access"]
     async fn head_test() {
         let url = "https://ghps.cc/https://github.com/quansitech/think-core/archive/refs/tags/v12.30.0.zip";
         let response = head(url).await;
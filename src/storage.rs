@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use qiniu_sdk::{
+    objects::ObjectsManager,
+    upload::{
+        apis::credential::Credential, AutoUploader, AutoUploaderObjectParams, UploadManager,
+        UploadTokenSigner,
+    },
+    ureq::http::AsyncResponseBody,
+};
+use reqwest::StatusCode;
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::request_helper;
+
+// 策略1 存放 dist 的自有存储
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // 对象对外的下载地址，composer 会被重定向到这里
+    fn url(&self, object_name: &str) -> String;
+
+    async fn exists(&self, object_name: &str) -> bool {
+        match request_helper::head(&self.url(object_name)).await {
+            Ok(response) => response.status() == StatusCode::OK,
+            Err(_) => false,
+        }
+    }
+
+    // 带有效期的下载地址，私有空间需要签名；返回 None 时由本服务转发对象内容
+    fn signed_url(&self, _object_name: &str, _expires_in: Duration) -> Option<String> {
+        None
+    }
+
+    async fn put(&self, object_name: &str, body: Vec<u8>) -> Result<(), String>;
+
+    // 上传本地文件，大文件不必整个读入内存的存储可以覆盖此方法
+    async fn put_file(&self, object_name: &str, path: &Path) -> Result<(), String> {
+        let body = tokio::fs::read(path).await.map_err(|err| err.to_string())?;
+        self.put(object_name, body).await
+    }
+
+    // 以 prefix 开头的对象名，用于管理接口查看已上传的 dist
+    async fn list(&self, _prefix: &str) -> Result<Vec<String>, String> {
+        Err("listing objects is not supported".to_string())
+    }
+}
+
+pub struct Qiniu {
+    domain: String,
+    access_key: String,
+    secret_key: String,
+    bucket_name: String,
+}
+
+impl Qiniu {
+    pub fn new(domain: &str, access_key: &str, secret_key: &str, bucket_name: &str) -> Self {
+        Self {
+            domain: domain.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            bucket_name: bucket_name.to_string(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            &env::var("DOMAIN").unwrap(),
+            &env::var("ACCESS_KEY").unwrap(),
+            &env::var("SECRET_KEY").unwrap(),
+            &env::var("BUCKET").unwrap(),
+        )
+    }
+
+    fn uploader(&self) -> AutoUploader {
+        let credential = Credential::new(&self.access_key, &self.secret_key);
+        UploadManager::builder(UploadTokenSigner::new_credential_provider(
+            credential,
+            &self.bucket_name,
+            Duration::from_secs(3600),
+        ))
+        .build()
+        .auto_uploader()
+    }
+}
+
+fn upload_params(object_name: &str) -> AutoUploaderObjectParams {
+    AutoUploaderObjectParams::builder()
+        .object_name(object_name)
+        .file_name(object_name)
+        .build()
+        .into()
+}
+
+#[async_trait]
+impl Storage for Qiniu {
+    fn url(&self, object_name: &str) -> String {
+        format!("http://{}/{}", self.domain, object_name)
+    }
+
+    fn signed_url(&self, object_name: &str, expires_in: Duration) -> Option<String> {
+        let credential = Credential::new(&self.access_key, &self.secret_key);
+        let url = self.url(object_name).parse().ok()?;
+        Some(credential.sign_download_url(url, expires_in).to_string())
+    }
+
+    async fn put(&self, object_name: &str, body: Vec<u8>) -> Result<(), String> {
+        self.uploader()
+            .async_upload_reader(
+                AsyncResponseBody::from_bytes(body),
+                upload_params(object_name),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("upload to qiniu failed: {}", err))
+    }
+
+    // 大文件由 SDK 分片上传
+    async fn put_file(&self, object_name: &str, path: &Path) -> Result<(), String> {
+        self.uploader()
+            .async_upload_path(path, upload_params(object_name))
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("upload to qiniu failed: {}", err))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let credential = Credential::new(&self.access_key, &self.secret_key);
+        let bucket = ObjectsManager::new(credential).bucket(self.bucket_name.as_str());
+        let mut stream = bucket.list().prefix(prefix).stream();
+        let mut names = Vec::new();
+        while let Some(entry) = stream.next().await {
+            let entry = entry.map_err(|err| format!("list qiniu objects failed: {}", err))?;
+            names.push(entry.get_key_as_str().to_string());
+        }
+        Ok(names)
+    }
+}
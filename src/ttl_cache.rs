@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 过期时间相同的条目用递增的序号区分
+type ExpiryKey = (Instant, u64);
+
+struct Entries<K, V> {
+    values: HashMap<K, (V, ExpiryKey)>,
+    // 按过期时间排序，满了之后从最早过期的条目开始淘汰
+    expiry: BTreeMap<ExpiryKey, K>,
+    next_id: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> Entries<K, V> {
+    fn remove(&mut self, key: &K) {
+        if let Some((_, expiry_key)) = self.values.remove(key) {
+            self.expiry.remove(&expiry_key);
+        }
+    }
+
+    fn pop_first(&mut self) {
+        if let Some((_, key)) = self.expiry.pop_first() {
+            self.values.remove(&key);
+        }
+    }
+}
+
+pub struct TtlCache<K, V> {
+    entries: Mutex<Entries<K, V>>,
+    capacity: usize,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                values: HashMap::new(),
+                expiry: BTreeMap::new(),
+                next_id: 0,
+            }),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.values.get(key) {
+            Some((value, (expires_at, _))) if *expires_at > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn entries(&self) -> Vec<(K, V, Duration)> {
+        let now = Instant::now();
+        self.entries
+            .lock()
+            .unwrap()
+            .values
+            .iter()
+            .filter(|(_, (_, (expires_at, _)))| *expires_at > now)
+            .map(|(key, (value, (expires_at, _)))| (key.clone(), value.clone(), *expires_at - now))
+            .collect()
+    }
+
+    pub fn remove_where(&self, predicate: impl Fn(&K) -> bool) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.values.len();
+        entries.values.retain(|key, _| !predicate(key));
+        entries.expiry.retain(|_, key| !predicate(key));
+        before - entries.values.len()
+    }
+
+    pub fn insert(&self, key: K, value: V, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(&key);
+        let now = Instant::now();
+        while entries
+            .expiry
+            .first_key_value()
+            .is_some_and(|((expires_at, _), _)| *expires_at <= now)
+        {
+            entries.pop_first();
+        }
+        if entries.values.len() >= self.capacity {
+            entries.pop_first();
+        }
+
+        let expiry_key = (now + ttl, entries.next_id);
+        entries.next_id += 1;
+        entries.expiry.insert(expiry_key, key.clone());
+        entries.values.insert(key, (value, expiry_key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ttl_cache_expire_test() {
+        let cache = TtlCache::new(10);
+        cache.insert("a", true, Duration::from_secs(60));
+        cache.insert("b", false, Duration::from_nanos(1));
+        cache.insert("c", true, Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_eq!(Some(true), cache.get(&"a"));
+        assert_eq!(None, cache.get(&"b"));
+        assert_eq!(None, cache.get(&"c"));
+    }
+
+    #[test]
+    fn ttl_cache_capacity_test() {
+        let cache = TtlCache::new(2);
+        cache.insert("a", 1, Duration::from_secs(60));
+        cache.insert("b", 2, Duration::from_secs(30));
+        cache.insert("c", 3, Duration::from_secs(60));
+
+        assert_eq!(Some(1), cache.get(&"a"));
+        assert_eq!(None, cache.get(&"b"));
+        assert_eq!(Some(3), cache.get(&"c"));
+    }
+
+    #[test]
+    fn ttl_cache_reinsert_test() {
+        let cache = TtlCache::new(2);
+        cache.insert("a", 1, Duration::from_secs(30));
+        cache.insert("b", 2, Duration::from_secs(60));
+        // 重新插入后按新的过期时间排序
+        cache.insert("a", 3, Duration::from_secs(90));
+        cache.insert("c", 4, Duration::from_secs(60));
+
+        assert_eq!(Some(3), cache.get(&"a"));
+        assert_eq!(None, cache.get(&"b"));
+        assert_eq!(Some(4), cache.get(&"c"));
+        assert_eq!(1, cache.remove_where(|key| *key == "a"));
+        assert_eq!(1, cache.entries().len());
+    }
+}
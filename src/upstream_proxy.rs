@@ -0,0 +1,170 @@
+use glob::Pattern;
+use reqwest::Url;
+use std::env;
+use std::sync::OnceLock;
+
+static PROXY_RULES: OnceLock<ProxyRules> = OnceLock::new();
+
+struct ProxyRule {
+    host_pattern: Pattern,
+    proxy: Url,
+}
+
+// 按上游域名选择出口代理，支持 http/https/socks5/socks5h
+pub struct ProxyRules {
+    rules: Vec<ProxyRule>,
+    default_proxy: Option<Url>,
+    no_proxy: Vec<String>,
+}
+
+impl ProxyRules {
+    // MirrorServerBuilder::build 中先调用 init 检查配置，这里不会再失败
+    pub fn global() -> &'static ProxyRules {
+        PROXY_RULES.get_or_init(|| ProxyRules::from_env().unwrap_or_else(|err| panic!("{}", err)))
+    }
+
+    // 解析环境变量中的代理配置，配置有误时返回错误
+    pub fn init() -> Result<(), String> {
+        let rules = ProxyRules::from_env()?;
+        let _ = PROXY_RULES.set(rules);
+        Ok(())
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(
+            &env::var("UPSTREAM_PROXY").unwrap_or_default(),
+            &env::var("UPSTREAM_PROXY_RULES").unwrap_or_default(),
+            &env::var("UPSTREAM_NO_PROXY").unwrap_or_default(),
+        )
+    }
+
+    pub fn parse(default_proxy: &str, rules: &str, no_proxy: &str) -> Result<Self, String> {
+        let rules = rules
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|rule| {
+                let (host, proxy) = rule
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid UPSTREAM_PROXY_RULES entry: {}", rule))?;
+                Ok(ProxyRule {
+                    host_pattern: Pattern::new(&host.trim().to_lowercase())
+                        .map_err(|err| format!("invalid host pattern {}: {}", host.trim(), err))?,
+                    proxy: parse_proxy(proxy.trim())?,
+                })
+            })
+            .collect::<Result<Vec<ProxyRule>, String>>()?;
+
+        let default_proxy = match default_proxy.trim() {
+            "" => None,
+            url => Some(parse_proxy(url)?),
+        };
+
+        let no_proxy = no_proxy
+            .split(',')
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        Ok(Self {
+            rules,
+            default_proxy,
+            no_proxy,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.default_proxy.is_none()
+    }
+
+    // 与 NO_PROXY 的写法一致：* 表示全部，example.com 匹配自身及子域名，.example.com 只匹配子域名
+    fn is_excluded(&self, host: &str) -> bool {
+        self.no_proxy.iter().any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+            if let Some(suffix) = entry.strip_prefix("*.") {
+                return host.ends_with(&format!(".{}", suffix));
+            }
+            if entry.starts_with('.') {
+                return host.ends_with(entry.as_str());
+            }
+            host == entry || host.ends_with(&format!(".{}", entry))
+        })
+    }
+
+    pub fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str()?.to_lowercase();
+        if self.is_excluded(&host) {
+            return None;
+        }
+
+        self.rules
+            .iter()
+            .find(|rule| rule.host_pattern.matches(&host))
+            .map(|rule| rule.proxy.clone())
+            .or_else(|| self.default_proxy.clone())
+    }
+}
+
+fn parse_proxy(url: &str) -> Result<Url, String> {
+    Url::parse(url)
+        .ok()
+        .filter(|url| ["http", "https", "socks5", "socks5h"].contains(&url.scheme()))
+        .ok_or_else(|| format!("invalid proxy url: {}", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn proxy_rule_test() {
+        let rules = ProxyRules::parse(
+            "",
+            "github.com=socks5h://127.0.0.1:1080,*.github.com=socks5h://127.0.0.1:1080,packagist.org=http://10.0.0.1:3128",
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(url("socks5h://127.0.0.1:1080")),
+            rules.proxy_for(&url("https://api.github.com/repos/quansitech/think-core/zipball/35c34ca5"))
+        );
+        assert_eq!(
+            Some(url("http://10.0.0.1:3128")),
+            rules.proxy_for(&url("https://packagist.org/p2/tiderjian/think-core.json"))
+        );
+        assert_eq!(None, rules.proxy_for(&url("https://mirrors.aliyun.com/composer/dists/a/b/c.zip")));
+    }
+
+    #[test]
+    fn no_proxy_test() {
+        let rules = ProxyRules::parse(
+            "http://10.0.0.1:3128",
+            "",
+            "mirrors.aliyun.com,.tencent.com,*.qiniucdn.com",
+        )
+        .unwrap();
+
+        assert_eq!(None, rules.proxy_for(&url("https://mirrors.aliyun.com/composer/")));
+        assert_eq!(None, rules.proxy_for(&url("https://mirrors.cloud.tencent.com/repository/")));
+        assert_eq!(None, rules.proxy_for(&url("http://cdn.qiniucdn.com/a.zip")));
+        assert_eq!(
+            Some(url("http://10.0.0.1:3128")),
+            rules.proxy_for(&url("https://codeload.github.com/a/b"))
+        );
+    }
+
+    #[test]
+    fn invalid_rule_test() {
+        assert!(ProxyRules::parse("127.0.0.1:1080", "", "").is_err());
+        assert!(ProxyRules::parse("", "github.com", "").is_err());
+        assert!(ProxyRules::parse("", "[github.com=http://10.0.0.1:3128", "").is_err());
+        assert!(ProxyRules::parse("", "github.com=not a url", "").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use axum::response::Response;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+
+use crate::constraint::{is_prerelease, version_key, Constraint, VersionKey};
+use crate::hosted::normalize_version;
+use crate::metadata::retain_versions;
+use crate::{check_package_in_white_list, request_helper, Config};
+
+// 配置文件中的一条规则
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct VersionFilterConfig {
+    // 支持 * 泛型匹配，如 laravel/*
+    packages: Vec<String>,
+    // 移除 dev-main、2.x-dev 等开发分支
+    #[serde(default)]
+    drop_dev: bool,
+    // 移除 alpha、beta、RC 版本
+    #[serde(default)]
+    drop_prerelease: bool,
+    // 移除发布时间早于该日期的版本，如 2020-01-01
+    #[serde(default)]
+    released_after: Option<String>,
+    // 移除低于该版本的版本，如 8.0
+    #[serde(default)]
+    min_version: Option<String>,
+    // 禁止使用的版本，写法与 composer 的版本约束相同，如 >=9.0,<9.52.7
+    #[serde(default)]
+    exclude: Option<String>,
+    // 平台的 php 版本，移除 require.php 不兼容的版本，如 8.1
+    #[serde(default)]
+    php: Option<String>,
+}
+
+// 解析后的规则
+#[derive(Clone, Debug)]
+struct Rule {
+    drop_dev: bool,
+    drop_prerelease: bool,
+    released_after: Option<String>,
+    min_version: Option<VersionKey>,
+    exclude: Option<Constraint>,
+    php: Option<VersionKey>,
+}
+
+fn parse_version(version: &str) -> Option<VersionKey> {
+    normalize_version(version).as_deref().and_then(version_key)
+}
+
+impl VersionFilterConfig {
+    fn rule(&self) -> Result<Rule, String> {
+        let min_version = match &self.min_version {
+            Some(version) => Some(
+                parse_version(version).ok_or_else(|| format!("invalid min-version {}", version))?,
+            ),
+            None => None,
+        };
+        let exclude = match &self.exclude {
+            Some(exclude) => Some(
+                Constraint::parse(exclude).ok_or_else(|| format!("invalid exclude {}", exclude))?,
+            ),
+            None => None,
+        };
+        let php = match &self.php {
+            Some(php) => Some(parse_version(php).ok_or_else(|| format!("invalid php {}", php))?),
+            None => None,
+        };
+        Ok(Rule {
+            drop_dev: self.drop_dev,
+            drop_prerelease: self.drop_prerelease,
+            released_after: self.released_after.clone(),
+            min_version,
+            exclude,
+            php,
+        })
+    }
+}
+
+// 返回元数据前按规则移除版本，匹配同一扩展的多条规则依次生效；
+// 规则在读取配置时解析，无效的规则直接报错，不会在请求时被忽略
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "VersionFilterConfig")]
+pub struct VersionFilter {
+    pub packages: Vec<String>,
+    rule: Rule,
+}
+
+impl TryFrom<VersionFilterConfig> for VersionFilter {
+    type Error = String;
+
+    fn try_from(config: VersionFilterConfig) -> Result<Self, String> {
+        let rule = config
+            .rule()
+            .map_err(|err| format!("{} of {}", err, config.packages.join(",")))?;
+        Ok(Self {
+            packages: config.packages,
+            rule,
+        })
+    }
+}
+
+impl VersionFilter {
+    pub fn matches(&self, package: &str) -> bool {
+        check_package_in_white_list(package, &self.packages)
+    }
+}
+
+impl Rule {
+    fn keep(&self, version: &Value) -> bool {
+        let name = version["version"].as_str().unwrap_or_default();
+        let normalized = version["version_normalized"].as_str().unwrap_or_default();
+        let is_dev = name.starts_with("dev-") || normalized.ends_with("-dev");
+        if self.drop_dev && is_dev {
+            return false;
+        }
+        // time 如 2023-09-06T16:49:12+00:00，按日期比较
+        if let (Some(after), Some(time)) = (&self.released_after, version["time"].as_str()) {
+            if time.get(..after.len()).unwrap_or(time) < after.as_str() {
+                return false;
+            }
+        }
+        if let Some(php) = &self.php {
+            let required = version["require"]["php"]
+                .as_str()
+                .and_then(Constraint::parse);
+            if required.is_some_and(|required| !required.matches(php)) {
+                return false;
+            }
+        }
+        // 以下规则只对能比较的版本生效，dev-main 等分支不受影响
+        let key = match version_key(normalized) {
+            Some(key) => key,
+            None => return true,
+        };
+        if self.drop_prerelease && !is_dev && is_prerelease(&key) {
+            return false;
+        }
+        if self.min_version.as_ref().is_some_and(|min| key < *min) {
+            return false;
+        }
+        !self
+            .exclude
+            .as_ref()
+            .is_some_and(|exclude| exclude.matches(&key))
+    }
+}
+
+// VERSION_FILTERS_PATH 指向的 JSON 数组，启动时检查规则是否有效
+pub fn load(path: &str) -> Result<Vec<VersionFilter>, String> {
+    let content =
+        fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+    serde_json::from_str::<Vec<VersionFilter>>(&content)
+        .map_err(|err| format!("failed to parse {}: {}", path, err))
+}
+
+fn filter(package: &str, body: &str, filters: &[&VersionFilter]) -> Option<String> {
+    retain_versions(package, body, |version| {
+        filters.iter().all(|filter| filter.rule.keep(version))
+    })
+    .map(|(body, _)| body)
+}
+
+// 有匹配的规则时元数据需要由本服务返回，不能跳转到第三方镜像
+pub fn applies(config: &Config, package: &str) -> bool {
+    config
+        .version_filters
+        .iter()
+        .any(|filter| filter.matches(package))
+}
+
+// 从返回的元数据中移除匹配规则的版本
+pub async fn apply(config: &Config, package: &str, response: Response) -> Response {
+    let filters = config
+        .version_filters
+        .iter()
+        .filter(|filter| filter.matches(package))
+        .collect::<Vec<&VersionFilter>>();
+    if filters.is_empty() || response.status() != StatusCode::OK {
+        return response;
+    }
+    request_helper::rewrite_body(response, |body| filter(package, body, &filters)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn filter_test() {
+        let version = |version: &str, normalized: &str, time: &str, php: &str| {
+            json!({
+                "name": "laravel/framework",
+                "version": version,
+                "version_normalized": normalized,
+                "time": time,
+                "require": { "php": php },
+            })
+        };
+        let body = json!({
+            "minified": "composer/2.0",
+            "packages": {
+                "laravel/framework": [
+                    version("dev-master", "dev-master", "2023-09-01T00:00:00+00:00", "^8.1"),
+                    version("v10.1.0", "10.1.0.0", "2023-02-20T00:00:00+00:00", "^8.1"),
+                    version("v10.0.0-RC1", "10.0.0.0-RC1", "2023-01-20T00:00:00+00:00", "^8.1"),
+                    version("v9.52.0", "9.52.0.0", "2023-02-14T00:00:00+00:00", "^8.0.2"),
+                    version("v9.1.0", "9.1.0.0", "2022-02-15T00:00:00+00:00", "^8.0.2"),
+                    version("v8.83.0", "8.83.0.0", "2022-02-08T00:00:00+00:00", "^7.3|^8.0"),
+                    version("v6.20.0", "6.20.0.0", "2020-11-03T00:00:00+00:00", "^7.2.5|^8.0"),
+                    version("v5.5.0", "5.5.0.0", "2017-08-30T00:00:00+00:00", ">=7.0"),
+                ]
+            }
+        })
+        .to_string();
+        let filters = serde_json::from_str::<Vec<VersionFilter>>(
+            r#"[
+                {"packages": ["laravel/*"], "drop-dev": true, "drop-prerelease": true, "released-after": "2018-01-01"},
+                {"packages": ["laravel/framework"], "min-version": "6.0", "exclude": ">=9.0,<9.52", "php": "8.0.30"}
+            ]"#,
+        )
+        .unwrap();
+        let filters = filters.iter().collect::<Vec<&VersionFilter>>();
+
+        let filtered = filter("laravel/framework", &body, &filters).unwrap();
+        let root = serde_json::from_str::<Value>(&filtered).unwrap();
+        let versions = root["packages"]["laravel/framework"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|version| version["version"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["v9.52.0", "v8.83.0", "v6.20.0"], versions);
+        assert!(root.get("minified").is_none());
+
+        // 没有版本被移除时原样返回
+        assert!(filter("laravel/framework", &filtered, &filters).is_none());
+    }
+
+    #[test]
+    fn invalid_rule_test() {
+        let err = serde_json::from_str::<Vec<VersionFilter>>(
+            r#"[{"packages": ["laravel/*"], "exclude": ">=9.0,<"}]"#,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("invalid exclude >=9.0,< of laravel/*"));
+        assert!(serde_json::from_str::<Vec<VersionFilter>>(
+            r#"[{"packages": ["laravel/*"], "min-version": "latest"}]"#,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use axum::{extract::Path, http::HeaderMap, response::Response, Extension};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+
+use crate::journal::StorageUploaded;
+use crate::{dist_dispatcher, package_meta, Config};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WarmTarget {
+    Metadata(String),
+    Dist {
+        package: String,
+        version: String,
+        reference: String,
+        dist_type: String,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Hit,
+    // 跳转到第三方镜像或源站，自有存储中仍然没有
+    Redirect,
+    Miss,
+    Upload,
+    Failure,
+}
+
+#[derive(Default)]
+struct Summary {
+    hits: usize,
+    redirects: usize,
+    misses: usize,
+    uploads: usize,
+    failures: usize,
+}
+
+fn parse_route(route: &str) -> Option<WarmTarget> {
+    let segments = route.trim().trim_start_matches('/').split('/').collect::<Vec<&str>>();
+    match segments.as_slice() {
+        ["p2", vendor, package] if package.ends_with(".json") => Some(WarmTarget::Metadata(
+            format!("{}/{}", vendor, package.trim_end_matches(".json")),
+        )),
+        ["dists", vendor, package, version, reference_and_type] => {
+            let (reference, dist_type) = reference_and_type.split_once('.')?;
+            Some(WarmTarget::Dist {
+                package: format!("{}/{}", vendor, package),
+                version: version.to_string(),
+                reference: reference.to_string(),
+                dist_type: dist_type.to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse_composer_lock(lock: &Value) -> Vec<WarmTarget> {
+    let mut targets = Vec::new();
+    for key in ["packages", "packages-dev"] {
+        for package in lock[key].as_array().into_iter().flatten() {
+            let name = match package["name"].as_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            targets.push(WarmTarget::Metadata(name.to_string()));
+
+            if let (Some(version), Some(reference), Some(dist_type)) = (
+                package["version"].as_str(),
+                package["dist"]["reference"].as_str(),
+                package["dist"]["type"].as_str(),
+            ) {
+                targets.push(WarmTarget::Dist {
+                    package: name.to_string(),
+                    version: version.to_string(),
+                    reference: reference.to_string(),
+                    dist_type: dist_type.to_string(),
+                });
+            }
+        }
+    }
+    targets
+}
+
+// 支持 composer.lock、JSONL 请求日志和每行一个请求路径的 log.txt
+pub fn parse_input(content: &str) -> Vec<WarmTarget> {
+    if let Ok(lock) = serde_json::from_str::<Value>(content) {
+        if lock.get("packages").map(|v| v.is_array()).unwrap_or(false) {
+            return parse_composer_lock(&lock);
+        }
+    }
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str::<Value>(line) {
+            Ok(record) => record["route"].as_str().and_then(parse_route),
+            Err(_) => parse_route(line),
+        })
+        .collect()
+}
+
+// 跳转到自有存储的才算命中，storage_prefix 为自有存储地址的前缀
+fn classify(response: &Response, storage_prefix: Option<&str>) -> Outcome {
+    if response.extensions().get::<StorageUploaded>().is_some() {
+        return Outcome::Upload;
+    }
+    match response.status() {
+        StatusCode::OK => Outcome::Hit,
+        StatusCode::TEMPORARY_REDIRECT => {
+            let location = response
+                .headers()
+                .get("location")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            match storage_prefix {
+                Some(prefix) if location.starts_with(prefix) => Outcome::Hit,
+                _ => Outcome::Redirect,
+            }
+        }
+        StatusCode::NOT_FOUND => Outcome::Miss,
+        _ => Outcome::Failure,
+    }
+}
+
+async fn warm_target(target: WarmTarget, config: Config) -> Outcome {
+    let task = tokio::spawn(async move {
+        let storage_prefix = config.storage.as_ref().map(|storage| storage.url(""));
+        let response = match target {
+            WarmTarget::Metadata(package) => {
+                package_meta(
+                    Path(format!("{}.json", package)),
+                    HeaderMap::new(),
+                    None,
+                    Extension(config),
+                )
+                .await
+            }
+            WarmTarget::Dist {
+                package,
+                version,
+                reference,
+                dist_type,
+            } => {
+                let (vendor, package) = match package.split_once('/') {
+                    Some(names) => names,
+                    None => return Outcome::Failure,
+                };
+                dist_dispatcher(
+                    Path((
+                        vendor.to_string(),
+                        package.to_string(),
+                        version,
+                        format!("{}.{}", reference, dist_type),
+                    )),
+                    Extension(config),
+                )
+                .await
+            }
+        };
+        classify(&response, storage_prefix.as_deref())
+    });
+
+    // 上游异常时处理函数可能 panic，记为失败
+    task.await.unwrap_or(Outcome::Failure)
+}
+
+fn usage() {
+    eprintln!("usage: composer_mirror warm [--concurrency N] <log.txt|requests.jsonl|composer.lock>...");
+}
+
+pub async fn run(args: &[String], config: Config) {
+    let mut concurrency = 4;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--concurrency" | "-c" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => concurrency = n,
+                _ => return usage(),
+            },
+            _ => files.push(arg.clone()),
+        }
+    }
+    if files.is_empty() {
+        return usage();
+    }
+
+    let mut seen = HashSet::new();
+    let mut targets = Vec::new();
+    for file in files {
+        match fs::read_to_string(&file) {
+            Ok(content) => {
+                for target in parse_input(&content) {
+                    if seen.insert(target.clone()) {
+                        targets.push(target);
+                    }
+                }
+            }
+            Err(err) => eprintln!("failed to read {}: {}", file, err),
+        }
+    }
+
+    println!("warming {} targets with concurrency {}", targets.len(), concurrency);
+
+    let mut summary = Summary::default();
+    let mut results = futures::stream::iter(targets.into_iter().map(|target| {
+        let config = config.clone();
+        async move {
+            let outcome = warm_target(target.clone(), config).await;
+            (target, outcome)
+        }
+    }))
+    .buffer_unordered(concurrency);
+
+    while let Some((target, outcome)) = results.next().await {
+        match outcome {
+            Outcome::Hit => summary.hits += 1,
+            Outcome::Redirect => summary.redirects += 1,
+            Outcome::Miss => summary.misses += 1,
+            Outcome::Upload => summary.uploads += 1,
+            Outcome::Failure => summary.failures += 1,
+        }
+        if outcome != Outcome::Hit && outcome != Outcome::Upload {
+            println!("{:?}: {:?}", outcome, target);
+        }
+    }
+
+    println!(
+        "hits: {}, redirects: {}, misses: {}, uploads: {}, failures: {}",
+        summary.hits, summary.redirects, summary.misses, summary.uploads, summary.failures
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_input_test() {
+        let log = "/dists/tiderjian/think-core/v12.30.0/35c34ca5af137fa28b151de5b0d839d51c4a1fa9.zip\n\n/p2/nesbot/carbon.json\n";
+        assert_eq!(
+            vec![
+                WarmTarget::Dist {
+                    package: "tiderjian/think-core".to_string(),
+                    version: "v12.30.0".to_string(),
+                    reference: "35c34ca5af137fa28b151de5b0d839d51c4a1fa9".to_string(),
+                    dist_type: "zip".to_string(),
+                },
+                WarmTarget::Metadata("nesbot/carbon".to_string()),
+            ],
+            parse_input(log)
+        );
+
+        let journal = r#"{"route":"/p2/nesbot/carbon.json","status":200}"#;
+        assert_eq!(
+            vec![WarmTarget::Metadata("nesbot/carbon".to_string())],
+            parse_input(journal)
+        );
+
+        let lock = r#"{
+            "packages": [{
+                "name": "nesbot/carbon",
+                "version": "2.70.0",
+                "dist": {"type": "zip", "url": "https://api.github.com/repos/briannesbitt/Carbon/zipball/d3298b38ea8612e5f77d38d1a99438e42f70341d", "reference": "d3298b38ea8612e5f77d38d1a99438e42f70341d"}
+            }],
+            "packages-dev": []
+        }"#;
+        assert_eq!(
+            vec![
+                WarmTarget::Metadata("nesbot/carbon".to_string()),
+                WarmTarget::Dist {
+                    package: "nesbot/carbon".to_string(),
+                    version: "2.70.0".to_string(),
+                    reference: "d3298b38ea8612e5f77d38d1a99438e42f70341d".to_string(),
+                    dist_type: "zip".to_string(),
+                },
+            ],
+            parse_input(lock)
+        );
+    }
+
+    #[test]
+    fn classify_test() {
+        use crate::request_helper;
+
+        let prefix = Some("http://cdn.example.com/");
+        let redirect = |url: &str| request_helper::redirect(url);
+        assert_eq!(
+            Outcome::Hit,
+            classify(&redirect("http://cdn.example.com/acme/lib/v1.0.0/abc.zip"), prefix)
+        );
+        assert_eq!(
+            Outcome::Redirect,
+            classify(&redirect("https://mirrors.tencent.com/composer/acme/lib.zip"), prefix)
+        );
+        assert_eq!(Outcome::Redirect, classify(&redirect("https://github.com/x.zip"), None));
+
+        let mut uploaded = redirect("http://cdn.example.com/acme/lib/v1.0.0/abc.zip");
+        uploaded.extensions_mut().insert(StorageUploaded);
+        assert_eq!(Outcome::Upload, classify(&uploaded, prefix));
+    }
+}
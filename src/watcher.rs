@@ -0,0 +1,283 @@
+use axum::{extract::Path, Extension};
+use glob::Pattern;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::constraint::{is_prerelease, version_key};
+use crate::lookup_error::NegativeCache;
+use crate::metadata::{PackageMetadata, Version};
+use crate::metadata_cache::MetadataCache;
+use crate::mirrors::packagist::Packagist;
+use crate::request_helper;
+use crate::{check_package_in_white_list, dist_dispatcher, Config, PackagistStrategy};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Release {
+    pub version: String,
+    pub reference: String,
+    pub dist_type: String,
+}
+
+impl Release {
+    pub fn from_version(version: &Version) -> Option<Self> {
+        Some(Self {
+            version: version.version.clone(),
+            reference: version.reference()?.to_string(),
+            dist_type: version.dist.as_ref()?.dist_type.clone(),
+        })
+    }
+}
+
+pub fn releases(metadata: &PackageMetadata) -> HashSet<Release> {
+    metadata
+        .versions
+        .iter()
+        .filter_map(Release::from_version)
+        .collect()
+}
+
+// 需要预取的版本：与上次记录的版本相比新增的版本；
+// 第一次见到且没有缓存的扩展只预取最新的正式版本，不回溯历史版本
+fn new_releases(metadata: &PackageMetadata, known: Option<&HashSet<Release>>) -> Vec<Release> {
+    match known {
+        Some(known) => {
+            let mut new = releases(metadata)
+                .into_iter()
+                .filter(|release| !known.contains(release))
+                .collect::<Vec<Release>>();
+            new.sort_by(|a, b| a.version.cmp(&b.version));
+            new
+        }
+        None => metadata
+            .versions
+            .iter()
+            .filter_map(|version| {
+                let key = version_key(version.version_normalized.as_deref()?)?;
+                let release = Release::from_version(version)?;
+                (!is_prerelease(&key)).then_some((key, release))
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, release)| release)
+            .into_iter()
+            .collect(),
+    }
+}
+
+// 策略1 下走一遍 dist 分发流程，把 dist 上传到自有存储
+pub async fn prefetch(config: Config, package: &str, release: &Release) -> bool {
+    if config.strategy != PackagistStrategy::StorageSelf {
+        return false;
+    }
+    let (vendor, name) = match package.split_once('/') {
+        Some(names) => names,
+        None => return false,
+    };
+
+    let path = (
+        vendor.to_string(),
+        name.to_string(),
+        release.version.clone(),
+        format!("{}.{}", release.reference, release.dist_type),
+    );
+    let task = tokio::spawn(async move { dist_dispatcher(Path(path), Extension(config)).await });
+    match task.await {
+        Ok(response) if response.status().is_redirection() => true,
+        _ => {
+            eprintln!("failed to prefetch {} {}", package, release.version);
+            false
+        }
+    }
+}
+
+// 定时检查白名单扩展是否有新版本，有则刷新元数据缓存并预先拉取 dist 到自有存储
+pub struct ReleaseWatcher {
+    config: Config,
+    packagist: Packagist,
+    interval: Duration,
+    changes_url: Option<String>,
+    since: Option<u64>,
+    known: HashMap<String, HashSet<Release>>,
+}
+
+impl ReleaseWatcher {
+    pub fn from_env(config: Config) -> Option<Self> {
+        let interval = env::var("WATCH_INTERVAL_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|secs| *secs > 0)?;
+
+        Some(Self {
+            packagist: Packagist::new(&config),
+            config,
+            interval: Duration::from_secs(interval),
+            changes_url: env::var("WATCH_CHANGES_URL").ok().filter(|s| !s.is_empty()),
+            since: None,
+            known: HashMap::new(),
+        })
+    }
+
+    // 白名单中不含通配符的条目、WATCH_PACKAGES 以及已缓存的白名单扩展
+    async fn watched_packages(&self) -> Vec<String> {
+        let mut packages = self
+            .config
+            .package_white_list
+            .iter()
+            .filter(|pattern| !pattern.contains(['*', '?', '[']))
+            .cloned()
+            .collect::<Vec<String>>();
+        packages.extend(
+            env::var("WATCH_PACKAGES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+        );
+        packages.extend(
+            MetadataCache::global()
+                .cached_packages()
+                .await
+                .into_iter()
+                .filter(|package| {
+                    check_package_in_white_list(package, &self.config.package_white_list)
+                }),
+        );
+        packages.sort();
+        packages.dedup();
+        packages
+    }
+
+    // 通过 packagist 的 metadata-changes-url 获取自上次检查以来有变化的白名单扩展
+    async fn changed_packages(&mut self, changes_url: &str) -> Option<Vec<String>> {
+        let url = match self.since {
+            Some(since) => format!("{}?since={}", changes_url, since),
+            None => changes_url.to_string(),
+        };
+        let changes = request_helper::try_get(&url)
+            .await
+            .ok()?
+            .json::<Value>()
+            .await
+            .ok()?;
+        let first_check = self.since.is_none();
+        self.since = changes["timestamp"].as_u64().or(self.since);
+        if first_check {
+            return Some(Vec::new());
+        }
+
+        let mut packages = changes["actions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|action| action["package"].as_str())
+            .map(|package| package.trim_end_matches("~dev").to_string())
+            .filter(|package| check_package_in_white_list(package, &self.config.package_white_list))
+            .collect::<Vec<String>>();
+        packages.sort();
+        packages.dedup();
+        Some(packages)
+    }
+
+    async fn check_package(&mut self, package: &str) {
+        let cache = MetadataCache::global();
+        // 第一次检查时以已缓存的元数据作为上次记录的版本
+        if !self.known.contains_key(package) {
+            if let Some(body) = cache.get(package).await {
+                if let Ok(metadata) = cache.index(package, &body) {
+                    self.known.insert(package.to_string(), releases(&metadata));
+                }
+            }
+        }
+
+        let url = self.packagist.get_package_meta_url(package);
+        let metadata = match cache.refresh(package, &url).await {
+            Ok(body) => match cache.index(package, &body) {
+                Ok(metadata) => metadata,
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
+        let latest = releases(&metadata);
+        let known = self.known.insert(package.to_string(), latest.clone());
+        if known.as_ref() != Some(&latest) {
+            NegativeCache::global().purge(&Pattern::new(&Pattern::escape(package)).unwrap());
+        }
+        for release in new_releases(&metadata, known.as_ref()) {
+            println!("new release found: {} {}", package, release.version);
+            prefetch(self.config.clone(), package, &release).await;
+        }
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            let packages = match self.changes_url.clone() {
+                Some(changes_url) => self.changed_packages(&changes_url).await.unwrap_or_default(),
+                None => self.watched_packages().await,
+            };
+            for package in packages {
+                self.check_package(&package).await;
+            }
+            sleep(self.interval).await;
+        }
+    }
+}
+
+pub fn spawn(config: Config) {
+    if let Some(watcher) = ReleaseWatcher::from_env(config) {
+        tokio::spawn(watcher.run());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(versions: &[(&str, &str, &str)]) -> PackageMetadata {
+        let versions = versions
+            .iter()
+            .map(|(version, normalized, reference)| {
+                serde_json::json!({
+                    "name": "acme/lib",
+                    "version": version,
+                    "version_normalized": normalized,
+                    "dist": { "type": "zip", "url": "https://example.com/acme/lib.zip", "reference": reference },
+                })
+            })
+            .collect::<Vec<Value>>();
+        let body = serde_json::json!({ "packages": { "acme/lib": versions } }).to_string();
+        PackageMetadata::parse("acme/lib", &body).unwrap()
+    }
+
+    fn versions(releases: Vec<Release>) -> Vec<String> {
+        releases.into_iter().map(|release| release.version).collect()
+    }
+
+    #[test]
+    fn new_releases_test() {
+        let before = metadata(&[("v1.1.0", "1.1.0.0", "b"), ("v1.0.0", "1.0.0.0", "a")]);
+        let known = releases(&before);
+        assert!(new_releases(&before, Some(&known)).is_empty());
+
+        let after = metadata(&[
+            ("dev-main", "dev-main", "d"),
+            ("v1.2.0", "1.2.0.0", "c"),
+            ("v1.1.0", "1.1.0.0", "b"),
+            ("v1.0.0", "1.0.0.0", "a"),
+        ]);
+        assert_eq!(
+            vec!["dev-main", "v1.2.0"],
+            versions(new_releases(&after, Some(&known)))
+        );
+
+        // 第一次见到的扩展只预取最新的正式版本
+        let first = metadata(&[
+            ("dev-main", "dev-main", "d"),
+            ("v2.0.0-RC1", "2.0.0.0-RC1", "e"),
+            ("v1.0.0", "1.0.0.0", "a"),
+            ("v1.2.0", "1.2.0.0", "c"),
+        ]);
+        assert_eq!(vec!["v1.2.0"], versions(new_releases(&first, None)));
+    }
+}
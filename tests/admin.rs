@@ -0,0 +1,218 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use composer_mirror::repositories::Repository;
+use composer_mirror::{MirrorServer, PackagistStrategy};
+use serde_json::{json, Value};
+use std::env;
+
+use support::{FakeStorage, FakeUpstream};
+
+#[tokio::test]
+async fn admin_api_test() {
+    env::set_var("ADMIN_TOKEN", "secret");
+    let packagist = FakeUpstream::start();
+    let origin = FakeUpstream::start();
+    let bucket = FakeUpstream::start();
+    packagist.serve(
+        "/p2/acme/lib.json",
+        StatusCode::OK,
+        support::p2(
+            "acme/lib",
+            &format!(
+                r#"[{{"version":"v1.0.0","dist":{{"type":"zip","url":"{}","reference":"abc"}}}}]"#,
+                origin.url("/zipball/abc")
+            ),
+        ),
+    );
+    origin.serve("/zipball/abc", StatusCode::OK, "zip content");
+    packagist.serve(
+        "/p2/acme/lib~dev.json",
+        StatusCode::OK,
+        support::p2("acme/lib", r#"[{"version":"dev-main"}]"#),
+    );
+    origin.serve(
+        "/p2/corp/tool.json",
+        StatusCode::OK,
+        support::p2("corp/tool", r#"[{"version":"v1.0.0"}]"#),
+    );
+
+    let mut config = support::config(&packagist, &["acme/*"]);
+    config.strategy = PackagistStrategy::StorageSelf;
+    config.repositories = vec![Repository::new(
+        "corp",
+        &origin.url("/p2/%package%.json"),
+        &["corp/*"],
+    )];
+    let server = MirrorServer::builder()
+        .config(config)
+        .storage(FakeStorage {
+            upstream: bucket.clone(),
+        })
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+    let client = support::client();
+    let admin = |method: Method, path: &str| {
+        client
+            .request(method, format!("{}/admin{}", mirror, path))
+            .bearer_auth("secret")
+    };
+
+    // 没有令牌或令牌错误
+    let response = client
+        .get(format!("{}/admin/packages", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    let response = client
+        .post(format!("{}/admin/purge", mirror))
+        .bearer_auth("wrong")
+        .json(&json!({ "pattern": "*" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let response = admin(Method::POST, "/packages/acme/lib/refresh")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let detail = response.json::<Value>().await.unwrap();
+    assert!(detail["metadata"]["bytes"].as_u64().unwrap() > 0);
+
+    // 上游仓库的扩展从该仓库刷新，不经过 packagist
+    let response = admin(Method::POST, "/packages/corp/tool/refresh")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(1, origin.hits(Method::GET, "/p2/corp/tool.json"));
+    assert_eq!(0, packagist.hits(Method::GET, "/p2/corp/tool.json"));
+    // 上游仓库的元数据单独缓存，管理接口同样可以查看和清除
+    let detail = response.json::<Value>().await.unwrap();
+    assert!(detail["metadata"]["bytes"].as_u64().unwrap() > 0);
+    let purged = admin(Method::DELETE, "/packages/corp/tool")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(json!(["corp/tool"]), purged["metadata"]);
+
+    let response = client
+        .get(format!("{}/p2/acme/lib~dev.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = admin(Method::POST, "/packages/acme/lib/dists/v1.0.0/abc.zip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let uploaded = response.json::<Value>().await.unwrap();
+    assert_eq!("acme/lib/v1.0.0/abc.zip", uploaded["object_name"]);
+    assert_eq!(
+        Some(b"zip content".to_vec()),
+        bucket.body("/bucket/acme/lib/v1.0.0/abc.zip")
+    );
+
+    let packages = admin(Method::GET, "/packages")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!("acme/lib", packages["packages"][0]["package"]);
+    assert_eq!("acme/lib~dev", packages["packages"][1]["package"]);
+
+    // 自有存储中已上传的 dist
+    let detail = admin(Method::GET, "/packages/acme/lib")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        "acme/lib/v1.0.0/abc.zip",
+        detail["stored_dists"][0]["object_name"]
+    );
+    assert_eq!(
+        bucket.url("/bucket/acme/lib/v1.0.0/abc.zip"),
+        detail["stored_dists"][0]["url"]
+    );
+
+    // 刷新失败时保留已缓存的元数据
+    packagist.serve("/p2/acme/lib.json", StatusCode::INTERNAL_SERVER_ERROR, "");
+    let response = admin(Method::POST, "/packages/acme/lib/refresh")
+        .send()
+        .await
+        .unwrap();
+    assert!(!response.status().is_success());
+    let detail = admin(Method::GET, "/packages/acme/lib")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert!(detail["metadata"]["bytes"].as_u64().unwrap() > 0);
+
+    // 不合法的扩展名不能访问缓存目录之外的文件
+    for path in [
+        "/packages/..%2F..%2Fetc/lib",
+        "/packages/.hidden/lib",
+        "/packages/acme/..%2F..%2Fx/refresh",
+    ] {
+        let method = match path.ends_with("/refresh") {
+            true => Method::POST,
+            false => Method::GET,
+        };
+        let response = admin(method, path).send().await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status(), "{}", path);
+    }
+
+    let purged = admin(Method::DELETE, "/packages/acme/lib")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    // ~dev 元数据一起清除
+    assert_eq!(json!(["acme/lib", "acme/lib~dev"]), purged["metadata"]);
+    let detail = admin(Method::GET, "/packages/acme/lib")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert!(detail["metadata"].is_null());
+
+    let response = admin(Method::POST, "/purge")
+        .json(&json!({ "pattern": "acme/*" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let response = admin(Method::POST, "/purge")
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+
+    let response = admin(Method::POST, "/repositories/build")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+}
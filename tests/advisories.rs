@@ -0,0 +1,144 @@
+mod support;
+
+use axum::http::StatusCode;
+use composer_mirror::advisories::Advisories;
+use composer_mirror::MirrorServer;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::sync::Arc;
+
+use support::FakeUpstream;
+
+fn write_advisory(dir: &std::path::Path, package: &str, file: &str, content: &str) {
+    let dir = dir.join(package);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(file), content).unwrap();
+}
+
+#[tokio::test]
+async fn security_advisories_test() {
+    let packagist = FakeUpstream::start();
+    packagist.serve(
+        "/p2/monolog/monolog.json",
+        StatusCode::OK,
+        support::p2(
+            "monolog/monolog",
+            r#"[{"name":"monolog/monolog","version":"3.4.0","version_normalized":"3.4.0.0","description":"Logging"},{"version":"3.3.0","version_normalized":"3.3.0.0"},{"version":"2.9.0","version_normalized":"2.9.0.0"}]"#,
+        ),
+    );
+    packagist.serve(
+        "/api/security-advisories/",
+        StatusCode::OK,
+        r#"{"advisories":{"monolog/monolog":[{"advisoryId":"PKSA-1234","packageName":"monolog/monolog","remoteId":"GHSA-xxxx","title":"Log injection","link":"https://github.com/advisories/GHSA-xxxx","cve":"CVE-2023-1111","affectedVersions":">=3.0.0,<3.4.0","source":"GitHub","reportedAt":"2023-05-01 00:00:00","composerRepository":"https://packagist.org","severity":"high","sources":[{"name":"GitHub","remoteId":"GHSA-xxxx"}]}]}}"#,
+    );
+
+    let dir = env::temp_dir().join(format!("composer_mirror_advisories_{}", std::process::id()));
+    let checkout = dir.join("security-advisories");
+    write_advisory(
+        &checkout,
+        "monolog/monolog",
+        "CVE-2023-1111.yaml",
+        "title: Log injection\nlink: https://example.com\ncve: CVE-2023-1111\nbranches:\n    3.x:\n        time: 2023-05-01 00:00:00\n        versions: ['>=3.0.0', '<3.4.0']\nreference: composer://monolog/monolog\n",
+    );
+    write_advisory(
+        &checkout,
+        "acme/private",
+        "2023-01.yaml",
+        "title: Private issue\nlink: https://example.com\ncve: ~\nbranches:\n    main:\n        time: 2023-06-01 00:00:00\n        versions: ['<1.0.1']\nreference: composer://acme/private\ncomposer-repository: false\n",
+    );
+    let advisories = Advisories::new(
+        dir.join("advisories.json"),
+        Some(packagist.url("/api/security-advisories/")),
+    )
+    .unwrap();
+    assert!(advisories.is_empty());
+
+    // 本地漏洞库为空时不能移除受影响的版本，启动失败
+    let mut config = support::config(&packagist, &[]);
+    config.advisories = Some(Arc::new(advisories));
+    config.security_block_packages = vec!["monolog/*".to_string()];
+    let err = MirrorServer::builder()
+        .config(config)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.contains("SECURITY_BLOCK_PACKAGES"));
+
+    let advisories = Advisories::new(
+        dir.join("advisories.json"),
+        Some(packagist.url("/api/security-advisories/")),
+    )
+    .unwrap();
+    assert_eq!(2, advisories.import(&checkout).unwrap());
+
+    // monolog 不在白名单中，需要移除受影响的版本时不再跳转到第三方镜像
+    let mut config = support::config(&packagist, &[]);
+    config.private_packages = vec!["acme/*".to_string()];
+    config.advisories = Some(Arc::new(advisories));
+    config.security_block_packages = vec!["monolog/*".to_string()];
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+    let client = support::client();
+
+    let root = client
+        .get(format!("{}/packages.json", mirror))
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        "/api/security-advisories/",
+        root["security-advisories"]["api-url"]
+    );
+
+    let result = client
+        .post(format!("{}/api/security-advisories/", mirror))
+        .form(&[
+            ("packages[]", "monolog/monolog"),
+            ("packages[]", "acme/private"),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    // 与上游相同 CVE 的本地记录不重复返回
+    let monolog = result["advisories"]["monolog/monolog"].as_array().unwrap();
+    assert_eq!(1, monolog.len());
+    assert_eq!("PKSA-1234", monolog[0]["advisoryId"]);
+    assert_eq!(
+        "<1.0.1",
+        result["advisories"]["acme/private"][0]["affectedVersions"]
+    );
+    // 私有扩展不发送到上游
+    let forwarded =
+        String::from_utf8(packagist.request_body("/api/security-advisories/").unwrap()).unwrap();
+    assert!(forwarded.contains("monolog"));
+    assert!(!forwarded.contains("acme"));
+
+    let metadata = client
+        .get(format!("{}/p2/monolog/monolog.json", mirror))
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert!(metadata.get("minified").is_none());
+    let versions = metadata["packages"]["monolog/monolog"].as_array().unwrap();
+    assert_eq!(2, versions.len());
+    assert_eq!("3.4.0", versions[0]["version"]);
+    assert_eq!("2.9.0", versions[1]["version"]);
+    assert_eq!("Logging", versions[1]["description"]);
+
+    // 本地漏洞库损坏时报错，不会当作没有漏洞
+    fs::write(dir.join("advisories.json"), "[{").unwrap();
+    let err = Advisories::new(dir.join("advisories.json"), None)
+        .err()
+        .unwrap();
+    assert!(err.contains("failed to parse"));
+    let _ = fs::remove_dir_all(dir);
+}
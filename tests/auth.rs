@@ -0,0 +1,102 @@
+mod support;
+
+use axum::http::StatusCode;
+use composer_mirror::auth::TokenStore;
+use composer_mirror::MirrorServer;
+use std::env;
+use std::sync::Arc;
+
+use support::FakeUpstream;
+
+#[tokio::test]
+async fn private_package_auth_test() {
+    let packagist = FakeUpstream::start();
+    for package in ["acme/private", "corp/private", "acme/public"] {
+        packagist.serve(
+            &format!("/p2/{}.json", package),
+            StatusCode::OK,
+            support::p2(package, r#"[{"version":"v1.0.0"}]"#),
+        );
+    }
+
+    let path = env::temp_dir().join(format!("composer_mirror_auth_{}.json", std::process::id()));
+    let tokens = TokenStore::new(&path);
+    let secret = tokens.add("ci", vec!["acme/*".to_string()]).unwrap();
+
+    let mut config = support::config(&packagist, &["*/*"]);
+    config.private_packages = vec!["acme/private".to_string(), "corp/*".to_string()];
+    config.tokens = Arc::new(tokens);
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+    let client = support::client();
+    let url = |package: &str| format!("{}/p2/{}.json", mirror, package);
+
+    // 公开扩展不需要令牌
+    let response = client.get(url("acme/public")).send().await.unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = client.get(url("acme/private")).send().await.unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    assert!(response.headers().contains_key("www-authenticate"));
+    let response = client
+        .get(url("acme/private"))
+        .bearer_auth("wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let response = client
+        .get(url("acme/private"))
+        .bearer_auth(&secret)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let response = client
+        .get(url("acme/private"))
+        .basic_auth("ci", Some(&secret))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    // 令牌范围外的私有扩展按不存在处理，dist 同样受保护
+    let response = client
+        .get(url("corp/private"))
+        .bearer_auth(&secret)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let response = client
+        .get(format!("{}/dists/corp/private/v1.0.0/abc.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    // 百分号编码和大小写不同的扩展名同样需要令牌
+    for encoded in [
+        "%61cme/private",
+        "acme%2Fprivate",
+        "ACME/Private",
+        "%41cme/PRIVATE",
+    ] {
+        let response = client.get(url(encoded)).send().await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status(), "{}", encoded);
+    }
+    let response = client
+        .get(format!("{}/dists/%63orp/private/v1.0.0/abc.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    let response = client
+        .get(format!("{}/dists/Corp/Private/v1.0.0/abc.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,82 @@
+mod support;
+
+use axum::{extract::Extension, http::StatusCode, routing::get, Router};
+use composer_mirror::mirrors::{aliyun::Aliyun, tencent::Tencent};
+use composer_mirror::{Config, MirrorServer, PackagistStrategy};
+
+use support::{FakeStorage, FakeUpstream};
+
+#[tokio::test]
+async fn storage_self_requires_storage_test() {
+    let packagist = FakeUpstream::start();
+    let mut config = support::config(&packagist, &["acme/*"]);
+    config.strategy = PackagistStrategy::StorageSelf;
+
+    let err = MirrorServer::builder()
+        .config(config.clone())
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!("PACKAGIST_STRATEGY=1 requires a storage backend", err);
+
+    let server = MirrorServer::builder()
+        .config(config)
+        .storage(FakeStorage {
+            upstream: FakeUpstream::start(),
+        })
+        .build()
+        .unwrap();
+    assert!(server.config().storage.is_some());
+}
+
+#[tokio::test]
+async fn mirrors_replace_config_test() {
+    let packagist = FakeUpstream::start();
+    let server = MirrorServer::builder()
+        .config(support::config(&packagist, &[]))
+        .mirror(Aliyun::with_base_url("http://127.0.0.1:1"))
+        .mirror(Tencent::with_base_url("http://127.0.0.1:2"))
+        .build()
+        .unwrap();
+    let names = server
+        .config()
+        .mirrors
+        .iter()
+        .map(|mirror| mirror.name())
+        .collect::<Vec<&str>>();
+    assert_eq!(vec!["aliyun", "tencent"], names);
+}
+
+#[tokio::test]
+async fn extra_routes_test() {
+    let packagist = FakeUpstream::start();
+    let server = MirrorServer::builder()
+        .config(support::config(&packagist, &["acme/*"]))
+        .route("/healthz", get(|| async { "ok" }))
+        .merge(Router::new().route(
+            "/white-list",
+            get(|Extension(config): Extension<Config>| async move {
+                config.package_white_list.join(",")
+            }),
+        ))
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+    let client = support::client();
+
+    let response = client
+        .get(format!("{}/healthz", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("ok", response.text().await.unwrap());
+
+    // 额外的路由可以取到配置
+    let response = client
+        .get(format!("{}/white-list", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!("acme/*", response.text().await.unwrap());
+}
@@ -0,0 +1,92 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use composer_mirror::downloads::DownloadStats;
+use composer_mirror::MirrorServer;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use support::FakeUpstream;
+
+#[tokio::test]
+async fn notify_batch_test() {
+    env::set_var("ADMIN_TOKEN", "secret");
+    env::set_var("NOTIFY_BATCH_FORWARD_SECS", "1");
+    let packagist = FakeUpstream::start();
+    packagist.serve("/downloads/", StatusCode::OK, r#"{"status":"success"}"#);
+    let path = env::temp_dir().join(format!(
+        "composer_mirror_downloads_test_{}.json",
+        std::process::id()
+    ));
+    let mut config = support::config(&packagist, &[]);
+    config.private_packages = vec!["acme/*".to_string()];
+    config.downloads = Some(Arc::new(DownloadStats::new(
+        &path,
+        Some(packagist.url("/downloads/")),
+    )));
+    let server = MirrorServer::builder().config(config).build().unwrap();
+    server.spawn_watcher();
+    let mirror = support::start(server);
+    let client = support::client();
+
+    let root = client
+        .get(format!("{}/packages.json", mirror))
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!("/downloads/", root["notify-batch"]);
+
+    let response = client
+        .post(format!("{}/downloads/", mirror))
+        .json(&json!({
+            "downloads": [
+                { "name": "monolog/monolog", "version": "3.4.0.0", "downloaded": true },
+                { "name": "monolog/monolog", "version": "3.4.0.0", "downloaded": true },
+                { "name": "acme/private", "version": "1.0.0.0", "downloaded": true },
+            ]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+
+    let stats = client
+        .get(format!("{}/admin/downloads?sort=total", mirror))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!("monolog/monolog", stats["packages"][0]["name"]);
+    assert_eq!(2, stats["packages"][0]["weekly"]);
+    assert_eq!(1, stats["packages"][1]["total"]);
+    assert_eq!("127.0.0.1", stats["clients"][0]["client"]);
+    assert_eq!(3, stats["clients"][0]["total"]);
+
+    let package = client
+        .get(format!("{}/admin/downloads/monolog/monolog", mirror))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(2, package["versions"]["3.4.0.0"]);
+
+    // 私有扩展不转发到上游
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(1, packagist.hits(Method::POST, "/downloads/"));
+    let forwarded =
+        serde_json::from_slice::<Value>(&packagist.request_body("/downloads/").unwrap()).unwrap();
+    assert_eq!(2, forwarded["downloads"].as_array().unwrap().len());
+    assert_eq!("monolog/monolog", forwarded["downloads"][1]["name"]);
+    let _ = std::fs::remove_file(path);
+}
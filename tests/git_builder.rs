@@ -0,0 +1,113 @@
+mod support;
+
+use axum::http::StatusCode;
+use composer_mirror::hosted::HostedPackages;
+use composer_mirror::MirrorServer;
+use sha1::{Digest, Sha1};
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+
+use support::{git, FakeStorage, FakeUpstream};
+
+fn commit(work: &Path, version: &str) {
+    std::fs::write(
+        work.join("composer.json"),
+        format!(
+            r#"{{"name":"acme/gitlib","description":"{}","minimum-stability":"dev"}}"#,
+            version
+        ),
+    )
+    .unwrap();
+    git(work, &["add", "composer.json"]);
+    git(work, &["commit", "-q", "-m", version]);
+}
+
+#[tokio::test]
+async fn build_local_repository_test() {
+    let packagist = FakeUpstream::start();
+    let bucket = FakeUpstream::start();
+    let dir = env::temp_dir().join(format!("composer_mirror_git_{}", std::process::id()));
+    let bare = dir.join("acme/gitlib.git");
+    let work = dir.join("work");
+    std::fs::create_dir_all(&bare).unwrap();
+    std::fs::create_dir_all(&work).unwrap();
+    git(&bare, &["init", "-q", "--bare"]);
+    git(&work, &["init", "-q", "-b", "main"]);
+    commit(&work, "first");
+    git(&work, &["tag", "v1.0.0"]);
+    commit(&work, "second");
+    git(&work, &["tag", "-a", "v1.1.0", "-m", "release"]);
+    git(&work, &["tag", "not-a-version"]);
+    git(
+        &work,
+        &["push", "-q", bare.to_str().unwrap(), "main", "--tags"],
+    );
+
+    let mut config = support::config(&packagist, &[]);
+    config.hosted = Arc::new(HostedPackages::new(dir.join("hosted")));
+    config.git_repositories = vec![bare.to_string_lossy().to_string()];
+    config.git_build_dir = dir.join("repos");
+    let server = MirrorServer::builder()
+        .config(config)
+        .storage(FakeStorage {
+            upstream: bucket.clone(),
+        })
+        .build()
+        .unwrap();
+    server.build_repositories().await;
+    let mirror = support::start(server);
+
+    let response = support::client()
+        .get(format!("{}/p2/acme/gitlib.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let metadata = response.json::<serde_json::Value>().await.unwrap();
+    let versions = metadata["packages"]["acme/gitlib"].as_array().unwrap();
+    let find = |version: &str| {
+        versions
+            .iter()
+            .find(|v| v["version"] == version)
+            .unwrap_or_else(|| panic!("{} not found", version))
+    };
+    assert_eq!(3, versions.len());
+    assert_eq!("first", find("v1.0.0")["description"]);
+    assert_eq!("second", find("v1.1.0")["description"]);
+    assert_eq!("dev-main", find("dev-main")["version_normalized"]);
+    assert!(find("v1.1.0").get("minimum-stability").is_none());
+
+    // 附注标签的 reference 为指向的提交，dist 已上传到存储
+    let v110 = find("v1.1.0");
+    assert_eq!(v110["source"]["reference"], v110["dist"]["reference"]);
+    assert_eq!(
+        v110["dist"]["reference"],
+        find("dev-main")["dist"]["reference"]
+    );
+    let reference = v110["dist"]["reference"].as_str().unwrap();
+    let path = format!("/dists/acme/gitlib/v1.1.0/{}.zip", reference);
+    assert_eq!(path, v110["dist"]["url"]);
+    let zip = bucket
+        .body(&format!("/bucket/acme/gitlib/v1.1.0/{}.zip", reference))
+        .unwrap();
+    assert!(zip.starts_with(b"PK"));
+    assert_eq!(hex::encode(Sha1::digest(&zip)), v110["dist"]["shasum"]);
+    // 打包用的临时文件上传后删除
+    let leftovers = std::fs::read_dir(dir.join("repos"))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "zip"))
+        .count();
+    assert_eq!(0, leftovers);
+
+    let response = support::client()
+        .get(format!("{}{}", mirror, path))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(zip, response.bytes().await.unwrap());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
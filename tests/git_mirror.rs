@@ -0,0 +1,138 @@
+mod support;
+
+use axum::http::StatusCode;
+use composer_mirror::MirrorServer;
+use std::env;
+
+use support::{git, FakeUpstream};
+
+#[tokio::test]
+async fn clone_through_git_mirror_test() {
+    let packagist = FakeUpstream::start();
+    let dir = env::temp_dir().join(format!("composer_mirror_git_mirror_{}", std::process::id()));
+    let origin = dir.join("origin");
+    std::fs::create_dir_all(&origin).unwrap();
+    git(&origin, &["init", "-q", "-b", "main"]);
+    std::fs::write(origin.join("README.md"), "hello").unwrap();
+    git(&origin, &["add", "README.md"]);
+    git(&origin, &["commit", "-q", "-m", "init"]);
+    packagist.serve(
+        "/p2/acme/src.json",
+        StatusCode::OK,
+        support::p2(
+            "acme/src",
+            &format!(
+                r#"[{{"version":"dev-main","source":{{"type":"git","url":"{}","reference":"abc"}}}}]"#,
+                origin.display()
+            ),
+        ),
+    );
+
+    let mut config = support::config(&packagist, &["acme/*"]);
+    config.git_mirror_url = Some("https://mirror.example.com".to_string());
+    config.git_mirror_dir = dir.join("mirrors");
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+
+    let metadata = support::client()
+        .get(format!("{}/p2/acme/src.json", mirror))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        "https://mirror.example.com/git/acme/src.git",
+        metadata["packages"]["acme/src"][0]["source"]["url"]
+    );
+
+    let checkout = dir.join("checkout");
+    let status = tokio::process::Command::new("git")
+        .args(["clone", "-q", &format!("{}/git/acme/src.git", mirror)])
+        .arg(&checkout)
+        .status()
+        .await
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        "hello",
+        std::fs::read_to_string(checkout.join("README.md")).unwrap()
+    );
+
+    // 只提供只读服务，非白名单扩展不提供镜像
+    let response = support::client()
+        .post(format!("{}/git/acme/src.git/git-receive-pack", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+    let response = support::client()
+        .get(format!(
+            "{}/git/other/src.git/info/refs?service=git-upload-pack",
+            mirror
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn upload_pack_without_info_refs_test() {
+    let packagist = FakeUpstream::start();
+    let dir = env::temp_dir().join(format!(
+        "composer_mirror_git_upload_pack_{}",
+        std::process::id()
+    ));
+    let origin = dir.join("origin");
+    std::fs::create_dir_all(&origin).unwrap();
+    git(&origin, &["init", "-q", "-b", "main"]);
+    std::fs::write(origin.join("README.md"), "hello").unwrap();
+    git(&origin, &["add", "README.md"]);
+    git(&origin, &["commit", "-q", "-m", "init"]);
+    let head = std::process::Command::new("git")
+        .arg("-C")
+        .arg(&origin)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .unwrap();
+    let head = String::from_utf8(head.stdout).unwrap().trim().to_string();
+    packagist.serve(
+        "/p2/acme/pack.json",
+        StatusCode::OK,
+        support::p2(
+            "acme/pack",
+            &format!(
+                r#"[{{"version":"dev-main","source":{{"type":"git","url":"{}","reference":"abc"}}}}]"#,
+                origin.display()
+            ),
+        ),
+    );
+
+    let mut config = support::config(&packagist, &["acme/*"]);
+    config.git_mirror_url = Some("https://mirror.example.com".to_string());
+    config.git_mirror_dir = dir.join("mirrors");
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+
+    // 镜像还不存在时直接 POST 也会先克隆；have 列表超过默认的 2MB 请求体限制
+    let mut body = format!("0032want {}\n0000", head);
+    for i in 0..50000 {
+        body.push_str(&format!("0032have {:040x}\n", i));
+    }
+    body.push_str("0009done\n");
+    assert!(body.len() > 2 * 1024 * 1024);
+    let response = support::client()
+        .post(format!("{}/git/acme/pack.git/git-upload-pack", mirror))
+        .header("content-type", "application/x-git-upload-pack-request")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let pack = response.bytes().await.unwrap();
+    assert!(pack.windows(4).any(|window| window == b"PACK"));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
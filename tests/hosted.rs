@@ -0,0 +1,137 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use composer_mirror::auth::TokenStore;
+use composer_mirror::hosted::HostedPackages;
+use composer_mirror::MirrorServer;
+use serde_json::json;
+use std::env;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use zip::write::FileOptions;
+
+use support::{FakeStorage, FakeUpstream};
+
+fn archive(composer: &str) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("composer.json", FileOptions::default())
+        .unwrap();
+    zip.write_all(composer.as_bytes()).unwrap();
+    zip.start_file("src/Lib.php", FileOptions::default())
+        .unwrap();
+    zip.write_all(b"<?php").unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+#[tokio::test]
+async fn upload_private_package_test() {
+    let packagist = FakeUpstream::start();
+    let bucket = FakeUpstream::start();
+    let dir = env::temp_dir().join(format!("composer_mirror_hosted_{}", std::process::id()));
+    let tokens = TokenStore::new(dir.join("tokens.json"));
+    let secret = tokens.add("ci", vec!["acme/*".to_string()]).unwrap();
+    let other = tokens.add("other", vec!["corp/*".to_string()]).unwrap();
+
+    let mut config = support::config(&packagist, &[]);
+    config.private_packages = vec!["acme/*".to_string(), "corp/*".to_string()];
+    config.tokens = Arc::new(tokens);
+    config.hosted = Arc::new(HostedPackages::new(dir.join("hosted")));
+    let server = MirrorServer::builder()
+        .config(config)
+        .storage(FakeStorage {
+            upstream: bucket.clone(),
+        })
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+    let client = support::client();
+    let zip = archive(
+        r#"{"name":"acme/private","require":{"php":">=8.0"},"config":{"sort-packages":true}}"#,
+    );
+    let upload = format!("{}/upload?version=v1.2.0", mirror);
+
+    let response = client.post(&upload).body(zip.clone()).send().await.unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    let response = client
+        .post(&upload)
+        .bearer_auth(&other)
+        .body(zip.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+    let response = client
+        .post(&upload)
+        .bearer_auth(&secret)
+        .body(b"not a zip".to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+
+    let response = client
+        .post(&upload)
+        .bearer_auth(&secret)
+        .body(zip.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::CREATED, response.status());
+    let entry = response.json::<serde_json::Value>().await.unwrap();
+    let shasum = entry["dist"]["shasum"].as_str().unwrap().to_string();
+    let object = format!("/bucket/acme/private/v1.2.0/{}.zip", shasum);
+    assert_eq!(Some(zip), bucket.body(&object));
+
+    // 元数据由本服务生成，不经过 packagist
+    let response = client
+        .get(format!("{}/p2/acme/private.json", mirror))
+        .bearer_auth(&secret)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let metadata = response.json::<serde_json::Value>().await.unwrap();
+    let version = &metadata["packages"]["acme/private"][0];
+    assert_eq!("v1.2.0", version["version"]);
+    assert_eq!("1.2.0.0", version["version_normalized"]);
+    assert_eq!(">=8.0", version["require"]["php"]);
+    assert!(version.get("config").is_none());
+    // dist 地址指向本服务，不暴露自有存储的地址
+    let dist = format!("/dists/acme/private/v1.2.0/{}.zip", shasum);
+    assert_eq!(json!(dist), version["dist"]["url"]);
+
+    let response = client
+        .get(format!("{}{}", mirror, dist))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    let response = client
+        .get(format!("{}{}", mirror, dist))
+        .bearer_auth(&other)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    // 存储不支持签名时由本服务转发对象内容
+    let response = client
+        .get(format!("{}{}", mirror, dist))
+        .bearer_auth(&secret)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(bucket.body(&object).unwrap(), response.bytes().await.unwrap());
+
+    let response = client
+        .get(format!("{}/dists/acme/private/v9.9.9/unknown.zip", mirror))
+        .bearer_auth(&secret)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert_eq!(0, packagist.hits(Method::GET, "/p2/acme/private.json"));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,53 @@
+mod support;
+
+use axum::http::StatusCode;
+use composer_mirror::MirrorServer;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+use support::FakeUpstream;
+
+#[tokio::test]
+async fn journal_bytes_test() {
+    // 请求日志是全局的，本文件只放这一个测试
+    let path = env::temp_dir().join(format!(
+        "composer_mirror_journal_{}.jsonl",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    env::set_var("JOURNAL_PATH", &path);
+    let packagist = FakeUpstream::start();
+    let metadata = support::p2("acme/lib", r#"[{"version":"v1.0.0"}]"#);
+    packagist.serve("/p2/acme/lib.json", StatusCode::OK, metadata.clone());
+
+    let config = support::config(&packagist, &["acme/*"]);
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+
+    let response = support::client()
+        .get(format!("{}/p2/acme/lib.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let length = response.content_length().unwrap();
+    assert_eq!(length, response.bytes().await.unwrap().len() as u64);
+
+    // 日志在响应体发送完后由单独的线程写入
+    let mut record = None;
+    for _ in 0..50 {
+        if let Some(line) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| s.lines().last().map(String::from))
+        {
+            record = serde_json::from_str::<Value>(&line).ok();
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let record = record.unwrap();
+    assert_eq!("/p2/acme/lib.json", record["route"]);
+    assert_eq!(length, record["bytes"].as_u64().unwrap());
+    fs::remove_file(&path).unwrap();
+}
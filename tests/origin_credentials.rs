@@ -0,0 +1,18 @@
+use composer_mirror::{Config, MirrorServer};
+use std::env;
+
+#[test]
+fn invalid_origin_credentials_test() {
+    // 认证信息是全局的，本文件只放这一个测试
+    env::set_var("ORIGIN_CREDENTIALS", "api.github.com=github-oauth");
+    let result = MirrorServer::builder()
+        .config(Config::new(r#"{"packages":[]}"#.to_string(), Vec::new()))
+        .build();
+    assert!(result.err().unwrap().contains("api.github.com=***"));
+
+    env::set_var("ORIGIN_CREDENTIALS", "api.github.com=github-oauth:ghp_xxx");
+    assert!(MirrorServer::builder()
+        .config(Config::new(r#"{"packages":[]}"#.to_string(), Vec::new()))
+        .build()
+        .is_ok());
+}
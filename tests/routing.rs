@@ -0,0 +1,489 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use composer_mirror::mirrors::{aliyun::Aliyun, tencent::Tencent};
+use composer_mirror::repositories::{DistMode, Repository};
+use composer_mirror::{MirrorServer, PackagistStrategy, PublicUrl};
+use std::time::Duration;
+
+use support::{FakeStorage, FakeUpstream};
+
+fn location(response: &reqwest::Response) -> &str {
+    response.headers()["location"].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn whitelist_metadata_test() {
+    let packagist = FakeUpstream::start();
+    let body = support::p2("acme/meta", r#"[{"version":"v1.0.0"}]"#);
+    packagist.serve("/p2/acme/meta.json", StatusCode::OK, body.clone());
+
+    let config = support::config(&packagist, &["acme/*"]);
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+    let client = support::client();
+
+    for _ in 0..2 {
+        let response = client
+            .get(format!("{}/p2/acme/meta.json", mirror))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(body, response.text().await.unwrap());
+    }
+    // 第二次命中元数据缓存
+    assert_eq!(1, packagist.hits(Method::GET, "/p2/acme/meta.json"));
+
+    let response = client
+        .get(format!("{}/p2/acme/missing.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let error = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(
+        packagist.url("/p2/acme/missing.json"),
+        error["consulted"][0]
+    );
+}
+
+#[tokio::test]
+async fn mirror_fallback_order_test() {
+    let packagist = FakeUpstream::start();
+    let tencent = FakeUpstream::start();
+    let aliyun = FakeUpstream::start();
+    aliyun.serve("/dists/other/only-aliyun/abc123.zip", StatusCode::OK, "zip");
+    tencent.serve(
+        "/other/both/v1.0.0/other-both-v1.0.0.zip",
+        StatusCode::OK,
+        "zip",
+    );
+    aliyun.serve("/dists/other/both/def456.zip", StatusCode::OK, "zip");
+
+    let server = MirrorServer::builder()
+        .config(support::config(&packagist, &["acme/*"]))
+        .mirror(Tencent::with_base_url(&tencent.base_url()))
+        .mirror(Aliyun::with_base_url(&aliyun.base_url()))
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+    let client = support::client();
+
+    // 腾讯云没有时退到阿里云
+    let response = client
+        .get(format!(
+            "{}/dists/other/only-aliyun/v1.0.0/abc123.zip",
+            mirror
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::TEMPORARY_REDIRECT, response.status());
+    assert_eq!(
+        aliyun.url("/dists/other/only-aliyun/abc123.zip"),
+        location(&response)
+    );
+
+    // 两边都有时优先腾讯云
+    let response = client
+        .get(format!("{}/dists/other/both/v1.0.0/def456.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        tencent.url("/other/both/v1.0.0/other-both-v1.0.0.zip"),
+        location(&response)
+    );
+    assert_eq!(0, aliyun.hits(Method::HEAD, "/dists/other/both/def456.zip"));
+
+    // 非白名单扩展的元数据交给第一个镜像
+    let response = client
+        .get(format!("{}/p2/other/both.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(tencent.url("/p/other/both.json"), location(&response));
+}
+
+#[tokio::test]
+async fn speed_test_selection_test() {
+    let packagist = FakeUpstream::start();
+    let fast = FakeUpstream::start();
+    let slow = FakeUpstream::start();
+    let mirrors = FakeUpstream::start();
+    packagist.serve(
+        "/p2/acme/race.json",
+        StatusCode::OK,
+        support::p2(
+            "acme/race",
+            r#"[{"version":"v2.0.0","source":{"type":"git","url":"https://github.com/acme/race.git","reference":"fedcba"}},{"version":"v1.0.0","source":{"type":"git","url":"https://github.com/acme/race.git","reference":"abcdef"}}]"#,
+        ),
+    );
+    let tag_path = "/https://github.com/acme/race/archive/refs/tags/v2.0.0.zip";
+    fast.serve(tag_path, StatusCode::OK, "zip");
+    slow.serve_slowly(tag_path, StatusCode::OK, "zip", Duration::from_millis(500));
+
+    let mut config = support::config(&packagist, &["acme/*"]);
+    config.strategy = PackagistStrategy::CacheThirdSite;
+    config.cache_site_list = vec![slow.base_url(), fast.base_url()];
+    let server = MirrorServer::builder()
+        .config(config)
+        .mirror(Tencent::with_base_url(&mirrors.base_url()))
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+
+    let response = support::client()
+        .get(format!("{}/dists/acme/race/v2.0.0/fedcba.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::TEMPORARY_REDIRECT, response.status());
+    assert_eq!(fast.url(tag_path), location(&response));
+
+    // 没有任何加速地址可用时返回 404
+    let response = support::client()
+        .get(format!("{}/dists/acme/race/v1.0.0/abcdef.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+}
+
+#[tokio::test]
+async fn self_storage_upload_test() {
+    let packagist = FakeUpstream::start();
+    let origin = FakeUpstream::start();
+    let bucket = FakeUpstream::start();
+    packagist.serve(
+        "/p2/acme/stored.json",
+        StatusCode::OK,
+        support::p2(
+            "acme/stored",
+            &format!(
+                r#"[{{"version":"v3.0.0","dist":{{"type":"zip","url":"{}","reference":"c0ffee"}}}}]"#,
+                origin.url("/zipball/c0ffee")
+            ),
+        ),
+    );
+    origin.serve("/zipball/c0ffee", StatusCode::OK, "zip content");
+
+    let mut config = support::config(&packagist, &["acme/*"]);
+    config.strategy = PackagistStrategy::StorageSelf;
+    let server = MirrorServer::builder()
+        .config(config)
+        .storage(FakeStorage {
+            upstream: bucket.clone(),
+        })
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+    let object = "/bucket/acme/stored/v3.0.0/c0ffee.zip";
+
+    for _ in 0..2 {
+        let response = support::client()
+            .get(format!("{}/dists/acme/stored/v3.0.0/c0ffee.zip", mirror))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::TEMPORARY_REDIRECT, response.status());
+        assert_eq!(bucket.url(object), location(&response));
+    }
+    assert_eq!(Some(b"zip content".to_vec()), bucket.body(object));
+    // 第二次请求时存储中已存在，不再从源站下载
+    assert_eq!(1, origin.hits(Method::GET, "/zipball/c0ffee"));
+
+    let response = support::client()
+        .get(format!("{}/dists/acme/stored/v9.9.9/unknown.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+}
+
+#[tokio::test]
+async fn upstream_repositories_test() {
+    let packagist = FakeUpstream::start();
+    let wpackagist = FakeUpstream::start();
+    let partner = FakeUpstream::start();
+    let bucket = FakeUpstream::start();
+    wpackagist.serve(
+        "/p2/wpackagist-plugin/akismet.json",
+        StatusCode::OK,
+        support::p2(
+            "wpackagist-plugin/akismet",
+            r#"[{"version":"5.3","dist":{"type":"zip","url":"https://downloads.wordpress.org/plugin/akismet.5.3.zip","reference":"a1b2c3"}}]"#,
+        ),
+    );
+    partner.serve(
+        "/p2/partner/sdk.json",
+        StatusCode::OK,
+        support::p2(
+            "partner/sdk",
+            &format!(
+                r#"[{{"version":"2.1.0","dist":{{"type":"zip","url":"{}","reference":"d1e2f3"}}}}]"#,
+                partner.url("/dist/sdk-2.1.0.zip")
+            ),
+        ),
+    );
+    partner.serve("/dist/sdk-2.1.0.zip", StatusCode::OK, "sdk zip");
+
+    let mut partner_repository = Repository::new(
+        "partner",
+        &partner.url("/p2/%package%.json"),
+        &["partner/*"],
+    );
+    partner_repository.dist = DistMode::Storage;
+    partner_repository.auth = Some("http-basic:user:secret".to_string());
+    let mut config = support::config(&packagist, &["*/*"]);
+    config.repositories = vec![
+        Repository::new(
+            "wpackagist",
+            &wpackagist.url("/p2/%package%.json"),
+            &["wpackagist-plugin/*", "wpackagist-theme/*"],
+        ),
+        partner_repository,
+    ];
+    let server = MirrorServer::builder()
+        .config(config)
+        .storage(FakeStorage {
+            upstream: bucket.clone(),
+        })
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+    let client = support::client();
+
+    let response = client
+        .get(format!("{}/p2/wpackagist-plugin/akismet.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert!(response.text().await.unwrap().contains("akismet.5.3.zip"));
+
+    // 默认直接跳转到上游元数据中的 dist 地址
+    let response = client
+        .get(format!(
+            "{}/dists/wpackagist-plugin/akismet/5.3/a1b2c3.zip",
+            mirror
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::TEMPORARY_REDIRECT, response.status());
+    assert_eq!(
+        "https://downloads.wordpress.org/plugin/akismet.5.3.zip",
+        location(&response)
+    );
+
+    // storage 模式下载后上传到自有存储
+    let response = client
+        .get(format!("{}/dists/partner/sdk/2.1.0/d1e2f3.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::TEMPORARY_REDIRECT, response.status());
+    assert_eq!(
+        bucket.url("/bucket/partner/sdk/2.1.0/d1e2f3.zip"),
+        location(&response)
+    );
+    assert_eq!(
+        Some(b"sdk zip".to_vec()),
+        bucket.body("/bucket/partner/sdk/2.1.0/d1e2f3.zip")
+    );
+    // 仓库的认证信息同时用于元数据和 dist
+    let basic = "Basic dXNlcjpzZWNyZXQ=";
+    assert_eq!(
+        Some(basic.to_string()),
+        partner.authorization("/p2/partner/sdk.json")
+    );
+    assert_eq!(
+        Some(basic.to_string()),
+        partner.authorization("/dist/sdk-2.1.0.zip")
+    );
+
+    let response = client
+        .get(format!("{}/p2/partner/missing.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert_eq!(0, packagist.hits(Method::GET, "/p2/partner/missing.json"));
+    assert_eq!(
+        0,
+        packagist.hits(Method::GET, "/p2/wpackagist-plugin/akismet.json")
+    );
+}
+
+#[tokio::test]
+async fn credential_cross_host_redirect_test() {
+    let packagist = FakeUpstream::start();
+    let gitlab = FakeUpstream::start();
+    let cdn = FakeUpstream::start();
+    let bucket = FakeUpstream::start();
+    // 同一主机内的跳转保留认证
+    gitlab.serve_redirect(
+        "/p2/corp/private.json",
+        &gitlab.url("/api/p2/corp/private.json"),
+    );
+    gitlab.serve(
+        "/api/p2/corp/private.json",
+        StatusCode::OK,
+        support::p2(
+            "corp/private",
+            &format!(
+                r#"[{{"version":"1.0.0","dist":{{"type":"zip","url":"{}","reference":"abc123"}}}}]"#,
+                gitlab.url("/archive.zip")
+            ),
+        ),
+    );
+    gitlab.serve_redirect("/archive.zip", &cdn.url("/signed.zip"));
+    cdn.serve("/signed.zip", StatusCode::OK, "private zip");
+
+    let mut repository = Repository::new("gitlab", &gitlab.url("/p2/%package%.json"), &["corp/*"]);
+    repository.dist = DistMode::Storage;
+    repository.auth = Some("gitlab-token:glpat-secret".to_string());
+    let mut config = support::config(&packagist, &["*/*"]);
+    config.repositories = vec![repository];
+    let server = MirrorServer::builder()
+        .config(config)
+        .storage(FakeStorage {
+            upstream: bucket.clone(),
+        })
+        .build()
+        .unwrap();
+    let mirror = support::start(server);
+    let client = support::client();
+
+    let response = client
+        .get(format!("{}/p2/corp/private.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        Some("glpat-secret".to_string()),
+        gitlab.header("/api/p2/corp/private.json", "private-token")
+    );
+
+    let response = client
+        .get(format!("{}/dists/corp/private/1.0.0/abc123.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::TEMPORARY_REDIRECT, response.status());
+    assert_eq!(
+        Some(b"private zip".to_vec()),
+        bucket.body("/bucket/corp/private/1.0.0/abc123.zip")
+    );
+    // 跳到其他主机时不带 PRIVATE-TOKEN
+    assert_eq!(
+        Some("glpat-secret".to_string()),
+        gitlab.header("/archive.zip", "private-token")
+    );
+    assert_eq!(1, cdn.hits(Method::GET, "/signed.zip"));
+    assert_eq!(None, cdn.header("/signed.zip", "private-token"));
+}
+
+#[tokio::test]
+async fn credentialed_repository_redirect_test() {
+    let packagist = FakeUpstream::start();
+    let vendor = FakeUpstream::start();
+    let metadata = support::p2(
+        "vendor/sdk",
+        &format!(
+            r#"[{{"version":"1.0.0","dist":{{"type":"zip","url":"{}","reference":"f00d"}}}}]"#,
+            vendor.url("/dist/sdk.zip")
+        ),
+    );
+    vendor.serve("/p2/vendor/sdk.json", StatusCode::OK, metadata.clone());
+    vendor.serve("/dist/sdk.zip", StatusCode::OK, "sdk zip");
+    // packagist 上的同名扩展不会和上游仓库的元数据共用缓存
+    packagist.serve(
+        "/p2/vendor/sdk.json",
+        StatusCode::OK,
+        support::p2("vendor/sdk", r#"[{"version":"9.9.9"}]"#),
+    );
+
+    let mut repository =
+        Repository::new("vendor", &vendor.url("/p2/%package%.json"), &["vendor/*"]);
+    repository.auth = Some("bearer:vendor-token".to_string());
+    let mut config = support::config(&packagist, &["*/*"]);
+    config.repositories = vec![repository];
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+    let client = support::client();
+
+    let response = client
+        .get(format!("{}/p2/vendor/sdk.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert!(response.text().await.unwrap().contains("f00d"));
+    let cache_dir = std::path::PathBuf::from(std::env::var("METADATA_CACHE_DIR").unwrap());
+    assert!(cache_dir
+        .join("repositories/vendor/vendor/sdk.json")
+        .exists());
+    assert!(!cache_dir.join("p2/vendor/sdk.json").exists());
+
+    // 需要认证的 dist 由镜像下载后转发，不把客户端跳转到源站
+    let response = client
+        .get(format!("{}/dists/vendor/sdk/1.0.0/f00d.zip", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("sdk zip", response.text().await.unwrap());
+    assert_eq!(
+        Some("Bearer vendor-token".to_string()),
+        vendor.authorization("/dist/sdk.zip")
+    );
+}
+
+#[tokio::test]
+async fn packages_json_test() {
+    let packagist = FakeUpstream::start();
+    let mut config = support::config(&packagist, &["acme/*"]);
+    config.packages = r#"{"info":"hello","provider-includes":{}}"#.to_string();
+    config.public_url = PublicUrl::FromHost;
+    config.private_repository = true;
+    config.private_packages = vec!["acme/*".to_string()];
+    config.repositories = vec![Repository::new(
+        "wpackagist",
+        &packagist.url("/wp/%package%.json"),
+        &["wpackagist-plugin/*"],
+    )];
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+
+    let root = support::client()
+        .get(format!("{}/packages.json", mirror))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!("hello", root["info"]);
+    assert!(root.get("provider-includes").is_none());
+    assert_eq!(
+        format!("{}/p2/%package%.json", mirror),
+        root["metadata-url"]
+    );
+    assert_eq!(
+        format!(
+            "{}/dists/%package%/%prettyVersion%/%reference%.%type%",
+            mirror
+        ),
+        root["mirrors"][0]["dist-url"]
+    );
+    assert_eq!(
+        serde_json::json!(["acme/*", "wpackagist-plugin/*"]),
+        root["available-package-patterns"]
+    );
+    assert!(chrono::NaiveDateTime::parse_from_str(
+        root["last-update"].as_str().unwrap(),
+        "%Y-%m-%d %H:%M:%S"
+    )
+    .is_ok());
+}
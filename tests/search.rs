@@ -0,0 +1,94 @@
+mod support;
+
+use axum::http::StatusCode;
+use composer_mirror::auth::TokenStore;
+use composer_mirror::hosted::HostedPackages;
+use composer_mirror::package_index::PackageIndex;
+use composer_mirror::MirrorServer;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Arc;
+
+use support::FakeUpstream;
+
+#[tokio::test]
+async fn list_and_search_test() {
+    let packagist = FakeUpstream::start();
+    packagist.serve(
+        "/packages/list.json",
+        StatusCode::OK,
+        r#"{"packageNames":["acme/lib","acme/tool","monolog/monolog","symfony/monolog-bundle"]}"#,
+    );
+    let dir = env::temp_dir().join(format!("composer_mirror_search_{}", std::process::id()));
+    let tokens = TokenStore::new(dir.join("tokens.json"));
+    let secret = tokens.add("ci", vec!["acme/*".to_string()]).unwrap();
+    let hosted = HostedPackages::new(dir.join("hosted"));
+    hosted
+        .set_versions(
+            "acme/private",
+            vec![json!({ "name": "acme/private", "version": "1.0.0", "description": "Private monolog handler" })],
+        )
+        .await
+        .unwrap();
+    let index = PackageIndex::new(
+        dir.join("package-index.json"),
+        &packagist.url("/packages/list.json"),
+    );
+    assert_eq!(4, index.refresh().await.unwrap());
+
+    let mut config = support::config(&packagist, &[]);
+    config.private_packages = vec!["acme/private".to_string()];
+    config.tokens = Arc::new(tokens);
+    config.hosted = Arc::new(hosted);
+    config.package_index = Some(Arc::new(index));
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+    let client = support::client();
+    let get = |path: &str, token: Option<&str>| {
+        let mut request = client.get(format!("{}{}", mirror, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        async move { request.send().await.unwrap().json::<Value>().await.unwrap() }
+    };
+
+    let root = get("/packages.json", None).await;
+    assert_eq!("/packages/list.json", root["list"]);
+    assert_eq!("/search.json?q=%query%", root["search"]);
+
+    let list = get("/packages/list.json?vendor=acme", None).await;
+    assert_eq!(json!(["acme/lib", "acme/tool"]), list["packageNames"]);
+    let list = get("/packages/list.json?vendor=acme", Some(&secret)).await;
+    assert_eq!(
+        json!(["acme/lib", "acme/private", "acme/tool"]),
+        list["packageNames"]
+    );
+    let list = get("/packages/list.json?filter=*/monolog*", None).await;
+    assert_eq!(
+        json!(["monolog/monolog", "symfony/monolog-bundle"]),
+        list["packageNames"]
+    );
+
+    let search = get("/search.json?q=monolog&per_page=1", Some(&secret)).await;
+    assert_eq!(2, search["total"]);
+    assert_eq!("monolog/monolog", search["results"][0]["name"]);
+    assert_eq!("/search.json?q=monolog&page=2&per_page=1", search["next"]);
+    let search = get("/search.json?q=private", Some(&secret)).await;
+    assert_eq!(
+        "Private monolog handler",
+        search["results"][0]["description"]
+    );
+    assert_eq!("", search["results"][0]["url"]);
+    let search = get("/search.json?q=private", None).await;
+    assert_eq!(0, search["total"]);
+
+    // 页码过大时不会溢出
+    let search = get(
+        &format!("/search.json?q=monolog&page={}&per_page=100", usize::MAX),
+        None,
+    )
+    .await;
+    assert_eq!(2, search["total"]);
+    assert_eq!(json!([]), search["results"]);
+    assert!(search.get("next").is_none());
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,274 @@
+// 集成测试用的假上游：packagist、腾讯云、阿里云、加速地址和自有存储都用它模拟，不需要访问外网
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Extension, Router,
+};
+use composer_mirror::storage::Storage;
+use composer_mirror::{Config, MirrorServer};
+use std::collections::HashMap;
+use std::env;
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+static INIT: Once = Once::new();
+
+// 全局缓存放到临时目录，上游失败时不重试
+pub fn init() {
+    INIT.call_once(|| {
+        let dir = env::temp_dir().join(format!("composer_mirror_test_{}", std::process::id()));
+        env::set_var("METADATA_CACHE_DIR", dir);
+        env::set_var("UPSTREAM_RETRY_TIMES", "0");
+        // 测试客户端直接连接，按反向代理对待
+        env::set_var("TRUSTED_PROXIES", "127.0.0.1");
+    });
+}
+
+#[derive(Clone)]
+struct FakeRoute {
+    status: StatusCode,
+    body: Vec<u8>,
+    delay: Duration,
+    location: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    routes: Mutex<HashMap<String, FakeRoute>>,
+    hits: Mutex<Vec<(Method, String)>>,
+    headers: Mutex<HashMap<String, HeaderMap>>,
+    request_bodies: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+#[derive(Clone)]
+pub struct FakeUpstream {
+    addr: SocketAddr,
+    state: Arc<State>,
+}
+
+async fn handle(
+    Extension(state): Extension<Arc<State>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path().to_string();
+    state.hits.lock().unwrap().push((method, path.clone()));
+    state.headers.lock().unwrap().insert(path.clone(), headers);
+    if !body.is_empty() {
+        state
+            .request_bodies
+            .lock()
+            .unwrap()
+            .insert(path.clone(), body.to_vec());
+    }
+    let route = state.routes.lock().unwrap().get(&path).cloned();
+    match route {
+        Some(route) => {
+            tokio::time::sleep(route.delay).await;
+            let mut response = (route.status, Bytes::from(route.body)).into_response();
+            if let Some(location) = route.location {
+                response
+                    .headers_mut()
+                    .insert("location", location.parse().unwrap());
+            }
+            response
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+impl FakeUpstream {
+    // 未配置的路径一律返回 404
+    pub fn start() -> Self {
+        init();
+        let state = Arc::new(State::default());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .fallback(handle)
+            .layer(Extension(state.clone()));
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        Self { addr, state }
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url(), path)
+    }
+
+    pub fn serve(&self, path: &str, status: StatusCode, body: impl Into<Vec<u8>>) {
+        self.serve_slowly(path, status, body, Duration::ZERO);
+    }
+
+    pub fn serve_slowly(
+        &self,
+        path: &str,
+        status: StatusCode,
+        body: impl Into<Vec<u8>>,
+        delay: Duration,
+    ) {
+        self.state.routes.lock().unwrap().insert(
+            path.to_string(),
+            FakeRoute {
+                status,
+                body: body.into(),
+                delay,
+                location: None,
+            },
+        );
+    }
+
+    pub fn serve_redirect(&self, path: &str, location: &str) {
+        self.state.routes.lock().unwrap().insert(
+            path.to_string(),
+            FakeRoute {
+                status: StatusCode::FOUND,
+                body: Vec::new(),
+                delay: Duration::ZERO,
+                location: Some(location.to_string()),
+            },
+        );
+    }
+
+    pub fn body(&self, path: &str) -> Option<Vec<u8>> {
+        self.state
+            .routes
+            .lock()
+            .unwrap()
+            .get(path)
+            .map(|route| route.body.clone())
+    }
+
+    // 最近一次请求该路径时带的 Authorization 头
+    pub fn authorization(&self, path: &str) -> Option<String> {
+        self.header(path, "authorization")
+    }
+
+    // 最近一次请求该路径时带的请求头
+    pub fn header(&self, path: &str, name: &str) -> Option<String> {
+        self.state
+            .headers
+            .lock()
+            .unwrap()
+            .get(path)
+            .and_then(|headers| headers.get(name))
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    }
+
+    // 最近一次请求该路径时的请求体
+    pub fn request_body(&self, path: &str) -> Option<Vec<u8>> {
+        self.state.request_bodies.lock().unwrap().get(path).cloned()
+    }
+
+    // 已配置的以 prefix 开头的路径
+    pub fn paths(&self, prefix: &str) -> Vec<String> {
+        let mut paths = self
+            .state
+            .routes
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|path| path.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<String>>();
+        paths.sort();
+        paths
+    }
+
+    pub fn hits(&self, method: Method, path: &str) -> usize {
+        self.state
+            .hits
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, p)| *m == method && p == path)
+            .count()
+    }
+}
+
+// 上传的对象直接挂到假上游的 /bucket/ 下
+pub struct FakeStorage {
+    pub upstream: FakeUpstream,
+}
+
+#[async_trait]
+impl Storage for FakeStorage {
+    fn url(&self, object_name: &str) -> String {
+        self.upstream.url(&format!("/bucket/{}", object_name))
+    }
+
+    async fn put(&self, object_name: &str, body: Vec<u8>) -> Result<(), String> {
+        self.upstream
+            .serve(&format!("/bucket/{}", object_name), StatusCode::OK, body);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        Ok(self
+            .upstream
+            .paths(&format!("/bucket/{}", prefix))
+            .into_iter()
+            .map(|path| path.trim_start_matches("/bucket/").to_string())
+            .collect())
+    }
+}
+
+pub fn config(packagist: &FakeUpstream, white_list: &[&str]) -> Config {
+    let mut config = Config::new(
+        r#"{"packages":[]}"#.to_string(),
+        white_list.iter().map(|s| s.to_string()).collect(),
+    );
+    config.packages_meta_url_template = packagist.url("/p2/%package%.json");
+    config
+}
+
+// 在随机端口启动镜像服务，返回服务地址
+pub fn start(server: MirrorServer) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.serve(listener));
+    format!("http://{}", addr)
+}
+
+// 不跟随跳转，便于检查 Location
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+pub fn p2(package: &str, versions: &str) -> String {
+    format!(
+        r#"{{"packages":{{"{}":{}}},"minified":"composer/2.0"}}"#,
+        package, versions
+    )
+}
+
+pub fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {:?} failed", args);
+}
@@ -0,0 +1,18 @@
+use composer_mirror::{Config, MirrorServer};
+use std::env;
+
+#[test]
+fn invalid_proxy_rules_test() {
+    // 代理配置是全局的，本文件只放这一个测试
+    env::set_var("UPSTREAM_PROXY_RULES", "github.com=127.0.0.1:1080");
+    let result = MirrorServer::builder()
+        .config(Config::new(r#"{"packages":[]}"#.to_string(), Vec::new()))
+        .build();
+    assert!(result.err().unwrap().contains("127.0.0.1:1080"));
+
+    env::set_var("UPSTREAM_PROXY_RULES", "github.com=socks5h://127.0.0.1:1080");
+    assert!(MirrorServer::builder()
+        .config(Config::new(r#"{"packages":[]}"#.to_string(), Vec::new()))
+        .build()
+        .is_ok());
+}
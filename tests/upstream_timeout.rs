@@ -0,0 +1,47 @@
+mod support;
+
+use axum::http::{Method, StatusCode};
+use composer_mirror::MirrorServer;
+use std::env;
+use std::time::{Duration, Instant};
+
+use support::FakeUpstream;
+
+#[tokio::test]
+async fn stalled_upstream_opens_breaker_test() {
+    // 熔断器是全局的，本文件只放这一个测试
+    env::set_var("UPSTREAM_TIMEOUT_SECS", "1");
+    env::set_var("CIRCUIT_BREAKER_FAILURE_THRESHOLD", "1");
+    let packagist = FakeUpstream::start();
+    packagist.serve_slowly(
+        "/p2/acme/slow.json",
+        StatusCode::OK,
+        support::p2("acme/slow", r#"[{"version":"v1.0.0"}]"#),
+        Duration::from_secs(5),
+    );
+
+    let config = support::config(&packagist, &["acme/*"]);
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+    let client = support::client();
+
+    // 上游卡住时按超时失败，不会等到上游返回
+    let started = Instant::now();
+    let response = client
+        .get(format!("{}/p2/acme/slow.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert!(!response.status().is_success());
+    assert!(started.elapsed() < Duration::from_secs(4));
+
+    // 超时计入熔断，之后的请求不再发往上游
+    let started = Instant::now();
+    let response = client
+        .get(format!("{}/p2/acme/slow.json", mirror))
+        .send()
+        .await
+        .unwrap();
+    assert!(!response.status().is_success());
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(1, packagist.hits(Method::GET, "/p2/acme/slow.json"));
+}
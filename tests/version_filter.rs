@@ -0,0 +1,87 @@
+mod support;
+
+use axum::http::StatusCode;
+use composer_mirror::version_filter::VersionFilter;
+use composer_mirror::MirrorServer;
+use serde_json::Value;
+
+use support::FakeUpstream;
+
+async fn versions(client: &reqwest::Client, url: String) -> Vec<String> {
+    let metadata = client
+        .get(url)
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    let package = metadata["packages"]
+        .as_object()
+        .unwrap()
+        .values()
+        .next()
+        .unwrap();
+    package
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|version| version["version"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn version_filter_test() {
+    let packagist = FakeUpstream::start();
+    packagist.serve(
+        "/p2/laravel/framework.json",
+        StatusCode::OK,
+        support::p2(
+            "laravel/framework",
+            r#"[{"name":"laravel/framework","version":"v10.1.0","version_normalized":"10.1.0.0","time":"2023-02-20T00:00:00+00:00","require":{"php":"^8.1"}},{"version":"v9.52.0","version_normalized":"9.52.0.0","time":"2023-02-14T00:00:00+00:00","require":{"php":"^8.0.2"}},{"version":"v9.1.0","version_normalized":"9.1.0.0","time":"2022-02-15T00:00:00+00:00"},{"version":"v8.83.0","version_normalized":"8.83.0.0","time":"2022-02-08T00:00:00+00:00","require":{"php":"^7.3|^8.0"}},{"version":"v5.5.0","version_normalized":"5.5.0.0","time":"2017-08-30T00:00:00+00:00","require":{"php":">=7.0"}}]"#,
+        ),
+    );
+    packagist.serve(
+        "/p2/laravel/framework~dev.json",
+        StatusCode::OK,
+        support::p2(
+            "laravel/framework",
+            r#"[{"name":"laravel/framework","version":"11.x-dev","version_normalized":"11.9999999.9999999.9999999-dev","require":{"php":"^8.2"}},{"version":"dev-master","version_normalized":"dev-master"}]"#,
+        ),
+    );
+    packagist.serve(
+        "/p2/monolog/monolog.json",
+        StatusCode::OK,
+        support::p2(
+            "monolog/monolog",
+            r#"[{"name":"monolog/monolog","version":"3.4.0","version_normalized":"3.4.0.0","require":{"php":">=8.1"}}]"#,
+        ),
+    );
+
+    // laravel 不在白名单中，匹配规则后不再跳转到第三方镜像
+    let mut config = support::config(&packagist, &["monolog/*"]);
+    config.version_filters = serde_json::from_str::<Vec<VersionFilter>>(
+        r#"[
+            {"packages": ["*/*"], "drop-dev": true},
+            {"packages": ["laravel/*"], "released-after": "2018-01-01", "exclude": ">=9.0,<9.52", "php": "8.0.30"}
+        ]"#,
+    )
+    .unwrap();
+    let mirror = support::start(MirrorServer::builder().config(config).build().unwrap());
+    let client = support::client();
+
+    assert_eq!(
+        vec!["v9.52.0", "v8.83.0"],
+        versions(&client, format!("{}/p2/laravel/framework.json", mirror)).await
+    );
+    assert!(
+        versions(&client, format!("{}/p2/laravel/framework~dev.json", mirror))
+            .await
+            .is_empty()
+    );
+    // 只匹配 */* 的规则，php 版本不受限制
+    assert_eq!(
+        vec!["3.4.0"],
+        versions(&client, format!("{}/p2/monolog/monolog.json", mirror)).await
+    );
+}